@@ -0,0 +1,177 @@
+//! Benchmarks for acceptance, determinization, and lookup on a small, fixed corpus of automata.
+
+#![allow(clippy::result_large_err)]
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::hint::black_box;
+use vpa::{
+    call, Deterministic, Edge, Lookup as _, Nondeterministic, Range, Return, Run as _, Wildcard,
+};
+
+#[cfg(feature = "persistent")]
+use vpa::PersistentStack;
+
+/// Balanced parentheses, deterministically.
+const PARENS: &str = "initial 0
+state 0 accepting
+on '(' push P -> 0
+on ')' when top P pop -> 0
+";
+
+/// Nesting depths for `Nondeterministic::bounded_depth`.
+const DEPTHS: [usize; 3] = [4, 16, 64];
+
+/// Number of identical automata to union before determinizing.
+const COPIES: [usize; 3] = [2, 8, 32];
+
+/// Alphabet sizes for `Wildcard::get`.
+const ALPHABETS: [u32; 3] = [4, 64, 1024];
+
+/// Balanced input that never nests deeper than 3, repeated to 1024 tokens.
+fn input() -> Vec<char> {
+    "(()(()))".chars().cycle().take(1024).collect()
+}
+
+/// Parentheses nested `depth` deep, then closed.
+fn nested(depth: usize) -> Vec<char> {
+    "(".repeat(depth)
+        .chars()
+        .chain(")".repeat(depth).chars())
+        .collect()
+}
+
+/// Parentheses nested no deeper than `depth`.
+fn bounded(depth: usize) -> Nondeterministic<char, ()> {
+    Nondeterministic::bounded_depth(depth, (), &Range::unit('('), &Range::unit(')'))
+}
+
+fn accept(c: &mut Criterion) {
+    let tokens = input();
+    let d = Deterministic::<char, char>::from_text(PARENS).unwrap();
+    let nd = bounded(16);
+    let nd_as_d = nd.determinize().unwrap();
+    let mut group = c.benchmark_group("accept");
+    let _ = group.bench_function("deterministic", |b| {
+        b.iter(|| d.accept(black_box(&tokens).iter().copied()));
+    });
+    let _ = group.bench_function("deterministic/step_det", |b| {
+        b.iter(|| d.accept_det(black_box(&tokens).iter().copied()));
+    });
+    let _ = group.bench_function("nondeterministic", |b| {
+        b.iter(|| nd.accept(black_box(&tokens).iter().copied()));
+    });
+    let _ = group.bench_function("nondeterministic/determinized", |b| {
+        b.iter(|| nd_as_d.accept_det(black_box(&tokens).iter().copied()));
+    });
+    // Through `Execution`, which checks after every token whether the run is dead
+    let _ = group.bench_function("deterministic/run", |b| {
+        b.iter(|| black_box(&tokens).iter().copied().run(&d).finish());
+    });
+    let _ = group.bench_function("nondeterministic/run", |b| {
+        b.iter(|| black_box(&tokens).iter().copied().run(&nd).finish());
+    });
+    group.finish();
+}
+
+fn determinize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("determinize");
+    for depth in DEPTHS {
+        let nd = bounded(depth);
+        let _ = group.bench_with_input(BenchmarkId::from_parameter(depth), &nd, |b, nd| {
+            b.iter(|| black_box(nd).determinize());
+        });
+    }
+    // Many copies of the same automaton, so nearly every merge is between identical edges
+    for copies in COPIES {
+        let nd = (1..copies).fold(bounded(16), |acc, _| acc | bounded(16));
+        let _ = group.bench_with_input(BenchmarkId::new("duplicated", copies), &nd, |b, nd| {
+            b.iter(|| black_box(nd).determinize());
+        });
+    }
+    group.finish();
+}
+
+fn into_deterministic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("into_deterministic");
+    for (name, nd) in [
+        ("bounded", bounded(256)),
+        (
+            "duplicated",
+            (1..8).fold(bounded(64), |acc, _| acc | bounded(64)),
+        ),
+    ] {
+        let _ = group.bench_with_input(BenchmarkId::new("borrowed", name), &nd, |b, nd| {
+            b.iter(|| black_box(nd).determinize());
+        });
+        // Cloning the input to consume is setup, not part of the measurement
+        let _ = group.bench_with_input(BenchmarkId::new("owned", name), &nd, |b, nd| {
+            b.iter_batched(
+                || nd.clone(),
+                |owned| black_box(owned).into_deterministic(),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn wildcard_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wildcard_get");
+    for size in ALPHABETS {
+        let wild: Wildcard<u32, Return<Edge<u32, (), usize>>> = Wildcard::Specific(
+            (0..size)
+                .map(|i| {
+                    (
+                        Range::unit(i),
+                        Return(Edge::Local {
+                            dst: 0,
+                            call: call!(|x| x),
+                        }),
+                    )
+                })
+                .collect(),
+        );
+        let middle = size / 2;
+        let _ = group.bench_with_input(BenchmarkId::from_parameter(size), &wild, |b, wild| {
+            b.iter(|| black_box(wild).get((black_box(&middle), ())));
+        });
+    }
+    group.finish();
+}
+
+/// Snapshot the stack after every token, which copies a `Vec` but shares a persistent stack.
+fn checkpoint(c: &mut Criterion) {
+    let d = Deterministic::<char, char>::from_text(PARENS).unwrap();
+    let mut group = c.benchmark_group("checkpoint");
+    for depth in DEPTHS {
+        let tokens = nested(depth);
+        let _ = group.bench_with_input(BenchmarkId::new("vec", depth), &tokens, |b, tokens| {
+            b.iter(|| black_box(tokens).iter().copied().run(&d).trace());
+        });
+        #[cfg(feature = "persistent")]
+        let _ = group.bench_with_input(
+            BenchmarkId::new("persistent", depth),
+            &tokens,
+            |b, tokens| {
+                b.iter(|| {
+                    black_box(tokens)
+                        .iter()
+                        .copied()
+                        .run_on_stack(&d, PersistentStack::default())
+                        .trace()
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    accept,
+    determinize,
+    into_deterministic,
+    wildcard_get,
+    checkpoint
+);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+fn main() {
+    #[cfg(not(feature = "quickcheck"))]
+    {
+        println!("`quickcheck` feature not enabled; passing...");
+    }
+    #[cfg(feature = "quickcheck")]
+    {
+        use quickcheck::{Arbitrary, Gen};
+        use std::{
+            collections::BTreeSet,
+            env,
+            io::{stdout, Write},
+            time::Instant,
+        };
+        use vpa::{BitSet, Nondeterministic};
+
+        let mut g = Gen::new(
+            env::var("QUICKCHECK_GENERATOR_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1000),
+        );
+        for _ in 0..env::var("QUICKCHECK_TESTS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10)
+        {
+            print!("Generating...");
+            stdout().flush().expect("Couldn't flush stdout");
+            let mut nd = Nondeterministic::<bool, bool>::arbitrary(&mut g);
+            let _ = nd.deabsurdify();
+            let bits = nd
+                .clone()
+                .reindex(|set: BTreeSet<usize>| set.into_iter().collect::<BitSet>());
+            println!("done ({} states)", nd.states.len());
+            print!("Determinizing with `BTreeSet`...");
+            stdout().flush().expect("Couldn't flush stdout");
+            let mut clock = Instant::now();
+            let tree_result = nd.determinize().ok();
+            println!("done in {:?}", clock.elapsed());
+            print!("Determinizing with `BitSet`...");
+            stdout().flush().expect("Couldn't flush stdout");
+            clock = Instant::now();
+            let bits_result = bits.determinize().ok();
+            println!("done in {:?}", clock.elapsed());
+            assert_eq!(tree_result, bits_result, "Determinized automata differ");
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{collections::BTreeMap, env, time::Instant};
+use vpa::{call, CurryOpt, Deterministic, Edge, Range, Return, State, Wildcard};
+
+/// Star-shaped automaton: state 0 jumps to state `i` on token `i`, and every other state jumps back on anything.
+fn star(size: u32) -> Deterministic<u32, ()> {
+    let mut d = Deterministic::with_capacity(size as usize, 0);
+    d.states.push(State {
+        transitions: CurryOpt {
+            wildcard: Some(Wildcard::Specific(
+                (1..size)
+                    .map(|i| {
+                        (
+                            Range::unit(i),
+                            Return(Edge::Local {
+                                dst: i as usize,
+                                call: call!(|x| x),
+                            }),
+                        )
+                    })
+                    .collect(),
+            )),
+            none: None,
+            some: BTreeMap::new(),
+        },
+        accepting: true,
+    });
+    d.states.extend((1..size).map(|_| State {
+        transitions: CurryOpt {
+            wildcard: Some(Wildcard::Any(Return(Edge::Local {
+                dst: 0,
+                call: call!(|x| x),
+            }))),
+            none: None,
+            some: BTreeMap::new(),
+        },
+        accepting: false,
+    }));
+    d
+}
+
+fn main() {
+    let size = env::var("STATES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(4000);
+    let d = star(size);
+    let nd = d.clone().generalize();
+    let clock = Instant::now();
+    let redone: Deterministic<u32, ()> = nd.determinize().expect("Couldn't determinize");
+    println!("Determinized {size} states in {:?}", clock.elapsed());
+    assert_eq!(redone, d, "Determinization changed the automaton");
+}
@@ -1,4 +1,4 @@
-use crate::{accept, parser, Run};
+use crate::{accept, parser};
 use quickcheck::*;
 
 #[derive(Clone, Copy, Debug)]
@@ -29,12 +29,17 @@ impl From<Paren> for char {
     }
 }
 
-quickcheck! {
-    fn exactly_correct(v: Vec<Paren>) -> bool {
-        let parser = parser();
-        let chars = v.into_iter().map(char::from);
-        let mut run = chars.clone().run(&parser);
-        while run.next().is_some() {}
-        run.ctrl == Err(accept(chars))
+fn exactly_correct(v: Vec<Paren>) {
+    let parser = parser();
+    let chars: Vec<char> = v.into_iter().map(char::from).collect();
+    if accept(chars.iter().copied()) {
+        parser.assert_accepts([chars]);
+    } else {
+        parser.assert_rejects([chars]);
     }
 }
+
+#[test]
+fn exactly_correct_quickcheck() {
+    quickcheck(exactly_correct as fn(Vec<Paren>));
+}
@@ -92,23 +92,17 @@ pub fn main() {
     for _ in 0..5 {
         let s = generate(&mut rng);
         println!("{s}");
-        let mut run = s.chars().run(&parser);
-        println!("    {run:?}");
-        while run.next().is_some() {
-            println!("    {run:?}");
-        }
-        assert_eq!(run.ctrl, Err(true));
+        let verdict = s.chars().run(&parser).finish();
+        println!("    {verdict:?}");
+        assert_eq!(verdict, Ok(true));
     }
 
     // Reject all invalid strings
     for _ in 0..5 {
         let s = shitpost(&mut rng);
         println!("{s}");
-        let mut run = s.chars().run(&parser);
-        println!("    {run:?}");
-        while run.next().is_some() {
-            println!("    {run:?}");
-        }
-        assert_eq!(run.ctrl, Err(accept(s.chars())));
+        let verdict = s.chars().run(&parser).finish();
+        println!("    {verdict:?}");
+        assert_eq!(verdict, Ok(accept(s.chars())));
     }
 }
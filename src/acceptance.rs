@@ -0,0 +1,51 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Why an input was accepted or rejected.
+
+use crate::{Deterministic, IllFormed};
+use core::fmt;
+
+/// Why an input was accepted or rejected.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Acceptance {
+    /// Ended in an accepting state with an empty stack.
+    Accepted,
+    /// Read the whole input but ended in a non-accepting state (whatever was left on the stack).
+    RejectedNonAccepting,
+    /// Ended in an accepting state, but with this many symbols still on the stack.
+    RejectedNonEmptyStack(usize),
+    /// Got stuck on the token at this index: either no transition matched or it tried to pop an empty stack.
+    RejectedDeadState(usize),
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Same as `accept`, but say why the input was rejected.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn classify<I: IntoIterator<Item = A>>(
+        &self,
+        input: I,
+    ) -> Result<Acceptance, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        for (i, token) in input.into_iter().enumerate() {
+            ctrl = match self.step_det(ctrl, &mut stack, Some(&token))? {
+                Ok(ok) => ok,
+                Err(_) => return Ok(Acceptance::RejectedDeadState(i)),
+            };
+        }
+        Ok(if !get!(self.states, ctrl).accepting {
+            Acceptance::RejectedNonAccepting
+        } else if stack.is_empty() {
+            Acceptance::Accepted
+        } else {
+            Acceptance::RejectedNonEmptyStack(stack.len())
+        })
+    }
+}
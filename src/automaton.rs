@@ -6,14 +6,23 @@
 
 //! Visibly pushdown automata.
 
-use crate::{merge, Edge, Execute, IllFormed, Indices, Lookup, Run, State};
-use core::{fmt, num::NonZeroUsize};
-use std::collections::BTreeSet;
+use crate::{
+    merge, Call, CurryOpt, Discrete, Edge, Execute, IllFormed, Indices, Lookup, LookupPolicy,
+    Range, Return, Run as _, Stack, State, Wildcard,
+};
+use core::{
+    borrow::Borrow,
+    fmt,
+    iter::{once, Peekable},
+    mem::{replace, take},
+    num::NonZeroUsize,
+};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Deterministic visibly pushdown automaton: each token causes exactly one transition.
-pub type Deterministic<A, S> = Automaton<A, S, usize>;
+pub type Deterministic<A, S, In = (), Out = ()> = Automaton<A, S, usize, In, Out>;
 /// Deterministic visibly pushdown automaton: each token can cause many transitions, and if any accept, the automaton accepts.
-pub type Nondeterministic<A, S> = Automaton<A, S, BTreeSet<usize>>;
+pub type Nondeterministic<A, S, In = (), Out = ()> = Automaton<A, S, BTreeSet<usize>, In, Out>;
 
 /// Visibly pushdown automaton containing all states.
 #[allow(clippy::exhaustive_structs)]
@@ -22,14 +31,18 @@ pub struct Automaton<
     A: 'static + fmt::Debug + Ord,
     S: 'static + fmt::Debug + Copy + Ord,
     Ctrl: Indices<A, S>,
+    In: 'static = (),
+    Out: 'static = (),
 > {
     /// Every state in the automaton.
-    pub states: Vec<State<A, S, Ctrl>>,
+    pub states: Vec<State<A, S, Ctrl, In, Out>>,
     /// Index of the state of the machine before parsing any input.
     pub initial: Ctrl,
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Default for Automaton<A, S, usize> {
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, In: 'static, Out: 'static> Default
+    for Automaton<A, S, usize, In, Out>
+{
     #[inline]
     fn default() -> Self {
         Self {
@@ -39,12 +52,15 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Default for Automaton<A, S
     }
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Default for Automaton<A, S, BTreeSet<usize>> {
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, In: 'static, Out: 'static> Default
+    for Automaton<A, S, BTreeSet<usize>, In, Out>
+{
+    /// Single non-accepting state, just like the deterministic default, so it rejects everything but is still well-formed.
     #[inline]
     fn default() -> Self {
         Self {
-            states: vec![],
-            initial: BTreeSet::new(),
+            states: vec![State::default()],
+            initial: once(0).collect(),
         }
     }
 }
@@ -67,23 +83,366 @@ impl<
         stack: &mut Vec<S>,
         maybe_token: Option<&A>,
     ) -> Result<Result<Self::Ctrl, bool>, IllFormed<A, S, Ctrl>> {
-        let mut states = ctrl.iter().map(|&i| get!(self.states, i));
+        self.step_in(ctrl, stack, maybe_token)
+    }
+    #[inline]
+    fn would_accept(&self, ctrl: &Self::Ctrl, stack: &[S]) -> bool {
+        stack.is_empty() && ctrl.iter().any(|i| get!(self.states, i).accepting)
+    }
+    #[inline]
+    fn step_in<St: Stack<S>>(
+        &self,
+        ctrl: Self::Ctrl,
+        stack: &mut St,
+        maybe_token: Option<&A>,
+    ) -> Result<Result<Self::Ctrl, bool>, IllFormed<A, S, Ctrl>> {
+        trace!(ctrl = ?ctrl, stack = ?stack, token = ?maybe_token, "step");
         let Some(token) = maybe_token else {
-            return Ok(Err(stack.is_empty() && states.any(|s| s.accepting)));
+            return Ok(Err(self.would_accept_in(&ctrl, stack)));
         };
+        let states = ctrl.iter().map(|i| get!(self.states, i));
         let maybe_stack_top = stack.last();
         let edges = states.filter_map(|s| s.transitions.get((maybe_stack_top, (token, ()))));
         let mega_edge: Edge<A, S, Ctrl> = match merge(edges) {
             None => return Ok(Err(false)),
-            Some(Err(e)) => return Err(e),
+            Some(Err(e)) => return Err(IllFormed::AmbiguousInput(token.clone(), Box::new(e))),
             Some(Ok(ok)) => ok,
         };
+        if matches!(mega_edge, Edge::Epsilon { .. }) {
+            return Err(IllFormed::Epsilon);
+        }
         Ok(mega_edge.invoke(stack))
     }
+    #[inline]
+    fn would_accept_in<St: Stack<S>>(&self, ctrl: &Self::Ctrl, stack: &St) -> bool {
+        stack.is_empty() && ctrl.iter().any(|i| get!(self.states, i).accepting)
+    }
+    /// No states left at all. Spotting trap states too would mean scanning their transitions after every token,
+    /// so a run stuck in one just keeps going until its input runs out.
+    #[inline(always)]
+    fn is_dead(&self, ctrl: &Self::Ctrl) -> bool {
+        ctrl.iter().next().is_none()
+    }
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices<A, S>> fmt::Debug
-    for Automaton<A, S, Ctrl>
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Same as `step`, but without `merge`: a deterministic automaton has at most one edge to take.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn step_det(
+        &self,
+        ctrl: usize,
+        stack: &mut Vec<S>,
+        maybe_token: Option<&A>,
+    ) -> Result<Result<usize, bool>, IllFormed<A, S, usize>> {
+        self.step_with_policy(ctrl, stack, maybe_token, LookupPolicy::Exclusive)
+    }
+
+    /// Same as `step_det`, but resolving overlapping ranges according to `lookup_policy`.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn step_with_policy(
+        &self,
+        ctrl: usize,
+        stack: &mut Vec<S>,
+        maybe_token: Option<&A>,
+        lookup_policy: LookupPolicy,
+    ) -> Result<Result<usize, bool>, IllFormed<A, S, usize>> {
+        let Some(token) = maybe_token else {
+            return Ok(Err(self.would_accept(&ctrl, stack)));
+        };
+        let transitions = &get!(self.states, ctrl).transitions;
+        let found = match lookup_policy {
+            LookupPolicy::Exclusive => transitions.get((stack.last(), (token, ()))),
+            LookupPolicy::FirstMatch => transitions
+                .first_match(stack.last(), token)
+                .map(|&Return(ref edge)| edge),
+        };
+        let Some(edge) = found else {
+            return Ok(Err(false));
+        };
+        Ok(match *edge {
+            Edge::Call { dst, push, .. } => {
+                stack.push(push);
+                Ok(dst)
+            }
+            Edge::Return { dst, .. } => stack.pop().map_or(Err(false), |_| Ok(dst)),
+            Edge::Local { dst, .. } => Ok(dst),
+            Edge::Epsilon { .. } => return Err(IllFormed::Epsilon),
+            Edge::Phantom(..) => never!(),
+        })
+    }
+
+    /// Same as `accept`, but stepping with `step_det`.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_det<I: IntoIterator<Item = A>>(
+        &self,
+        i: I,
+    ) -> Result<bool, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        for token in i {
+            ctrl = match self.step_det(ctrl, &mut stack, Some(&token))? {
+                Ok(ok) => ok,
+                Err(b) => return Ok(b),
+            };
+        }
+        Ok(self.would_accept(&ctrl, &stack))
+    }
+
+    /// Same as `accept_det`, but resolving overlapping ranges according to `lookup_policy`,
+    /// e.g. to let earlier ranges deliberately take priority over later ones.
+    /// ```rust
+    /// use vpa::*;
+    /// let edge = |dst| Return(Edge::Local { dst, call: call!(|x| x) });
+    /// let d: Deterministic<char, ()> = Automaton {
+    ///     states: vec![
+    ///         State {
+    ///             transitions: CurryOpt {
+    ///                 wildcard: Some(Wildcard::Specific(vec![
+    ///                     (Range { first: 'a', last: 'c' }, edge(1)),
+    ///                     (Range { first: 'a', last: 'z' }, edge(2)),
+    ///                 ])),
+    ///                 none: None,
+    ///                 some: Default::default(),
+    ///             },
+    ///             accepting: false,
+    ///         },
+    ///         State { transitions: CurryOpt::default(), accepting: true },
+    ///         State { transitions: CurryOpt::default(), accepting: false },
+    ///     ],
+    ///     initial: 0,
+    /// };
+    /// assert_eq!(d.accept_with_policy("b".chars(), LookupPolicy::FirstMatch), Ok(true));
+    /// assert_eq!(d.accept_with_policy("x".chars(), LookupPolicy::FirstMatch), Ok(false));
+    /// ```
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_with_policy<I: IntoIterator<Item = A>>(
+        &self,
+        input: I,
+        lookup_policy: LookupPolicy,
+    ) -> Result<bool, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        for token in input {
+            ctrl = match self.step_with_policy(ctrl, &mut stack, Some(&token), lookup_policy)? {
+                Ok(ok) => ok,
+                Err(b) => return Ok(b),
+            };
+        }
+        Ok(self.would_accept(&ctrl, &stack))
+    }
+
+    /// Same as `accept_det`, but reading tokens through dynamic dispatch,
+    /// so only one copy of this function is compiled no matter how many kinds of iterators feed it.
+    /// Each token costs a virtual call instead of something the optimizer can inline,
+    /// so prefer `accept_det` in hot loops over a single kind of input.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_dyn(
+        &self,
+        tokens: &mut dyn Iterator<Item = A>,
+    ) -> Result<bool, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        for token in tokens {
+            ctrl = match self.step_det(ctrl, &mut stack, Some(&token))? {
+                Ok(ok) => ok,
+                Err(b) => return Ok(b),
+            };
+        }
+        Ok(self.would_accept(&ctrl, &stack))
+    }
+
+    /// Same as `accept_det`, but reading tokens from a fallible source (e.g. a lexer),
+    /// stopping at the first input error and returning it in the inner `Result`.
+    /// Input rejected before any error stops there too, without reading the rest.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn accept_try<E, I: IntoIterator<Item = Result<A, E>>>(
+        &self,
+        i: I,
+    ) -> Result<Result<bool, E>, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        for maybe_token in i {
+            let token = match maybe_token {
+                Ok(ok) => ok,
+                Err(e) => return Ok(Err(e)),
+            };
+            ctrl = match self.step_det(ctrl, &mut stack, Some(&token))? {
+                Ok(ok) => ok,
+                Err(b) => return Ok(Ok(b)),
+            };
+        }
+        Ok(Ok(self.would_accept(&ctrl, &stack)))
+    }
+
+    /// Length of the longest nonempty prefix of this input that would be accepted on its own,
+    /// or `None` if there isn't one (as in a maximal-munch tokenizer).
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn longest_match(&self, input: &[A]) -> Result<Option<usize>, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        let mut longest = None;
+        for (i, token) in input.iter().enumerate() {
+            ctrl = match self.step_det(ctrl, &mut stack, Some(token))? {
+                Ok(ok) => ok,
+                Err(_) => break,
+            };
+            if self.would_accept(&ctrl, &stack) {
+                longest = Some(i.saturating_add(1));
+            }
+        }
+        Ok(longest)
+    }
+
+    /// Consume tokens from the front of this stream for as long as the automaton has somewhere to go,
+    /// peeking at each before taking it, and return whether everything consumed is accepted.
+    /// The token that would have been rejected (and everything after it) is left for the next parser.
+    /// Since a peekable stream can't be rewound, this commits to the longest prefix without a dead end,
+    /// which is the longest accepted prefix whenever every prefix of an accepted input can still be extended.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_prefix_in_place<I: Iterator<Item = A>>(
+        &self,
+        iter: &mut Peekable<I>,
+    ) -> Result<bool, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        while let Some(token) = iter.peek() {
+            // A rejected step leaves the stack untouched
+            match self.step_det(ctrl, &mut stack, Some(token))? {
+                Ok(next) => ctrl = next,
+                Err(_) => break,
+            }
+            drop(iter.next());
+        }
+        Ok(self.would_accept(&ctrl, &stack))
+    }
+
+    /// Span `(start, end)` of the first nonempty slice of this input that would be accepted on its own,
+    /// taking the earliest start and then the longest match from there, or `None` if there isn't one.
+    /// This tries `longest_match` from every start position, so it's quadratic in the worst case;
+    /// to match the whole input, use `accept`.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn find(&self, input: &[A]) -> Result<Option<(usize, usize)>, IllFormed<A, S, usize>> {
+        for start in 0..input.len() {
+            if let Some(len) = self.longest_match(get!(input, start..))? {
+                return Ok(Some((start, start.saturating_add(len))));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Same as `accept`, but also return the stack as it was when the run ended,
+    /// e.g. to report how deeply nested an unbalanced input was left.
+    /// If the input was rejected partway through, this is the stack at that point.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::unreachable)]
+    pub fn run_to_end<I: IntoIterator<Item = A>>(
+        &self,
+        input: I,
+    ) -> Result<(bool, Vec<S>), IllFormed<A, S, usize>> {
+        let mut run = input.into_iter().run(self);
+        for r in &mut run {
+            drop(r?);
+        }
+        if let Err(b) = run.ctrl {
+            Ok((b, run.stack))
+        } else {
+            never!()
+        }
+    }
+}
+
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Deterministic<A, S, In, Out>
+{
+    /// Run as a syntax-directed translator: for every edge taken, in order, map its call to an output with `calls`.
+    /// Return the outputs if the input is accepted, or `None` if it's rejected.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn transduce<T, I: IntoIterator<Item = A>, F: Fn(&Call<In, Out>) -> T>(
+        &self,
+        input: I,
+        calls: F,
+    ) -> Result<Option<Vec<T>>, IllFormed<A, S, usize, In, Out>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        let mut outputs = vec![];
+        for token in input {
+            let Some(edge) = get!(self.states, ctrl)
+                .transitions
+                .get((stack.last(), (&token, ())))
+            else {
+                return Ok(None);
+            };
+            let Some(call) = edge.call() else {
+                return Err(IllFormed::Epsilon);
+            };
+            outputs.push(calls(call));
+            ctrl = match edge.clone().invoke(&mut stack) {
+                Ok(ok) => ok,
+                Err(_) => return Ok(None),
+            };
+        }
+        Ok((stack.is_empty() && get!(self.states, ctrl).accepting).then_some(outputs))
+    }
+
+    /// Left quotient by a single token: an automaton accepting exactly the inputs `w` such that this one accepts `token` followed by `w`.
+    /// Return `None` if `token` leads to a dead state (no transition from the initial state on an empty stack).
+    ///
+    /// Only stackless tokens are supported for now: if `token` pushes, the quotient would have to start with that symbol
+    /// already on the stack, which an initial state can't express, so this returns `None` as well.
+    #[inline]
+    #[must_use]
+    pub fn derivative(&self, token: &A) -> Option<Self> {
+        match *get!(self.states, self.initial)
+            .transitions
+            .get((None, (token, ())))?
+        {
+            Edge::Local { dst, .. } => Some(Self {
+                states: self.states.clone(),
+                initial: dst,
+            }),
+            Edge::Call { .. } | Edge::Return { .. } | Edge::Epsilon { .. } => None,
+            Edge::Phantom(..) => never!(),
+        }
+    }
+}
+
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: fmt::Debug + Indices<A, S>,
+        In: 'static,
+        Out: 'static,
+    > fmt::Debug for Automaton<A, S, Ctrl, In, Out>
 {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -96,77 +455,563 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>
-    Automaton<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Automaton<A, S, Ctrl, In, Out>
 {
-    /// Run to completion and return whether or not the input was valid.
-    /// # Errors
-    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    /// Automaton with no states yet but room for `capacity` of them without reallocating.
     #[inline]
-    #[allow(clippy::unreachable)]
-    pub fn accept<I: IntoIterator>(&self, i: I) -> Result<bool, IllFormed<A, S, Ctrl>>
-    where
-        Ctrl: fmt::Debug,
-        I::IntoIter: Run<A>,
-    {
-        let mut run = i.into_iter().run(self);
-        for r in &mut run {
-            drop(r?);
+    #[must_use]
+    pub fn with_capacity(capacity: usize, initial: Ctrl) -> Self {
+        Self {
+            states: Vec::with_capacity(capacity),
+            initial,
         }
-        if let Err(b) = run.ctrl {
-            Ok(b)
-        } else {
-            never!()
+    }
+
+    /// Automaton with these states, starting from these, checked with `check`.
+    /// Also refuses an empty set of initial states, which rejects every input:
+    /// that's well-formed but almost always a bug, so to build a reject-all automaton on purpose,
+    /// construct the struct directly.
+    /// # Errors
+    /// If the result would not be well-formed or would have no initial state.
+    #[inline]
+    pub fn new(
+        states: Vec<State<A, S, Ctrl, In, Out>>,
+        initial: Ctrl,
+    ) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
+        if initial.iter().next().is_none() {
+            return Err(IllFormed::EmptyInitialSet);
         }
+        let automaton = Self { states, initial };
+        automaton.check()?;
+        Ok(automaton)
     }
 
     /// Check for structural errors.
     /// # Errors
     /// If this automaton is not well-formed.
     #[inline]
-    pub fn check(&self) -> Result<(), IllFormed<A, S, Ctrl>> {
+    pub fn check(&self) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
         let size = self.states.len();
-        if self.initial.iter().any(|&i| i >= size) {
+        if self.initial.iter().any(|i| i >= size) {
             return Err(IllFormed::OutOfBounds);
         }
         if let Some(nz) = NonZeroUsize::new(size) {
+            for (i, state) in self.states.iter().enumerate() {
+                state.check(nz).map_err(|e| {
+                    if let IllFormed::CurryOptMergeConflict(condition, tokens) = e {
+                        IllFormed::WildcardShadowsSpecific {
+                            state: i,
+                            condition,
+                            tokens,
+                        }
+                    } else {
+                        e
+                    }
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove every state that is either unreachable from an initial state
+    /// or from which no accepting state is reachable, then renumber the rest.
+    /// Initial states are always kept so that the automaton stays well-formed.
+    /// Transitions into removed states are removed as well, which changes nothing,
+    /// since any run taking them could never have accepted anyway.
+    #[inline]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn trim(&mut self) {
+        let size = self.states.len();
+        let reachable = self.reachable();
+
+        // Backward reachability from the accepting states
+        let mut predecessors = vec![vec![]; size];
+        for i in 0..size {
+            for j in self.successors(i) {
+                get_mut!(predecessors, j).push(i);
+            }
+        }
+        let mut coreachable = vec![false; size];
+        let mut frontier: Vec<usize> = (0..size)
+            .filter(|&i| get!(self.states, i).accepting)
+            .collect();
+        while let Some(i) = frontier.pop() {
+            if !replace(get_mut!(coreachable, i), true) {
+                frontier.extend(get!(predecessors, i).iter().copied());
+            }
+        }
+
+        // Keep only what's both, plus the initial states
+        let mut keep: Vec<bool> = reachable
+            .into_iter()
+            .zip(coreachable)
+            .map(|(fwd, bwd)| fwd && bwd)
+            .collect();
+        for i in self.initial.iter() {
+            *get_mut!(keep, i) = true;
+        }
+        self.retain_states(&keep);
+    }
+
+    /// Remove every state unreachable from an initial state, then renumber the rest.
+    /// Unlike `trim`, this keeps dead states, so every remaining transition stays exactly as it was.
+    /// Return whether anything was removed.
+    #[inline]
+    pub fn remove_unreachable(&mut self) -> bool {
+        let reachable = self.reachable();
+        if reachable.iter().all(|&r| r) {
+            return false;
+        }
+        self.retain_states(&reachable);
+        true
+    }
+
+    /// Make the empty input accepted (`true`) or rejected (`false`) without changing the verdict on any other input.
+    /// If that's already the case, nothing changes.
+    /// Otherwise, each initial state is replaced by a fresh copy with the same transitions but the requested `accepting` flag;
+    /// since no edge leads back into these copies, their flags only ever matter before the first token.
+    #[inline]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn set_accepts_empty(&mut self, value: bool) {
+        if self.initial.iter().any(|i| get!(self.states, i).accepting) == value {
+            return;
+        }
+        let states = &mut self.states;
+        self.initial.map(|i| {
+            let mut copy = get!(states, *i).clone();
+            copy.accepting = value;
+            *i = states.len();
+            states.push(copy);
+        });
+    }
+
+    /// Which states are reachable from an initial state, ignoring the stack.
+    /// Out-of-bounds indices are skipped, leaving them for `check` to report.
+    #[inline]
+    pub(crate) fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.states.len()];
+        let mut frontier: Vec<usize> = self.initial.iter().collect();
+        while let Some(i) = frontier.pop() {
+            if reachable.get_mut(i).is_some_and(|r| !replace(r, true)) {
+                frontier.extend(self.successors(i));
+            }
+        }
+        reachable
+    }
+
+    /// Keep only the states marked `true` (which must include every initial state), renumbering the rest
+    /// and removing transitions into anything removed.
+    /// Out-of-bounds indices are left alone, so they stay out of bounds for `check` to report.
+    #[inline]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(crate) fn retain_states(&mut self, keep: &[bool]) {
+        let mut renumber = Vec::with_capacity(keep.len());
+        let mut next = 0;
+        for &k in keep {
+            renumber.push(k.then_some(next));
+            if k {
+                next += 1;
+            }
+        }
+        self.states = take(&mut self.states)
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(state, &k)| k.then_some(state))
+            .collect();
+        for state in &mut self.states {
+            state.transitions.retain_edges(|edge| {
+                <Ctrl as Indices<A, S>>::collect(
+                    edge.dst()
+                        .iter()
+                        .filter_map(|j| renumber.get(j).map_or(Some(j), |&r| r)),
+                )
+                .map(|dst| *edge.dst_mut() = dst)
+                .is_ok()
+            });
+        }
+        Indices::<A, S>::map(&mut self.initial, |j| {
+            if let Some(&Some(r)) = renumber.get(*j) {
+                *j = r;
+            }
+        });
+    }
+
+    /// Collapse structurally identical states into one, redirecting every edge to the survivor.
+    /// Repeats until nothing changes, since merging two states can make their predecessors identical.
+    /// Calls count toward identity (by `src`, as in every comparison), since collapsing two states
+    /// that run different calls would change what the automaton computes, not just what it accepts.
+    #[inline]
+    #[allow(clippy::arithmetic_side_effects, clippy::missing_panics_doc)]
+    pub fn dedup_states(&mut self)
+    where
+        Ctrl: Ord,
+    {
+        loop {
+            let size = self.states.len();
+            let mut representatives = BTreeMap::new();
+            let mut renumber = Vec::with_capacity(size);
+            let mut keep = Vec::with_capacity(size);
             for state in &self.states {
-                state.check(nz)?;
+                let next = representatives.len();
+                let &mut j = representatives.entry(state.clone()).or_insert(next);
+                renumber.push(j);
+                keep.push(j == next);
+            }
+            if representatives.len() == size {
+                return;
+            }
+            self.states = take(&mut self.states)
+                .into_iter()
+                .zip(&keep)
+                .filter_map(|(state, &k)| k.then_some(state))
+                .collect();
+            for state in &mut self.states {
+                state
+                    .transitions
+                    .map_values(|edge| edge.dst_mut().map(|j| *j = *get!(renumber, *j)));
+            }
+            self.initial.map(|j| *j = *get!(renumber, *j));
+        }
+    }
+
+    /// Apply a function to every edge's call (e.g. to retarget code generation).
+    #[inline]
+    pub fn map_calls<F: FnMut(&mut Call<In, Out>)>(&mut self, mut f: F) {
+        for state in &mut self.states {
+            state.transitions.map_values(|edge| {
+                if let Some(call) = edge.call_mut() {
+                    f(call);
+                }
+            });
+        }
+    }
+
+    /// Move each state `i` to index `perm[i]`, updating every edge and the initial state(s) to match.
+    /// # Errors
+    /// If `perm` is not a permutation of `0..self.states.len()`.
+    #[inline]
+    pub fn relabel_states(&mut self, perm: &[usize]) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
+        let size = self.states.len();
+        if perm.len() != size {
+            return Err(IllFormed::NotAPermutation);
+        }
+        let mut seen = vec![false; size];
+        for &j in perm {
+            if seen.get_mut(j).is_none_or(|b| replace(b, true)) {
+                return Err(IllFormed::NotAPermutation);
             }
         }
+        let mut indexed: Vec<_> = take(&mut self.states).into_iter().zip(perm).collect();
+        indexed.sort_unstable_by_key(|&(_, &j)| j);
+        self.states = indexed.into_iter().map(|(state, _)| state).collect();
+        for state in &mut self.states {
+            state
+                .transitions
+                .map_values(|edge| edge.dst_mut().map(|j| *j = *get!(perm, *j)));
+        }
+        self.initial.map(|j| *j = *get!(perm, *j));
         Ok(())
     }
+
+    /// States with a `Return` edge that can only ever be taken on an empty stack, which always rejects.
+    /// Tracks only whether the stack is empty or not, so reachability is over-approximated
+    /// and every state flagged here is genuinely a dead pop.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn find_unmatched_returns(&self) -> Vec<usize> {
+        let reachable = self.stack_reachability();
+        (0..self.states.len())
+            .filter(|&i| {
+                let transitions = &get!(self.states, i).transitions;
+                let [empty, nonempty] = *get!(reachable, i);
+                let none_pops = transitions
+                    .none
+                    .iter()
+                    .flat_map(Wildcard::values)
+                    .any(|edge| matches!(*edge, Edge::Return { .. }));
+                let wildcard_pops = transitions
+                    .wildcard
+                    .iter()
+                    .flat_map(Wildcard::values)
+                    .any(|edge| matches!(*edge, Edge::Return { .. }));
+                empty && (none_pops || (!nonempty && wildcard_pops))
+            })
+            .collect()
+    }
+
+    /// Whether each state is reachable with an empty (`[true, _]`) or nonempty (`[_, true]`) stack.
+    /// Tracks only whether the stack is empty, so this over-approximates.
+    #[inline]
+    pub(crate) fn stack_reachability(&self) -> Vec<[bool; 2]> {
+        let size = self.states.len();
+        // Whether each state is reachable with an empty (`0`) or nonempty (`1`) stack
+        let mut reachable = vec![[false; 2]; size];
+        let mut frontier: Vec<(usize, usize)> = self.initial.iter().map(|i| (i, 0)).collect();
+        while let Some((i, nonempty)) = frontier.pop() {
+            if replace(get_mut!(get_mut!(reachable, i), nonempty), true) {
+                continue;
+            }
+            let transitions = &get!(self.states, i).transitions;
+            // Keyed on the empty stack only if it is empty, and on a symbol only if it isn't
+            let wilds = transitions.wildcard.iter().chain(if nonempty == 0 {
+                transitions.none.iter().collect::<Vec<_>>()
+            } else {
+                transitions.some.values().collect()
+            });
+            for edge in wilds.flat_map(Wildcard::values) {
+                for j in edge.dst().iter() {
+                    match *edge {
+                        Edge::Call { .. } => frontier.push((j, 1)),
+                        Edge::Return { .. } if nonempty == 1 => frontier.extend([(j, 0), (j, 1)]),
+                        Edge::Return { .. } => {}
+                        Edge::Local { .. } | Edge::Epsilon { .. } => frontier.push((j, nonempty)),
+                        Edge::Phantom(..) => never!(),
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Indices of all states reachable in one transition from this state, ignoring the stack.
+    #[inline]
+    fn successors(&self, i: usize) -> Vec<usize> {
+        get!(self.states, i)
+            .transitions
+            .values()
+            .flat_map(Wildcard::values)
+            .flat_map(|edge| edge.dst().iter())
+            .collect()
+    }
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>
+    Automaton<A, S, Ctrl>
+{
+    /// Run to completion and return whether or not the input was valid.
+    /// This is the usual visibly pushdown _acceptor_ convention:
+    /// input is accepted only if it ends in an accepting state _and_ with an empty stack.
+    /// See `accept_configuration` for the lenient alternative.
+    /// Tokens may be owned or borrowed (e.g. `chars()` or `&[char]`), since each is only ever looked at.
+    /// This steps the automaton directly rather than through an `Execution`, so there's no stack limit:
+    /// run it with `Run::run` and `Execution::with_max_stack_depth` to bound the stack on untrusted input.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept<T: Borrow<A>, I: IntoIterator<Item = T>>(
+        &self,
+        i: I,
+    ) -> Result<bool, IllFormed<A, S, Ctrl>>
+    where
+        Ctrl: fmt::Debug,
+    {
+        let mut ctrl = self.initial.clone();
+        let mut stack = vec![];
+        for token in i {
+            ctrl = match self.step(ctrl, &mut stack, Some(token.borrow()))? {
+                Ok(ok) if !self.is_dead(&ok) => ok,
+                Ok(_) => return Ok(false),
+                Err(b) => return Ok(b),
+            };
+        }
+        Ok(self.would_accept(&ctrl, &stack))
+    }
+
+    /// Same as `accept`, but normalize each token before lookup (e.g. `char::to_ascii_lowercase` for case-insensitive matching).
+    /// The automaton's ranges must be expressed in normalized space.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_normalized<I: IntoIterator<Item = A>>(
+        &self,
+        i: I,
+        normalize: fn(A) -> A,
+    ) -> Result<bool, IllFormed<A, S, Ctrl>>
+    where
+        Ctrl: fmt::Debug,
+    {
+        self.accept(i.into_iter().map(normalize))
+    }
+
+    /// Same as `accept`, which takes borrowed tokens too.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[deprecated = "`accept` takes borrowed tokens too"]
+    pub fn accept_ref<'t, I: IntoIterator<Item = &'t A>>(
+        &self,
+        i: I,
+    ) -> Result<bool, IllFormed<A, S, Ctrl>>
+    where
+        A: 't,
+        Ctrl: fmt::Debug,
+    {
+        self.accept(i)
+    }
+
+    /// Run to completion and return whether or not the input was valid under either acceptance convention:
+    /// - If `require_empty_stack`, this is exactly `accept`: input must end in an accepting state with an empty stack.
+    /// - Otherwise, this is a pushdown _recognizer_: input must end in an accepting state, whatever is left on the stack.
+    ///
+    /// Either way, input is rejected if it runs into a missing transition or tries to pop an empty stack.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_configuration<I: IntoIterator<Item = A>>(
+        &self,
+        i: I,
+        require_empty_stack: bool,
+    ) -> Result<bool, IllFormed<A, S, Ctrl>>
+    where
+        Ctrl: fmt::Debug,
+    {
+        let mut ctrl = self.initial.clone();
+        let mut stack = vec![];
+        for token in i {
+            ctrl = match self.step(ctrl, &mut stack, Some(&token))? {
+                Ok(ok) if !self.is_dead(&ok) => ok,
+                Ok(_) => return Ok(false),
+                Err(b) => return Ok(b),
+            };
+        }
+        Ok((!require_empty_stack || stack.is_empty())
+            && ctrl.iter().any(|j| get!(self.states, j).accepting))
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices<A, S>>
+    Automaton<A, S, Ctrl>
+{
+    /// Assert that every one of these inputs is accepted.
+    /// # Panics
+    /// If any input is rejected or if the automaton is ill-formed.
+    #[inline]
+    #[allow(clippy::panic)]
+    pub fn assert_accepts<I: IntoIterator<Item = Vec<A>>>(&self, positives: I) {
+        for input in positives {
+            match self.accept(input.iter().cloned()) {
+                Ok(true) => {}
+                Ok(false) => panic!("Rejected {input:?} but should have accepted: {self:?}"),
+                Err(e) => panic!("Ill-formed automaton on input {input:?}: {e:?} in {self:?}"),
+            }
+        }
+    }
+
+    /// Assert that every one of these inputs is rejected.
+    /// # Panics
+    /// If any input is accepted or if the automaton is ill-formed.
+    #[inline]
+    #[allow(clippy::panic)]
+    pub fn assert_rejects<I: IntoIterator<Item = Vec<A>>>(&self, negatives: I) {
+        for input in negatives {
+            match self.accept(input.iter().cloned()) {
+                Ok(false) => {}
+                Ok(true) => panic!("Accepted {input:?} but should have rejected: {self:?}"),
+                Err(e) => panic!("Ill-formed automaton on input {input:?}: {e:?} in {self:?}"),
+            }
+        }
+    }
 }
 
 impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Automaton<A, S, BTreeSet<usize>> {
+    /// Automaton accepting exactly the balanced sequences of `open` and `close` tokens nested at most `max` deep,
+    /// e.g. to intersect with a grammar to cap its recursion depth.
+    /// State `i` means "currently nested `i` deep," and each `open` pushes `push`.
+    /// `open` and `close` should not overlap.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn bounded_depth(max: usize, push: S, open: &Range<A>, close: &Range<A>) -> Self {
+        Self {
+            states: (0..=max)
+                .map(|depth| State {
+                    transitions: CurryOpt {
+                        wildcard: (depth < max).then(|| {
+                            Wildcard::Specific(vec![(
+                                open.clone(),
+                                Return(Edge::Call {
+                                    dst: once(depth + 1).collect(),
+                                    call: call!(|x| x),
+                                    push,
+                                }),
+                            )])
+                        }),
+                        none: None,
+                        some: depth
+                            .checked_sub(1)
+                            .map(|shallower| {
+                                (
+                                    push,
+                                    Wildcard::Specific(vec![(
+                                        close.clone(),
+                                        Return(Edge::Return {
+                                            dst: once(shallower).collect(),
+                                            call: call!(|x| x),
+                                        }),
+                                    )]),
+                                )
+                            })
+                            .into_iter()
+                            .collect(),
+                    },
+                    accepting: depth == 0,
+                })
+                .collect(),
+            initial: once(0).collect(),
+        }
+    }
+
     /// Eliminate absurd relations like transitions to non-existing states.
+    /// Return whether that worked: `false` if some wildcard shadows only part of an entry that matches any token
+    /// (see `CurryOpt::deabsurdify`), which is left as it was.
     #[inline]
     #[allow(clippy::arithmetic_side_effects)]
-    pub fn deabsurdify(&mut self) -> bool {
-        if let Some(size) = NonZeroUsize::new(self.states.len()) {
-            Indices::<A, S>::map(&mut self.initial, |i| *i = *i % size);
-            for state in &mut self.states {
-                state.deabsurdify(size);
-            }
-        } else {
+    pub fn deabsurdify(&mut self) -> bool
+    where
+        A: Discrete,
+    {
+        let Some(size) = NonZeroUsize::new(self.states.len()) else {
             self.initial = BTreeSet::new();
+            return true;
         };
-        true
+        Indices::<A, S>::map(&mut self.initial, |i| *i = *i % size);
+        // Not `all`, which would stop at the first failure and leave later states untouched
+        let mut ok = true;
+        for state in &mut self.states {
+            ok &= state.deabsurdify(size).is_ok();
+        }
+        ok
     }
 }
 
 impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Automaton<A, S, usize> {
     /// Eliminate absurd relations like transitions to non-existing states.
+    /// Return whether that worked: `false` if there are no states for the initial state to be,
+    /// or if some wildcard shadows only part of an entry that matches any token
+    /// (see `CurryOpt::deabsurdify`), which is left as it was.
     #[inline]
     #[allow(clippy::arithmetic_side_effects)]
-    pub fn deabsurdify(&mut self) -> bool {
+    pub fn deabsurdify(&mut self) -> bool
+    where
+        A: Discrete,
+    {
         let Some(size) = NonZeroUsize::new(self.states.len()) else {
             return false;
         };
         Indices::<A, S>::map(&mut self.initial, |i| *i = *i % size);
+        // Not `all`, which would stop at the first failure and leave later states untouched
+        let mut ok = true;
         for state in &mut self.states {
-            state.deabsurdify(size);
+            ok &= state.deabsurdify(size).is_ok();
         }
-        true
+        ok
     }
 }
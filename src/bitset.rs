@@ -0,0 +1,248 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Compact set of indices stored one bit per index.
+
+use crate::{IllFormed, Indices, Merge};
+use core::{cmp, fmt, slice};
+
+/// Number of indices stored in each word.
+const BITS: usize = 64;
+
+/// Shift an index right by this much to get the index of its word.
+const SHIFT: usize = 6;
+
+/// Mask an index with this to get the index of its bit within its word.
+const MASK: usize = BITS - 1;
+
+/// Compact set of indices stored one bit per index.
+///
+/// Much more cache-friendly than a `BTreeSet<usize>` when there are many states and frontiers are dense.
+/// Iterates (and compares) in ascending order, exactly like a `BTreeSet<usize>`,
+/// so determinizing with either produces identical automata.
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+pub struct BitSet {
+    /// One bit per index, least significant first.
+    /// Never ends in a zero word, so structural equality is set equality.
+    words: Vec<u64>,
+}
+
+/// Iterator over the indices in a `BitSet`, in ascending order.
+#[derive(Clone, Debug)]
+pub struct BitSetIter<'a> {
+    /// Words we haven't started reading yet.
+    words: slice::Iter<'a, u64>,
+    /// Bits of the current word we haven't yet yielded.
+    current: u64,
+    /// Index of the least significant bit in the current word.
+    offset: usize,
+}
+
+impl Iterator for BitSetIter<'_> {
+    type Item = usize;
+    #[inline]
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current == 0 {
+            self.current = *self.words.next()?;
+            self.offset += BITS;
+        }
+        let bit = self.current.trailing_zeros() as usize;
+        self.current &= self.current - 1;
+        Some(self.offset - BITS + bit)
+    }
+}
+
+#[allow(clippy::same_name_method)]
+impl BitSet {
+    /// Empty set.
+    #[inline(always)]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { words: vec![] }
+    }
+
+    /// Add an index. Return whether it was newly added.
+    #[inline]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn insert(&mut self, i: usize) -> bool {
+        let (word, bit) = (i >> SHIFT, 1 << (i & MASK));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let w = get_mut!(self.words, word);
+        let fresh = *w & bit == 0;
+        *w |= bit;
+        fresh
+    }
+
+    /// Remove an index. Return whether it was present.
+    #[inline]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn remove(&mut self, i: usize) -> bool {
+        let Some(w) = self.words.get_mut(i >> SHIFT) else {
+            return false;
+        };
+        let bit = 1 << (i & MASK);
+        let present = *w & bit != 0;
+        *w &= !bit;
+        self.normalize();
+        present
+    }
+
+    /// Check whether an index is present.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn contains(&self, i: usize) -> bool {
+        self.words
+            .get(i >> SHIFT)
+            .is_some_and(|w| w & (1 << (i & MASK)) != 0)
+    }
+
+    /// Number of indices present.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::as_conversions)]
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Check whether no indices are present.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Iterate over indices in ascending order.
+    #[inline]
+    #[must_use]
+    pub fn iter(&self) -> BitSetIter<'_> {
+        BitSetIter {
+            words: self.words.iter(),
+            current: 0,
+            offset: 0,
+        }
+    }
+
+    /// Add every index in another set.
+    #[inline]
+    pub fn union_with(&mut self, other: &Self) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (l, r) in self.words.iter_mut().zip(&other.words) {
+            *l |= *r;
+        }
+    }
+
+    /// Remove trailing zero words.
+    #[inline]
+    fn normalize(&mut self) {
+        while self.words.last() == Some(&0) {
+            let _ = self.words.pop();
+        }
+    }
+}
+
+impl fmt::Debug for BitSet {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Ord for BitSet {
+    #[inline]
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl PartialOrd for BitSet {
+    #[inline(always)]
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut rtn = Self::new();
+        rtn.extend(iter);
+        rtn
+    }
+}
+
+impl Extend<usize> for BitSet {
+    #[inline]
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for i in iter {
+            let _ = self.insert(i);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a BitSet {
+    type Item = usize;
+    type IntoIter = BitSetIter<'a>;
+    #[inline(always)]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Indices<A, S> for BitSet {
+    type View<'a> = BitSetIter<'a>;
+    #[inline(always)]
+    fn iter(&self) -> Self::View<'_> {
+        self.iter()
+    }
+    #[inline(always)]
+    fn contains(&self, index: usize) -> bool {
+        BitSet::contains(self, index)
+    }
+    #[inline]
+    fn map<F: FnMut(&mut usize)>(&mut self, mut f: F) {
+        *self = self
+            .iter()
+            .map(|immut| {
+                let mut i = immut;
+                f(&mut i);
+                i
+            })
+            .collect();
+    }
+    #[inline]
+    fn flat_map<F: FnMut(usize) -> Self>(self, mut f: F) -> Self {
+        let mut rtn = Self::new();
+        for i in &self {
+            rtn.union_with(&f(i));
+        }
+        rtn
+    }
+    #[inline]
+    fn collect<I: IntoIterator<Item = usize>>(iter: I) -> Result<Self, bool> {
+        let rtn: Self = iter.into_iter().collect();
+        if rtn.is_empty() {
+            Err(false)
+        } else {
+            Ok(rtn)
+        }
+    }
+}
+
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, In: 'static, Out: 'static>
+    Merge<A, S, BitSet, In, Out> for BitSet
+{
+    #[inline(always)]
+    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, BitSet, In, Out>> {
+        self.union_with(other);
+        Ok(self)
+    }
+}
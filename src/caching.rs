@@ -0,0 +1,118 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Incremental validation of a growing (or shrinking) input, e.g. as a user types.
+
+use crate::{Execute, IllFormed, Stack};
+use core::fmt;
+
+/// Execution that remembers its configuration after every prefix of its input.
+///
+/// Appending a token takes one step, and editing the input re-runs only what changed.
+/// With a persistent stack, every remembered configuration shares structure with the one before it.
+#[allow(clippy::exhaustive_structs, clippy::type_complexity)]
+#[derive(Clone)]
+pub struct CachingRun<
+    'a,
+    A: 'static + fmt::Debug + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    E: Execute<A, S>,
+    St: Stack<S> = Vec<S>,
+> {
+    /// Reference to the automaton we're running.
+    pub graph: &'a E,
+    /// Input so far.
+    pub tokens: Vec<A>,
+    /// Configuration after each prefix of the input: index `i` is after the first `i` tokens,
+    /// so there's always exactly one more configuration than there are tokens.
+    pub configurations: Vec<(Result<E::Ctrl, bool>, St)>,
+}
+
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, E: Execute<A, S>, St: Stack<S>> fmt::Debug
+    for CachingRun<'_, A, S, E, St>
+where
+    E::Ctrl: fmt::Debug,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CachingRun {{ tokens: {:?}, configurations: {:?} }}",
+            self.tokens, self.configurations,
+        )
+    }
+}
+
+impl<
+        'a,
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        E: Execute<A, S>,
+        St: Stack<S>,
+    > CachingRun<'a, A, S, E, St>
+{
+    /// Start running this automaton without any input yet.
+    #[inline]
+    #[must_use]
+    pub fn new(graph: &'a E) -> Self {
+        Self {
+            graph,
+            tokens: vec![],
+            configurations: vec![(Ok(graph.initial()), St::default())],
+        }
+    }
+
+    /// Append one token, taking exactly one step (or none, if the input has already been rejected).
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn push(&mut self, token: A) -> Result<(), IllFormed<A, S, E::Ctrl>> {
+        let (ctrl, mut stack) = unwrap!(self.configurations.last()).clone();
+        let next = match ctrl {
+            Ok(ok) => self.graph.step_in(ok, &mut stack, Some(&token))?,
+            Err(b) => Err(b),
+        };
+        self.tokens.push(token);
+        self.configurations.push((next, stack));
+        Ok(())
+    }
+
+    /// Forget everything after the first `len` tokens without taking any steps.
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        self.tokens.truncate(len);
+        self.configurations.truncate(len.saturating_add(1));
+    }
+
+    /// Replace the input with this one, re-running only what comes after the prefix it shares with the last input.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn set_input(&mut self, input: &[A]) -> Result<(), IllFormed<A, S, E::Ctrl>> {
+        let shared = self
+            .tokens
+            .iter()
+            .zip(input)
+            .take_while(|&(old, new)| old == new)
+            .count();
+        self.truncate(shared);
+        for token in input.iter().skip(shared) {
+            self.push(token.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Whether the input so far would be accepted if it ended here.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn accepts(&self) -> bool {
+        let &(ref ctrl, ref stack) = unwrap!(self.configurations.last());
+        ctrl.as_ref()
+            .is_ok_and(|ok| self.graph.would_accept_in(ok, stack))
+    }
+}
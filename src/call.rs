@@ -7,11 +7,17 @@
 //! Calls both now and, in textual form, in the autogenerated source code.
 
 use crate::{IllFormed, Indices, Merge};
-use core::{cmp, fmt};
+use core::{
+    cmp, fmt,
+    hash::{Hash, Hasher},
+};
+use std::collections::BTreeMap;
 
 /// Both a function pointer and a source-code representation.
-#[derive(Clone, Hash)]
-#[allow(clippy::derived_hash_with_manual_eq, clippy::exhaustive_structs)]
+/// Comparison and hashing look only at `src`, the semantic identity:
+/// function addresses aren't stable across builds or optimization levels.
+#[derive(Clone)]
+#[allow(clippy::exhaustive_structs)]
 pub struct Call<I, O> {
     /// Function pointer.
     pub ptr: fn(I) -> O,
@@ -28,6 +34,13 @@ impl<I, O> PartialEq for Call<I, O> {
 
 impl<I, O> Eq for Call<I, O> {}
 
+impl<I, O> Hash for Call<I, O> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.src.hash(state);
+    }
+}
+
 impl<I, O> Ord for Call<I, O> {
     #[inline]
     fn cmp(&self, other: &Self) -> cmp::Ordering {
@@ -49,11 +62,16 @@ impl<I, O> fmt::Debug for Call<I, O> {
     }
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>, I, O> Merge<A, S, Ctrl>
-    for Call<I, O>
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        I: 'static,
+        O: 'static,
+    > Merge<A, S, Ctrl, I, O> for Call<I, O>
 {
     #[inline]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, I, O>> {
         if self == *other {
             Ok(self)
         } else {
@@ -62,10 +80,25 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>, I, O>
     }
 }
 
+impl<I, O> From<(fn(I) -> O, String)> for Call<I, O> {
+    #[inline(always)]
+    fn from((ptr, src): (fn(I) -> O, String)) -> Self {
+        Self::new(ptr, src)
+    }
+}
+
 impl<I, O> Call<I, O> {
     /// Construct a new `Call` from a function pointer and a source-code representation.
+    /// Prefer `call!` when writing a call by hand, since it keeps the source text in sync automatically;
+    /// use this (or `From<(fn(I) -> O, String)>`) when building calls programmatically, e.g. in generic code.
     #[inline(always)]
     pub const fn new(ptr: fn(I) -> O, src: String) -> Self {
         Self { ptr, src }
     }
+    /// Look up a function pointer by its source-code representation, e.g. when reloading a serialized automaton.
+    #[inline]
+    #[must_use]
+    pub fn from_registry(src: &str, registry: &BTreeMap<String, fn(I) -> O>) -> Option<Self> {
+        registry.get(src).map(|&ptr| Self::new(ptr, src.to_owned()))
+    }
 }
@@ -0,0 +1,57 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Determinize once, then validate many inputs.
+
+use crate::{Deterministic, IllFormed, Nondeterministic};
+use core::fmt;
+use std::collections::BTreeSet;
+
+/// Nondeterministic automaton determinized once up front, so each input runs in a single pass without tracking sets of states.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Compiled<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord> {
+    /// Determinized automaton.
+    pub automaton: Deterministic<A, S>,
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Nondeterministic<A, S> {
+    /// Determinize once and keep the result around for repeated use.
+    /// Running `accept` on a nondeterministic automaton tracks every reachable state at each step,
+    /// so when validating many inputs, this amortizes determinization over all of them.
+    /// # Errors
+    /// If determinization fails (see `determinize`).
+    #[inline]
+    pub fn compile(&self) -> Result<Compiled<A, S>, IllFormed<A, S, BTreeSet<usize>>> {
+        self.determinize().map(|automaton| Compiled { automaton })
+    }
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Compiled<A, S> {
+    /// Run to completion and return whether or not the input was valid.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept<I: IntoIterator<Item = A>>(
+        &self,
+        input: I,
+    ) -> Result<bool, IllFormed<A, S, usize>> {
+        self.automaton.accept_det(input)
+    }
+
+    /// Validate each input in turn, lazily.
+    #[inline]
+    pub fn accept_all<'c, Is: IntoIterator>(
+        &'c self,
+        inputs: Is,
+    ) -> impl Iterator<Item = Result<bool, IllFormed<A, S, usize>>> + 'c
+    where
+        Is::IntoIter: 'c,
+        Is::Item: IntoIterator<Item = A>,
+    {
+        inputs.into_iter().map(|input| self.accept(input))
+    }
+}
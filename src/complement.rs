@@ -0,0 +1,221 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Completion and complement of deterministic automata.
+
+use crate::{
+    complement_ranges, product::pushes, Alphabet, CurryOpt, Deterministic, Discrete, Edge,
+    IllFormed, Range, Return, State, Wildcard,
+};
+use core::{
+    fmt,
+    iter::{empty, once},
+};
+
+impl<A: 'static + fmt::Debug + Clone + Discrete + Ord, S: 'static + fmt::Debug + Copy + Ord>
+    Deterministic<A, S>
+{
+    /// Whether every state has a transition on every token in `universe` under every stack condition it could see.
+    #[inline]
+    #[must_use]
+    pub fn is_total(&self, universe: &Range<A>) -> bool {
+        let conditions = conditions(self);
+        self.states.iter().all(|state| {
+            conditions
+                .iter()
+                .all(|condition| gaps(&state.transitions, condition.as_ref(), universe).is_empty())
+        })
+    }
+
+    /// Send every token in `universe` that has no transition to a new non-accepting trap state,
+    /// so that no run stops before the end of its input.
+    /// Return whether anything was missing (and hence whether the trap state was added).
+    #[inline]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn complete(&mut self, universe: &Range<A>) -> bool {
+        let trap = self.states.len();
+        let conditions = conditions(self);
+        let mut added = false;
+        for state in &mut self.states {
+            for condition in &conditions {
+                let gaps = gaps(&state.transitions, condition.as_ref(), universe);
+                if gaps.is_empty() {
+                    continue;
+                }
+                added = true;
+                let entry = match *condition {
+                    None => state
+                        .transitions
+                        .none
+                        .get_or_insert_with(|| Wildcard::Specific(vec![])),
+                    Some(s) => state
+                        .transitions
+                        .some
+                        .entry(s)
+                        .or_insert_with(|| Wildcard::Specific(vec![])),
+                };
+                let Wildcard::Specific(ref mut v) = *entry else {
+                    never!()
+                };
+                v.extend(gaps.into_iter().map(|gap| (gap, to_trap(trap))));
+                v.sort_unstable_by_key(|&(ref range, _)| range.clone());
+            }
+        }
+        if added {
+            self.states.push(State {
+                transitions: CurryOpt {
+                    wildcard: Some(Wildcard::Any(to_trap(trap))),
+                    none: None,
+                    some: empty().collect(),
+                },
+                accepting: false,
+            });
+        }
+        added
+    }
+
+    /// Complement relative to inputs that end with an empty stack (and never pop an empty one):
+    /// among those, this accepts exactly the inputs this automaton rejects.
+    /// Acceptance always requires an empty stack, so no automaton can accept the rest,
+    /// and inputs that leave symbols on the stack are rejected by both.
+    ///
+    /// Missing transitions are first `complete`d with `universe`, so that they count as rejections
+    /// instead of silently turning into acceptances. Without a universe, the universe is every token
+    /// from the least to the greatest this automaton mentions, over which it must already be total
+    /// (as it is after `complete`).
+    /// # Errors
+    /// `IllFormed::NeedsAlphabet` if `universe` is `None` and this automaton isn't already total.
+    #[inline]
+    pub fn complement(&self, universe: Option<&Range<A>>) -> Result<Self, IllFormed<A, S, usize>> {
+        let mut total = self.clone();
+        if let Some(tokens) = universe {
+            let _: bool = total.complete(tokens);
+        } else {
+            let is_total = self.mentioned().map_or_else(
+                // No ranges at all, so only wildcards can cover anything
+                || {
+                    let conditions = conditions(self);
+                    self.states.iter().all(|state| {
+                        conditions.iter().all(|condition| {
+                            state
+                                .transitions
+                                .wildcard
+                                .iter()
+                                .chain(keyed(&state.transitions, condition.as_ref()))
+                                .any(|wild| matches!(*wild, Wildcard::Any(..)))
+                        })
+                    })
+                },
+                |hull| self.is_total(&hull),
+            );
+            if !is_total {
+                return Err(IllFormed::NeedsAlphabet);
+            }
+        }
+        for state in &mut total.states {
+            state.accepting = !state.accepting;
+        }
+        Ok(total)
+    }
+
+    /// Smallest range containing every token any transition names, or `None` if none name any.
+    #[inline]
+    fn mentioned(&self) -> Option<Range<A>> {
+        let mut ranges = self
+            .states
+            .iter()
+            .flat_map(|state| state.transitions.values())
+            .filter_map(|wild| match *wild {
+                Wildcard::Any(..) => None,
+                Wildcard::Specific(ref v) => Some(v.iter().map(|&(ref range, _)| range)),
+            })
+            .flatten();
+        let first = ranges.next()?;
+        Some(ranges.fold(first.clone(), |hull, range| Range {
+            first: hull.first.min(range.first.clone()),
+            last: hull.last.max(range.last.clone()),
+        }))
+    }
+}
+
+impl<A: 'static + fmt::Debug + Alphabet, S: 'static + fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Same as `complete`, over the universe the token type declares.
+    #[inline]
+    pub fn complete_declared(&mut self) -> bool {
+        self.complete(&A::universe())
+    }
+
+    /// Same as `complement`, over the universe the token type declares, so it can't fail.
+    #[inline]
+    #[must_use]
+    pub fn complement_declared(&self) -> Self {
+        let mut total = self.clone();
+        let _: bool = total.complete_declared();
+        for state in &mut total.states {
+            state.accepting = !state.accepting;
+        }
+        total
+    }
+}
+
+/// Every stack condition a run could see: an empty stack or any symbol this automaton could push.
+#[inline]
+fn conditions<
+    A: 'static + fmt::Debug + Clone + Discrete + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+>(
+    automaton: &Deterministic<A, S>,
+) -> Vec<Option<S>> {
+    once(None)
+        .chain(pushes(automaton).into_iter().map(Some))
+        .collect()
+}
+
+/// Tokens in `universe` with no transition under this stack condition.
+#[inline]
+#[allow(clippy::type_complexity)]
+fn gaps<A: 'static + fmt::Debug + Clone + Discrete + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    transitions: &CurryOpt<S, Wildcard<A, Return<Edge<A, S, usize, (), ()>>>>,
+    condition: Option<&S>,
+    universe: &Range<A>,
+) -> Vec<Range<A>> {
+    let mut covered = vec![];
+    for wild in transitions
+        .wildcard
+        .iter()
+        .chain(keyed(transitions, condition))
+    {
+        match *wild {
+            Wildcard::Any(..) => return vec![],
+            Wildcard::Specific(ref v) => {
+                covered.extend(v.iter().map(|&(ref range, _)| range.clone()));
+            }
+        }
+    }
+    covered.sort_unstable();
+    complement_ranges(&covered, universe)
+}
+
+/// Transitions keyed on this exact stack condition.
+#[inline]
+#[allow(clippy::type_complexity)]
+fn keyed<'t, A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    transitions: &'t CurryOpt<S, Wildcard<A, Return<Edge<A, S, usize, (), ()>>>>,
+    condition: Option<&S>,
+) -> Option<&'t Wildcard<A, Return<Edge<A, S, usize, (), ()>>>> {
+    condition.map_or(transitions.none.as_ref(), |s| transitions.some.get(s))
+}
+
+/// Edge to the trap state that leaves the stack alone.
+#[inline]
+fn to_trap<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    trap: usize,
+) -> Return<Edge<A, S, usize, (), ()>> {
+    Return(Edge::Local {
+        dst: trap,
+        call: call!(|x| x),
+    })
+}
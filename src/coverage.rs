@@ -0,0 +1,68 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Which transitions a corpus of inputs actually exercises.
+
+use crate::{Deterministic, Edge, Execute as _, Execution, IllFormed, Lookup as _, Wildcard};
+use core::{fmt, mem::replace};
+use std::collections::BTreeSet;
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Every `(state, edge)` pair a run could take, to compare against those `Execution::cover` records.
+    #[inline]
+    #[must_use]
+    pub fn edges(&self) -> BTreeSet<(usize, Edge<A, S, usize>)> {
+        self.states
+            .iter()
+            .enumerate()
+            .flat_map(|(i, state)| {
+                state
+                    .transitions
+                    .values()
+                    .flat_map(Wildcard::values)
+                    .map(move |edge| (i, edge.clone()))
+            })
+            .collect()
+    }
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Iter: Iterator<Item = A>>
+    Execution<'_, A, S, Deterministic<A, S>, Iter>
+{
+    /// Run to completion, adding every `(state, edge)` pair taken along the way to `covered`,
+    /// and return whether the input was accepted.
+    /// Share one set across a whole corpus, then anything in `edges()` but not in it was never exercised.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn cover(
+        mut self,
+        covered: &mut BTreeSet<(usize, Edge<A, S, usize>)>,
+    ) -> Result<bool, IllFormed<A, S, usize>> {
+        let Ok(mut ctrl) = replace(&mut self.ctrl, Err(false)) else {
+            return Ok(false);
+        };
+        let max_stack_depth = self.max_stack_depth();
+        while let Some(token) = self.read_token() {
+            if let Some(edge) = get!(self.graph.states, ctrl)
+                .transitions
+                .get((self.stack.last(), (&token, ())))
+            {
+                let _: bool = covered.insert((ctrl, edge.clone()));
+            }
+            match self.graph.step_det(ctrl, &mut self.stack, Some(&token))? {
+                Ok(next) => ctrl = next,
+                Err(_) => return Ok(false),
+            }
+            if let Some(max) = max_stack_depth {
+                if self.stack.len() > max {
+                    return Err(IllFormed::StackOverflow(max));
+                }
+            }
+        }
+        Ok(self.graph.would_accept(&ctrl, &self.stack))
+    }
+}
@@ -11,7 +11,7 @@
 //! I don't want to impose a `Clone` bound on a type that never actually needs to be cloned
 //! just because an interpreter would be easier to write if it were `Clone`.
 
-use crate::{Edge, IllFormed, Indices, Lookup, Merge, Return, Wildcard};
+use crate::{Discrete, Edge, IllFormed, Indices, Lookup, Merge, Return, Wildcard};
 use core::{fmt, iter::*, num::NonZeroUsize, option};
 use std::collections::{
     btree_map::{IntoIter, Iter},
@@ -69,7 +69,7 @@ where
     fn get(&self, key: Self::Key<'_>) -> Option<&Self::Value> {
         let (head, tail) = key;
         if let woohoo @ Some(_) = self.wildcard.as_ref().and_then(|etc| etc.get(tail)) {
-            #[cfg(any(test, debug_assertions))]
+            #[cfg(any(test, debug_assertions, feature = "checked"))]
             {
                 assert_eq!(
                     self.get_if_no_wildcard(head, tail),
@@ -83,6 +83,22 @@ where
         }
     }
     #[inline]
+    fn get_mut(&mut self, key: Self::Key<'_>) -> Option<&mut Self::Value> {
+        let (head, tail) = key;
+        if self
+            .wildcard
+            .as_ref()
+            .and_then(|etc| etc.get(tail))
+            .is_some()
+        {
+            return self.wildcard.as_mut()?.get_mut(tail);
+        }
+        match head {
+            None => self.none.as_mut()?.get_mut(tail),
+            Some(some) => self.some.get_mut(some)?.get_mut(tail),
+        }
+    }
+    #[inline]
     fn map_values<F: FnMut(&mut Self::Value)>(&mut self, mut f: F) {
         if let Some(ref mut wild) = self.wildcard {
             wild.map_values(&mut f);
@@ -96,26 +112,58 @@ where
     }
 }
 
+impl<Arg: Ord, A: 'static + fmt::Debug + Ord, Etc: Lookup> CurryOpt<Arg, Wildcard<A, Etc>> {
+    /// Same as `get`, but where ranges overlap, the first one wins (see `LookupPolicy::FirstMatch`).
+    #[inline]
+    pub fn first_match(&self, head: Option<&Arg>, token: &A) -> Option<&Etc> {
+        self.wildcard
+            .as_ref()
+            .and_then(|wild| wild.first_match(token))
+            .or_else(|| {
+                head.map_or(self.none.as_ref(), |some| self.some.get(some))?
+                    .first_match(token)
+            })
+    }
+}
+
 impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
-        Ctrl: Indices<A, S>,
-    > Merge<A, S, Ctrl> for CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl>>>>
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>>
 {
     #[inline]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
+        // Only overlaps the merge introduces are conflicts, so keep the left side around to compare if it might matter
+        let left = (self.wildcard.is_some() || other.wildcard.is_some()).then(|| self.clone());
         let wildcard = self.wildcard.merge(&other.wildcard)?;
         let none = self.none.merge(&other.none)?;
         let some = self.some.merge(&other.some)?;
+        // Whether one side already had this exact wildcard and these transitions under this condition
+        let had = |side: &Self, condition: Option<S>, curry| {
+            side.wildcard == wildcard
+                && condition.map_or(side.none.as_ref(), |arg| side.some.get(&arg)) == Some(curry)
+        };
+        // `get` tries the wildcard first, so anything else on the same tokens would be shadowed
         if let Some(ref wild) = wildcard {
-            if let Some(ref curry) = none {
-                if let Some(overlap) = wild.disjoint(curry) {
-                    return Err(IllFormed::CurryOptMergeConflict(None, overlap));
+            for (condition, curry) in none
+                .iter()
+                .map(|curry| (None, curry))
+                .chain(some.iter().map(|(arg, curry)| (Some(*arg), curry)))
+            {
+                if left
+                    .as_ref()
+                    .is_some_and(|side| had(side, condition, curry))
+                    || had(other, condition, curry)
+                {
+                    continue;
                 }
-            }
-            for (arg, curry) in &some {
-                if let Some(overlap) = wild.disjoint(curry) {
-                    return Err(IllFormed::CurryOptMergeConflict(Some(*arg), overlap));
+                if let Some((tokens, lhs, rhs)) = wild.overlapping_edges(curry) {
+                    // Running on these tokens would take both edges at once, so report the same conflict that would
+                    drop(lhs.clone().merge(rhs)?);
+                    return Err(IllFormed::CurryOptMergeConflict(condition, tokens));
                 }
             }
         }
@@ -173,6 +221,22 @@ impl<'a, Arg: Ord, Etc: Lookup> IntoIterator for &'a CurryOpt<Arg, Etc> {
 }
 
 impl<Arg: Ord, Etc: Lookup> CurryOpt<Arg, Etc> {
+    /// Number of entries, counting the wildcard and the empty-stack entry (if present) alongside every specific one.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        usize::from(self.wildcard.is_some())
+            .saturating_add(usize::from(self.none.is_some()))
+            .saturating_add(self.some.len())
+    }
+
+    /// Whether there are no entries at all.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.wildcard.is_none() && self.none.is_none() && self.some.is_empty()
+    }
+
     /// Iterate over keys only, ignoring values.
     #[inline]
     pub fn keys_without_wildcard(&self) -> impl Iterator<Item = Option<&Arg>> {
@@ -214,23 +278,25 @@ impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
         Ctrl: Indices<A, S>,
-    > CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl>>>>
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>>
 {
     /// Check for structural errors.
     /// # Errors
     /// If this automaton is not well-formed.
     #[inline]
-    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl>> {
+    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
         if let Some(overlap) = self
             .wildcard
             .as_ref()
-            .and_then(|wc| self.none.as_ref().and_then(|none| wc.disjoint(none)))
+            .and_then(|wc| self.none.as_ref().and_then(|none| wc.overlap(none)))
         {
             return Err(IllFormed::CurryOptMergeConflict(None, overlap));
         }
         if let Some((key, overlap)) = self.wildcard.as_ref().and_then(|wc| {
             self.some.iter().fold(None, |acc, (k, v)| {
-                acc.or_else(|| wc.disjoint(v).map(|x| (k, x)))
+                acc.or_else(|| wc.overlap(v).map(|x| (k, x)))
             })
         }) {
             return Err(IllFormed::CurryOptMergeConflict(Some(*key), overlap));
@@ -240,47 +306,67 @@ impl<
         self.some.iter().try_fold((), |(), (_, x)| x.check(size))
     }
 
-    /// Eliminate absurd relations like transitions to non-existing states.
-    /// # Panics
-    /// TODO
+    /// Keep only edges for which `f` returns `true`, dropping any branch left without edges.
     #[inline]
-    #[allow(clippy::never_loop)]
-    pub fn deabsurdify(&mut self, size: NonZeroUsize) {
-        if let Some(ref mut none) = self.none {
-            none.deabsurdify(Some(size));
-        }
+    pub fn retain_edges<F: FnMut(&mut Edge<A, S, Ctrl, In, Out>) -> bool>(&mut self, mut f: F) {
         if let Some(ref mut wild) = self.wildcard {
-            wild.deabsurdify(Some(size));
-            'dont_delete_none: loop {
-                'delete_none: loop {
-                    if let Some(ref mut none) = self.none {
-                        while let Some(overlap) = wild.disjoint(none) {
-                            match overlap {
-                                Some(ref not_wild) => none.remove(not_wild),
-                                None => break 'delete_none,
-                            }
-                        }
-                    }
-                    break 'dont_delete_none;
-                }
+            if !wild.retain_edges(&mut f) {
+                self.wildcard = None;
+            }
+        }
+        if let Some(ref mut none) = self.none {
+            if !none.retain_edges(&mut f) {
                 self.none = None;
-                break 'dont_delete_none;
             }
         }
-        for etc in self.some.values_mut() {
+        self.some.retain(|_, etc| etc.retain_edges(&mut f));
+    }
+
+    /// Eliminate absurd relations like transitions to non-existing states,
+    /// and remove whatever the wildcard shadows from every other entry (since `get` tries the wildcard first).
+    /// # Errors
+    /// If the wildcard shadows only part of an entry that matches any token at all,
+    /// which can't lose just those tokens without a universe to carve them out of.
+    /// That entry is left as it was, so the result still fails `check`.
+    #[inline]
+    pub fn deabsurdify(&mut self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl, In, Out>>
+    where
+        A: Discrete,
+        Ctrl: Merge<A, S, Ctrl, In, Out>,
+    {
+        for etc in self
+            .wildcard
+            .iter_mut()
+            .chain(self.none.iter_mut())
+            .chain(self.some.values_mut())
+        {
             etc.deabsurdify(Some(size));
-            while let Some(overlap) = self
-                .wildcard
-                .as_ref()
-                .and_then(|wc| wc.disjoint(etc))
-                .or_else(|| self.none.as_ref().and_then(|none| none.disjoint(etc)))
-            {
-                etc.remove(
-                    overlap
-                        .as_ref()
-                        .expect("Internal error: please open an issue on GitHub!"),
-                );
+        }
+        let Some(ref wild) = self.wildcard else {
+            return Ok(());
+        };
+        let mut result = Ok(());
+        for (condition, etc) in self
+            .none
+            .iter_mut()
+            .map(|etc| (None, etc))
+            .chain(self.some.iter_mut().map(|(arg, etc)| (Some(*arg), etc)))
+        {
+            if !etc.subtract(wild) && result.is_ok() {
+                result = Err(IllFormed::CurryOptMergeConflict(
+                    condition,
+                    wild.overlap(etc).flatten(),
+                ));
             }
         }
+        if self
+            .none
+            .as_ref()
+            .is_some_and(|none| none.values().next().is_none())
+        {
+            self.none = None;
+        }
+        self.some.retain(|_, etc| etc.values().next().is_some());
+        result
     }
 }
@@ -0,0 +1,144 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Graphviz export.
+
+use crate::{Automaton, Edge, Indices, Return, Wildcard};
+use core::{fmt, mem::replace};
+use std::collections::BTreeMap;
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>
+    Automaton<A, S, Ctrl>
+{
+    /// Graphviz source for this automaton, with states grouped into clusters by their minimum reachable stack depth
+    /// (see `min_stack_depths`) and unreachable states left outside any cluster.
+    /// Accepting states are drawn with a double circle.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn to_dot_clustered(&self) -> String {
+        let mut clusters: BTreeMap<Option<usize>, Vec<usize>> = BTreeMap::new();
+        for (i, depth) in self.min_stack_depths().into_iter().enumerate() {
+            clusters.entry(depth).or_default().push(i);
+        }
+        let mut lines = vec!["digraph {".to_owned()];
+        for (depth, states) in clusters {
+            let node = |i: &usize| {
+                format!(
+                    "{i} [shape={}];",
+                    if get!(self.states, *i).accepting {
+                        "doublecircle"
+                    } else {
+                        "circle"
+                    },
+                )
+            };
+            if let Some(d) = depth {
+                lines.push(format!("  subgraph cluster_depth_{d} {{"));
+                lines.push(format!("    label=\"stack depth {d}\";"));
+                lines.extend(states.iter().map(|i| format!("    {}", node(i))));
+                lines.push("  }".to_owned());
+            } else {
+                lines.extend(states.iter().map(|i| format!("  {}", node(i))));
+            }
+        }
+        lines.push("  start [shape=point];".to_owned());
+        lines.extend(self.initial.iter().map(|i| format!("  start -> {i};")));
+        for (i, state) in self.states.iter().enumerate() {
+            let keyed = state
+                .transitions
+                .wildcard
+                .iter()
+                .map(|wild| (String::new(), wild))
+                .chain(
+                    state
+                        .transitions
+                        .none
+                        .iter()
+                        .map(|wild| (" when empty".to_owned(), wild)),
+                )
+                .chain(
+                    state
+                        .transitions
+                        .some
+                        .iter()
+                        .map(|(s, wild)| (format!(" when top {s:?}"), wild)),
+                );
+            for (condition, wild) in keyed {
+                let edges = match *wild {
+                    Wildcard::Any(Return(ref edge)) => vec![("*".to_owned(), edge)],
+                    Wildcard::Specific(ref v) => v
+                        .iter()
+                        .map(|&(ref range, Return(ref edge))| {
+                            let tokens = if range.first == range.last {
+                                format!("{:?}", range.first)
+                            } else {
+                                format!("{:?}..{:?}", range.first, range.last)
+                            };
+                            (tokens, edge)
+                        })
+                        .collect(),
+                };
+                for (tokens, edge) in edges {
+                    let action = match *edge {
+                        Edge::Call { push, .. } => format!("push {push:?}"),
+                        Edge::Return { .. } => "pop".to_owned(),
+                        Edge::Local { .. } => "local".to_owned(),
+                        Edge::Epsilon { .. } => "epsilon".to_owned(),
+                        Edge::Phantom(..) => never!(),
+                    };
+                    let label = format!("{tokens}{condition} {action}")
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"");
+                    lines.extend(
+                        edge.dst()
+                            .iter()
+                            .map(|j| format!("  {i} -> {j} [label=\"{label}\"];")),
+                    );
+                }
+            }
+        }
+        lines.push("}".to_owned());
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Smallest stack depth with which each state is reachable, or `None` if it isn't found to be reachable.
+    /// Like `find_unmatched_returns`, this tracks only how deep the stack is, not what's on it,
+    /// and to stay finite, it never looks deeper than the number of states.
+    #[inline]
+    #[must_use]
+    pub fn min_stack_depths(&self) -> Vec<Option<usize>> {
+        let size = self.states.len();
+        let mut seen = vec![vec![false; size.saturating_add(1)]; size];
+        let mut frontier: Vec<(usize, usize)> = self.initial.iter().map(|i| (i, 0)).collect();
+        while let Some((i, depth)) = frontier.pop() {
+            if depth > size || replace(get_mut!(get_mut!(seen, i), depth), true) {
+                continue;
+            }
+            let transitions = &get!(self.states, i).transitions;
+            let wilds = transitions.wildcard.iter().chain(if depth == 0 {
+                transitions.none.iter().collect::<Vec<_>>()
+            } else {
+                transitions.some.values().collect()
+            });
+            for edge in wilds.flat_map(Wildcard::values) {
+                let next = match *edge {
+                    Edge::Call { .. } => depth.checked_add(1),
+                    Edge::Return { .. } => depth.checked_sub(1),
+                    Edge::Local { .. } | Edge::Epsilon { .. } => Some(depth),
+                    Edge::Phantom(..) => never!(),
+                };
+                if let Some(d) = next {
+                    frontier.extend(edge.dst().iter().map(|j| (j, d)));
+                }
+            }
+        }
+        seen.into_iter()
+            .map(|depths| depths.into_iter().position(|b| b))
+            .collect()
+    }
+}
@@ -6,19 +6,28 @@
 
 //! Edge in a visibly pushdown automaton (everything except the source state and the token that triggers it).
 
-use crate::{Call, IllFormed, Indices, Merge};
+use crate::{Call, IllFormed, Indices, Merge, Stack};
 use core::{convert::Infallible, fmt, marker::PhantomData, num::NonZeroUsize};
 
+#[cfg(any(test, debug_assertions, feature = "checked"))]
+use core::mem::discriminant;
+
 /// Edge in a visibly pushdown automaton (everything except the source state and the token that triggers it).
 #[allow(clippy::exhaustive_enums)]
 #[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub enum Edge<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> {
+pub enum Edge<
+    A: fmt::Debug + Ord,
+    S: fmt::Debug + Copy + Ord,
+    Ctrl: Indices<A, S>,
+    In = (),
+    Out = (),
+> {
     /// Transition that causes a stack push.
     Call {
         /// Index of the machine's state after this transition.
         dst: Ctrl,
         /// Function to call when compiled to a source file.
-        call: Call<(), ()>,
+        call: Call<In, Out>,
         /// Symbol to push onto the stack.
         push: S,
     },
@@ -27,21 +36,34 @@ pub enum Edge<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A,
         /// Index of the machine's state after this transition.
         dst: Ctrl,
         /// Function to call when compiled to a source file.
-        call: Call<(), ()>,
+        call: Call<In, Out>,
     },
     /// Transition that causes neither a stack push nor a stack pop.
     Local {
         /// Index of the machine's state after this transition.
         dst: Ctrl,
         /// Function to call when compiled to a source file.
-        call: Call<(), ()>,
+        call: Call<In, Out>,
+    },
+    /// Transition that reads no input and touches neither the stack nor any call.
+    /// Only meaningful in nondeterministic automata, and the key it's filed under is irrelevant:
+    /// eliminate these with `Nondeterministic::eliminate_epsilon` before running the automaton
+    /// (`determinize` does this automatically).
+    Epsilon {
+        /// Index of the machine's state after this transition.
+        dst: Ctrl,
     },
     /// Bullshit uninhabited state to typecheck the `<A>` parameter.
     Phantom(Infallible, PhantomData<A>),
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices<A, S>> fmt::Debug
-    for Edge<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: fmt::Debug + Indices<A, S>,
+        In,
+        Out,
+    > fmt::Debug for Edge<A, S, Ctrl, In, Out>
 {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -69,17 +91,32 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices
                     dst.iter().collect::<Vec<_>>(),
                 )
             }
+            Self::Epsilon { ref dst } => {
+                write!(
+                    f,
+                    "Edge::Epsilon {{ dst: {:?}.into_iter().collect() }}",
+                    dst.iter().collect::<Vec<_>>(),
+                )
+            }
             Self::Phantom(..) => never!(),
         }
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge<A, S, Ctrl>
-    for Edge<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for Edge<A, S, Ctrl, In, Out>
 {
     #[inline]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
-        match (self, other) {
+    #[allow(clippy::panic_in_result_fn)]
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
+        #[cfg(any(test, debug_assertions, feature = "checked"))]
+        let variant = discriminant(&self);
+        let merged = match (self, other) {
             (
                 Self::Call {
                     dst: ldst,
@@ -122,23 +159,45 @@ impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S
                     dst: ref rdst,
                     call: ref rcall,
                 },
-            ) => Ok(Self::Return {
+            ) => Ok(Self::Local {
                 dst: ldst.merge(rdst)?,
                 call: lcall.merge(rcall)?,
             }),
-            (lhs, rhs) => Err(IllFormed::EdgeMergeConflict(lhs, rhs.clone())),
+            (Self::Epsilon { dst: ldst }, &Self::Epsilon { dst: ref rdst }) => Ok(Self::Epsilon {
+                dst: ldst.merge(rdst)?,
+            }),
+            (lhs, rhs) => return Err(IllFormed::EdgeMergeConflict(lhs, rhs.clone())),
+        };
+        #[cfg(any(test, debug_assertions, feature = "checked"))]
+        {
+            if let Ok(ref edge) = merged {
+                assert_eq!(
+                    discriminant(edge),
+                    variant,
+                    "Merging two edges of the same kind produced a different kind: {edge:?}",
+                );
+            }
         }
+        merged
     }
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Edge<A, S, Ctrl> {
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static,
+        Out: 'static,
+    > Edge<A, S, Ctrl, In, Out>
+{
     /// Index of the machine's state after this transition.
     #[inline]
     pub const fn dst(&self) -> &Ctrl {
         match *self {
             Self::Call { ref dst, .. }
             | Self::Return { ref dst, .. }
-            | Self::Local { ref dst, .. } => dst,
+            | Self::Local { ref dst, .. }
+            | Self::Epsilon { ref dst } => dst,
             Self::Phantom(..) => never!(),
         }
     }
@@ -149,15 +208,41 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Edge<
         match *self {
             Self::Call { ref mut dst, .. }
             | Self::Return { ref mut dst, .. }
-            | Self::Local { ref mut dst, .. } => dst,
+            | Self::Local { ref mut dst, .. }
+            | Self::Epsilon { ref mut dst } => dst,
             Self::Phantom(..) => never!(),
         }
     }
+
+    /// Function to call when compiled to a source file, if this edge has one.
+    #[inline]
+    pub const fn call(&self) -> Option<&Call<In, Out>> {
+        match *self {
+            Self::Call { ref call, .. }
+            | Self::Return { ref call, .. }
+            | Self::Local { ref call, .. } => Some(call),
+            Self::Epsilon { .. } => None,
+            Self::Phantom(..) => never!(),
+        }
+    }
+
+    /// Function to call when compiled to a source file, if this edge has one.
+    #[inline]
+    pub fn call_mut(&mut self) -> Option<&mut Call<In, Out>> {
+        match *self {
+            Self::Call { ref mut call, .. }
+            | Self::Return { ref mut call, .. }
+            | Self::Local { ref mut call, .. } => Some(call),
+            Self::Epsilon { .. } => None,
+            Self::Phantom(..) => never!(),
+        }
+    }
+
     /// Take this edge in an actual execution. Return the index of the machine's state after this transition.
     /// # Errors
     /// If we try to pop the stack and it's empty.
     #[inline]
-    pub fn invoke(self, stack: &mut Vec<S>) -> Result<Ctrl, bool> {
+    pub fn invoke<St: Stack<S>>(self, stack: &mut St) -> Result<Ctrl, bool> {
         match self {
             Self::Call {
                 dst,
@@ -169,6 +254,7 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Edge<
             }
             Self::Return { dst, call: _call } => stack.pop().map_or(Err(false), |_| Ok(dst)),
             Self::Local { dst, call: _call } => Ok(dst),
+            Self::Epsilon { dst } => Ok(dst),
             Self::Phantom(..) => never!(),
         }
     }
@@ -177,8 +263,8 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Edge<
     /// # Errors
     /// If this automaton is not well-formed.
     #[inline]
-    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl>> {
-        if self.dst().iter().all(|&i| i < size.into()) {
+    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
+        if self.dst().iter().all(|i| i < size.into()) {
             Ok(())
         } else {
             Err(IllFormed::OutOfBounds)
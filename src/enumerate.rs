@@ -0,0 +1,75 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Listing the inputs an automaton accepts, shortest first.
+
+use crate::{Deterministic, Execute as _, Wildcard};
+use core::{fmt, iter::from_fn};
+use std::collections::{BTreeSet, VecDeque};
+
+impl<A: fmt::Debug + Clone + Default + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Every accepted input of at most `max_len` tokens, shortest first (and in token order within each length),
+    /// using one representative token per range (its first) and `A::default()` for anything matched by `Wildcard::Any`.
+    ///
+    /// This is a breadth-first search over inputs, not states, so it can take exponential time:
+    /// it's meant for small automata, e.g. in tests and documentation.
+    /// Ill-formed transitions count as rejection.
+    /// ```rust
+    /// use vpa::*;
+    /// let parens = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'))
+    ///     .determinize()
+    ///     .unwrap();
+    /// let words: Vec<String> = parens.enumerate(4).map(|w| w.into_iter().collect()).collect();
+    /// assert_eq!(words, ["", "()", "(())", "()()"]);
+    /// ```
+    #[inline]
+    pub fn enumerate(&self, max_len: usize) -> impl Iterator<Item = Vec<A>> + '_ {
+        let tokens = self.samples();
+        let mut queue = VecDeque::from([(self.initial, vec![], vec![])]);
+        from_fn(move || {
+            while let Some((ctrl, stack, input)) = queue.pop_front() {
+                let remaining = max_len.saturating_sub(input.len());
+                if remaining > 0 {
+                    for token in &tokens {
+                        let mut next_stack = stack.clone();
+                        let Ok(Ok(next)) = self.step_det(ctrl, &mut next_stack, Some(token)) else {
+                            continue;
+                        };
+                        // Skip stacks too deep to empty in the tokens left
+                        if next_stack.len() < remaining {
+                            let mut extended = input.clone();
+                            extended.push(token.clone());
+                            queue.push_back((next, next_stack, extended));
+                        }
+                    }
+                }
+                if self.would_accept(&ctrl, &stack) {
+                    return Some(input);
+                }
+            }
+            None
+        })
+    }
+
+    /// One token for each range this automaton matches, plus `A::default()` if anything matches every token.
+    #[inline]
+    fn samples(&self) -> BTreeSet<A> {
+        let mut tokens = BTreeSet::new();
+        for state in &self.states {
+            for wild in state.transitions.values() {
+                match *wild {
+                    Wildcard::Any(_) => {
+                        let _ = tokens.insert(A::default());
+                    }
+                    Wildcard::Specific(ref v) => {
+                        tokens.extend(v.iter().map(|&(ref range, _)| range.first.clone()));
+                    }
+                }
+            }
+        }
+        tokens
+    }
+}
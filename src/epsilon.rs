@@ -0,0 +1,95 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Epsilon (empty-input) transitions and their elimination.
+
+use crate::{merge, Automaton, Edge, IllFormed, Indices, Merge, State, Wildcard};
+use core::fmt;
+use std::collections::BTreeSet;
+
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Automaton<A, S, Ctrl, In, Out>
+{
+    /// Check whether any state has an epsilon transition.
+    #[inline]
+    #[must_use]
+    pub fn has_epsilon(&self) -> bool {
+        self.states.iter().any(|state| {
+            state
+                .transitions
+                .values()
+                .flat_map(Wildcard::values)
+                .any(|edge| matches!(*edge, Edge::Epsilon { .. }))
+        })
+    }
+
+    /// Every state reachable from this one by epsilon transitions alone (including itself),
+    /// or `None` if this index (or any epsilon transition on the way) is out of bounds.
+    #[inline]
+    #[must_use]
+    pub fn epsilon_closure(&self, i: usize) -> Option<BTreeSet<usize>> {
+        let mut closure = BTreeSet::new();
+        let mut frontier = vec![i];
+        while let Some(j) = frontier.pop() {
+            if closure.insert(j) {
+                frontier.extend(
+                    self.states
+                        .get(j)?
+                        .transitions
+                        .values()
+                        .flat_map(Wildcard::values)
+                        .filter_map(|edge| {
+                            if let Edge::Epsilon { ref dst } = *edge {
+                                Some(dst.iter())
+                            } else {
+                                None
+                            }
+                        })
+                        .flatten(),
+                );
+            }
+        }
+        Some(closure)
+    }
+
+    /// Rewrite this automaton into an equivalent one without epsilon transitions
+    /// by merging each state with every state in its epsilon closure.
+    /// # Errors
+    /// If this automaton is ill-formed,
+    /// or if some state can't be merged with its epsilon closure (e.g. two different edges on the same token).
+    #[inline]
+    #[allow(clippy::missing_panics_doc, clippy::unwrap_in_result)]
+    pub fn eliminate_epsilon(&mut self) -> Result<(), IllFormed<A, S, Ctrl, In, Out>>
+    where
+        Ctrl: Merge<A, S, Ctrl, In, Out>,
+    {
+        // Closures and merges below index states without bounds checks
+        self.check()?;
+        let closures: Vec<BTreeSet<usize>> = (0..self.states.len())
+            .map(|i| unwrap!(self.epsilon_closure(i)))
+            .collect();
+        let mut stripped = self.states.clone();
+        for state in &mut stripped {
+            state
+                .transitions
+                .retain_edges(|edge| !matches!(*edge, Edge::Epsilon { .. }));
+        }
+        self.states = closures
+            .iter()
+            .map(|closure| {
+                unwrap!(merge::<_, _, _, _, _, State<A, S, Ctrl, In, Out>, _>(
+                    closure.iter().map(|&i| get!(stripped, i))
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@
 //! Execution of a visibly pushdown automaton on an input sequence.
 
 use crate::{Edge, Indices, Range, Return, Wildcard};
-use core::{fmt, mem::replace};
+use core::{cmp::Ordering, fmt, marker::PhantomData, mem::replace};
 
 /// Any executable automaton.
 pub trait Execute<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> {
@@ -26,30 +26,128 @@ pub trait Execute<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> {
         stack: &mut Vec<S>,
         maybe_token: Option<&A>,
     ) -> Result<Result<Self::Ctrl, bool>, IllFormed<A, S, Self::Ctrl>>;
+    /// Whether this configuration would be accepted if input ended right now.
+    #[must_use]
+    fn would_accept(&self, ctrl: &Self::Ctrl, stack: &[S]) -> bool;
+    /// Same as `step`, but on any kind of `Stack`.
+    /// The default hands `step` a `Vec` holding only the top symbol, then pushes or pops to match,
+    /// which is exact as long as `step` never looks below the top or pushes more than one symbol.
+    /// # Errors
+    /// If the automaton decides to accept or not to (check the Boolean).
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    fn step_in<St: Stack<S>>(
+        &self,
+        ctrl: Self::Ctrl,
+        stack: &mut St,
+        maybe_token: Option<&A>,
+    ) -> Result<Result<Self::Ctrl, bool>, IllFormed<A, S, Self::Ctrl>>
+    where
+        Self: Sized,
+    {
+        let mut top: Vec<S> = stack.last().copied().into_iter().collect();
+        let before = top.len();
+        let rtn = self.step(ctrl, &mut top, maybe_token)?;
+        match top.len().cmp(&before) {
+            Ordering::Greater => stack.push(*unwrap!(top.last())),
+            Ordering::Less => drop(stack.pop()),
+            Ordering::Equal => {}
+        }
+        Ok(rtn)
+    }
+    /// Same as `would_accept`, but on any kind of `Stack`.
+    /// The default hands `would_accept` a slice holding only the top symbol,
+    /// which is exact as long as `would_accept` never looks below the top.
+    #[inline]
+    #[must_use]
+    fn would_accept_in<St: Stack<S>>(&self, ctrl: &Self::Ctrl, stack: &St) -> bool
+    where
+        Self: Sized,
+    {
+        let top: Vec<S> = stack.last().copied().into_iter().collect();
+        self.would_accept(ctrl, &top)
+    }
+    /// Whether no input from here on could ever be accepted, so running any further is pointless.
+    /// Only a hint: the default never gives up early.
+    #[inline(always)]
+    #[must_use]
+    fn is_dead(&self, _: &Self::Ctrl) -> bool {
+        false
+    }
+}
+
+/// Anything that can hold an automaton's stack while it runs.
+/// `Vec<S>` is the usual choice; a persistent stack makes every clone (e.g. each snapshot in a `trace`) constant-time.
+pub trait Stack<S>: Clone + Default + fmt::Debug {
+    /// Push a symbol onto the top.
+    fn push(&mut self, symbol: S);
+    /// Remove and return the top symbol, if any.
+    fn pop(&mut self) -> Option<S>;
+    /// Top symbol, if any.
+    #[must_use]
+    fn last(&self) -> Option<&S>;
+    /// Number of symbols on the stack.
+    #[must_use]
+    fn len(&self) -> usize;
+    /// Whether there's nothing on the stack.
+    #[must_use]
+    fn is_empty(&self) -> bool;
+}
+
+impl<S: Clone + fmt::Debug> Stack<S> for Vec<S> {
+    #[inline(always)]
+    fn push(&mut self, symbol: S) {
+        Self::push(self, symbol);
+    }
+    #[inline(always)]
+    fn pop(&mut self) -> Option<S> {
+        Self::pop(self)
+    }
+    #[inline(always)]
+    fn last(&self) -> Option<&S> {
+        <[S]>::last(self)
+    }
+    #[inline(always)]
+    fn len(&self) -> usize {
+        Self::len(self)
+    }
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        <[S]>::is_empty(self)
+    }
 }
 
 /// Ran an automaton that tried to take a nonsensical action.
 #[non_exhaustive]
+#[allow(clippy::type_complexity)]
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum IllFormed<
     A: 'static + fmt::Debug + Ord,
     S: 'static + fmt::Debug + Copy + Ord,
     Ctrl: Indices<A, S>,
+    In: 'static = (),
+    Out: 'static = (),
 > {
     /// Index larger than the number of states.
     OutOfBounds,
     /// Two different `usize`s trying to merge into a single `usize`.
     IndexMergeConflict(usize, usize),
-    /// Same key mapped to different outputs in two `BTreeMap`s being merged.
+    /// Overlapping ranges in one `Vec`, or different but overlapping ranges in two being merged: the range they share.
     VecMergeConflict(Range<A>),
-    /// Same key mapped to different outputs in two `BTreeMap`s being merged.
+    /// Overlapping ranges in two `Vec`s being merged, mapped to edges that can't merge: the range they share and both edges.
+    RangeMergeConflict(
+        Range<A>,
+        Edge<A, S, Ctrl, In, Out>,
+        Edge<A, S, Ctrl, In, Out>,
+    ),
+    /// Same key mapped to outputs that can't merge in two `BTreeMap`s being merged.
     MapMergeConflict(
         S,
-        Wildcard<A, Return<Edge<A, S, Ctrl>>>,
-        Wildcard<A, Return<Edge<A, S, Ctrl>>>,
+        Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>,
+        Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>,
     ),
     /// Merging two edges of different kinds (e.g. one pushes and one pops).
-    EdgeMergeConflict(Edge<A, S, Ctrl>, Edge<A, S, Ctrl>),
+    EdgeMergeConflict(Edge<A, S, Ctrl, In, Out>, Edge<A, S, Ctrl, In, Out>),
     /// Merging two curries into one in which a specific value is overwritten by a wildcard with a different output.
     CurryOptMergeConflict(Option<S>, Option<Range<A>>),
     /// Merging two wildcards into one in which a specific value is overwritten by a wildcard with a different output.
@@ -58,10 +156,36 @@ pub enum IllFormed<
     CallMergeConflict(String, String),
     /// Merging two incompatible stack symbols.
     PushMergeConflict(S, S),
+    /// Tried to take an epsilon transition at runtime instead of eliminating it beforehand.
+    Epsilon,
+    /// Tried to relabel states with something other than a permutation of their indices.
+    NotAPermutation,
+    /// Tried to reverse an automaton whose reversal isn't representable (or isn't deterministic, where that's required).
+    Irreversible,
+    /// On this token, a nondeterministic automaton tried to take two incompatible edges at once.
+    /// The automaton may well be well-formed; it's this particular input that exposed the ambiguity.
+    AmbiguousInput(A, Box<Self>),
+    /// The stack grew past the limit set with `Execution::with_max_stack_depth`.
+    StackOverflow(usize),
+    /// No initial state at all, so every input would be rejected: well-formed, but refused by `Automaton::new`.
+    EmptyInitialSet,
+    /// Tried to complete (or complement) an automaton that's missing transitions without knowing the alphabet.
+    NeedsAlphabet,
+    /// Transitions on any stack overlap transitions on a specific stack condition in the same state,
+    /// so lookup would silently take the former and never the latter.
+    WildcardShadowsSpecific {
+        /// Index of the state.
+        state: usize,
+        /// Which stack condition: `None` for an empty stack, or `Some(s)` for `s` on top.
+        condition: Option<S>,
+        /// Tokens both cover, or `None` for every token.
+        tokens: Option<Range<A>>,
+    },
 }
 
 /// Execution of a visibly pushdown automaton on an input sequence.
 #[allow(clippy::exhaustive_structs)]
+#[must_use = "call `.finish()` or drain the `Execution` to get a result"]
 #[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Execution<
     'a,
@@ -69,6 +193,7 @@ pub struct Execution<
     S: 'static + fmt::Debug + Copy + Ord,
     E: Execute<A, S>,
     Iter: Iterator<Item = A>,
+    St: Stack<S> = Vec<S>,
 > {
     /// Reference to the automaton we're running.
     pub graph: &'a E,
@@ -78,7 +203,11 @@ pub struct Execution<
     #[allow(clippy::type_complexity)]
     pub ctrl: Result<E::Ctrl, bool>,
     /// Current stack.
-    pub stack: Vec<S>,
+    pub stack: St,
+    /// Deepest the stack may grow before execution stops with `IllFormed::StackOverflow`, if anywhere.
+    max_stack_depth: Option<usize>,
+    /// Number of tokens read so far, so an error from the most recent call to `next` came from token `read - 1`.
+    read: usize,
 }
 
 impl<
@@ -86,7 +215,8 @@ impl<
         S: fmt::Debug + Copy + Ord,
         E: Execute<A, S>,
         Iter: Iterator<Item = A>,
-    > fmt::Debug for Execution<'_, A, S, E, Iter>
+        St: Stack<S>,
+    > fmt::Debug for Execution<'_, A, S, E, Iter, St>
 where
     E::Ctrl: fmt::Debug,
 {
@@ -105,7 +235,8 @@ impl<
         S: fmt::Debug + Copy + Ord,
         E: Execute<A, S>,
         Iter: Iterator<Item = A>,
-    > Iterator for Execution<'_, A, S, E, Iter>
+        St: Stack<S>,
+    > Iterator for Execution<'_, A, S, E, Iter, St>
 where
     E::Ctrl: fmt::Debug,
 {
@@ -113,9 +244,9 @@ where
     #[inline]
     #[allow(clippy::unwrap_in_result)]
     fn next(&mut self) -> Option<Self::Item> {
-        let maybe_token = self.iter.next();
+        let maybe_token = self.read_token();
         if self.ctrl.is_ok() {
-            self.ctrl = match self.graph.step(
+            self.ctrl = match self.graph.step_in(
                 unwrap!(replace(&mut self.ctrl, Err(false))),
                 &mut self.stack,
                 maybe_token.as_ref(),
@@ -123,7 +254,289 @@ where
                 Ok(ok) => ok,
                 Err(e) => return Some(Err(e)),
             };
+            if let Err(e) = self.check_stack_depth() {
+                self.ctrl = Err(false);
+                return Some(Err(e));
+            }
+            // Once dead, stop stepping and just drain the input
+            if self
+                .ctrl
+                .as_ref()
+                .is_ok_and(|ctrl| self.graph.is_dead(ctrl))
+            {
+                self.ctrl = Err(false);
+            }
         }
         maybe_token.map(Ok) // <-- Propagate the iterator's input
     }
 }
+
+impl<
+        'a,
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        E: Execute<A, S>,
+        Iter: Iterator<Item = A>,
+        St: Stack<S>,
+    > Execution<'a, A, S, E, Iter, St>
+{
+    /// Start running `graph` on `iter` from its initial state, on top of `stack`.
+    #[inline]
+    pub(crate) fn new(graph: &'a E, iter: Iter, stack: St) -> Self {
+        Self {
+            graph,
+            iter,
+            ctrl: Ok(graph.initial()),
+            stack,
+            max_stack_depth: None,
+            read: 0,
+        }
+    }
+
+    /// Stop with `IllFormed::StackOverflow` as soon as the stack holds more than `depth` symbols,
+    /// e.g. to keep a network-facing parser from growing its stack without bound on adversarially nested input.
+    #[inline(always)]
+    pub const fn with_max_stack_depth(mut self, depth: usize) -> Self {
+        self.max_stack_depth = Some(depth);
+        self
+    }
+
+    /// Deepest the stack may grow before execution stops with `IllFormed::StackOverflow`, if anywhere.
+    #[inline(always)]
+    #[must_use]
+    pub const fn max_stack_depth(&self) -> Option<usize> {
+        self.max_stack_depth
+    }
+
+    /// Number of tokens read so far, so an error from the most recent call to `next` came from token `read() - 1`.
+    #[inline(always)]
+    #[must_use]
+    pub const fn read(&self) -> usize {
+        self.read
+    }
+
+    /// Next input token, counting it as read.
+    #[inline]
+    pub(crate) fn read_token(&mut self) -> Option<A> {
+        let token = self.iter.next()?;
+        self.read = self.read.saturating_add(1);
+        Some(token)
+    }
+
+    /// Whether the stack is within `max_stack_depth`.
+    #[inline]
+    fn check_stack_depth(&self) -> Result<(), IllFormed<A, S, E::Ctrl>> {
+        match self.max_stack_depth {
+            Some(max) if self.stack.len() > max => Err(IllFormed::StackOverflow(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// After each token, report whether the input so far would be accepted if it ended there.
+    #[inline(always)]
+    #[must_use]
+    pub const fn verdicts(self) -> Verdicts<'a, A, S, E, Iter, St> {
+        Verdicts(self)
+    }
+
+    /// Run to completion and return whether the input was accepted.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::missing_panics_doc, clippy::unreachable)]
+    pub fn finish(mut self) -> Result<bool, IllFormed<A, S, E::Ctrl>>
+    where
+        E::Ctrl: fmt::Debug,
+    {
+        for r in &mut self {
+            drop(r?);
+        }
+        if let Err(b) = self.ctrl {
+            Ok(b)
+        } else {
+            never!()
+        }
+    }
+
+    /// Run to completion, recording a snapshot of the control flow and stack after every token.
+    /// If the input is rejected partway through, the trace stops at the last token that had somewhere to go.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn trace(mut self) -> Result<Vec<(E::Ctrl, St)>, IllFormed<A, S, E::Ctrl>>
+    where
+        E::Ctrl: fmt::Debug,
+    {
+        let mut snapshots = vec![];
+        while let Some(r) = self.next() {
+            drop(r?);
+            let Ok(ref ctrl) = self.ctrl else {
+                break;
+            };
+            snapshots.push((ctrl.clone(), self.stack.clone()));
+        }
+        Ok(snapshots)
+    }
+}
+
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        E: Execute<A, S>,
+        Iter: Iterator<Item = A>,
+    > Execution<'_, A, S, E, Iter>
+{
+    /// Run to completion, then let the caller decide whether to accept based on the final control flow and stack,
+    /// instead of the built-in `accepting` state with an empty stack.
+    /// Input rejected partway through (or an execution that had already halted) is still rejected.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn run_with<F: FnOnce(&E::Ctrl, &[S]) -> bool>(
+        mut self,
+        accept: F,
+    ) -> Result<bool, IllFormed<A, S, E::Ctrl>> {
+        let Ok(mut ctrl) = replace(&mut self.ctrl, Err(false)) else {
+            return Ok(false);
+        };
+        while let Some(token) = self.read_token() {
+            match self.graph.step_in(ctrl, &mut self.stack, Some(&token))? {
+                Ok(next) if !self.graph.is_dead(&next) => ctrl = next,
+                _ => return Ok(false),
+            }
+            self.check_stack_depth()?;
+        }
+        Ok(accept(&ctrl, &self.stack))
+    }
+}
+
+/// After each token, report whether the input so far would be accepted if it ended there.
+#[allow(clippy::exhaustive_structs)]
+pub struct Verdicts<
+    'a,
+    A: 'static + fmt::Debug + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    E: Execute<A, S>,
+    Iter: Iterator<Item = A>,
+    St: Stack<S> = Vec<S>,
+>(pub Execution<'a, A, S, E, Iter, St>);
+
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        E: Execute<A, S>,
+        Iter: Iterator<Item = A>,
+        St: Stack<S>,
+    > fmt::Debug for Verdicts<'_, A, S, E, Iter, St>
+where
+    E::Ctrl: fmt::Debug,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Verdicts({:?})", self.0)
+    }
+}
+
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        E: Execute<A, S>,
+        Iter: Iterator<Item = A>,
+        St: Stack<S>,
+    > Iterator for Verdicts<'_, A, S, E, Iter, St>
+where
+    E::Ctrl: fmt::Debug,
+{
+    type Item = Result<bool, IllFormed<A, S, E::Ctrl>>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.0.next()?.map(|_| {
+            self.0
+                .ctrl
+                .as_ref()
+                .is_ok_and(|ctrl| self.0.graph.would_accept_in(ctrl, &self.0.stack))
+        }))
+    }
+}
+
+/// Execution of a visibly pushdown automaton on tokens fed one at a time, e.g. from a callback.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PushExecution<
+    'a,
+    A: 'static + fmt::Debug + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    E: Execute<A, S>,
+> {
+    /// Reference to the automaton we're running.
+    pub graph: &'a E,
+    /// Current state in the automaton.
+    #[allow(clippy::type_complexity)]
+    pub ctrl: Result<E::Ctrl, bool>,
+    /// Current stack.
+    pub stack: Vec<S>,
+    /// Type of tokens we'll be fed.
+    pub token: PhantomData<A>,
+}
+
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, E: Execute<A, S>> fmt::Debug
+    for PushExecution<'_, A, S, E>
+where
+    E::Ctrl: fmt::Debug,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PushExecution {{ stack: {:?}, ctrl: {:?} }}",
+            self.stack, self.ctrl,
+        )
+    }
+}
+
+impl<'a, A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, E: Execute<A, S>>
+    PushExecution<'a, A, S, E>
+{
+    /// Start running this automaton without any input yet.
+    #[inline]
+    #[must_use]
+    pub fn new(graph: &'a E) -> Self {
+        Self {
+            graph,
+            ctrl: Ok(graph.initial()),
+            stack: vec![],
+            token: PhantomData,
+        }
+    }
+
+    /// Take one step on this token. Once the input has been rejected, further tokens do nothing.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::missing_panics_doc, clippy::unwrap_in_result)]
+    pub fn feed(&mut self, token: &A) -> Result<(), IllFormed<A, S, E::Ctrl>> {
+        if self.ctrl.is_ok() {
+            self.ctrl = self.graph.step_in(
+                unwrap!(replace(&mut self.ctrl, Err(false))),
+                &mut self.stack,
+                Some(token),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// End the input and return whether it was accepted.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn finish(mut self) -> Result<bool, IllFormed<A, S, E::Ctrl>> {
+        match self.ctrl {
+            Ok(ctrl) => match self.graph.step_in(ctrl, &mut self.stack, None)? {
+                Ok(_) => never!(),
+                Err(b) => Ok(b),
+            },
+            Err(b) => Ok(b),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Grapheme clusters as tokens.
+//!
+//! A single user-perceived character can span several code points (e.g. an emoji with a skin-tone modifier),
+//! so this reads it all at once instead of one `char` at a time.
+
+use crate::{Execute, Execution, Run as _};
+use core::{fmt, iter::Map};
+use unicode_segmentation::{Graphemes, UnicodeSegmentation as _};
+
+/// Run this automaton over the (extended) grapheme clusters of this text, one `String` token per cluster.
+///
+/// The automaton's tokens must be `String`s, so its ranges are ranges of grapheme strings
+/// (most usefully units, like `Range::unit("👍🏽".to_owned())`) under the usual lexicographic order.
+#[inline]
+#[allow(clippy::type_complexity)]
+pub fn grapheme_run<'t, 'g, S: fmt::Debug + Copy + Ord, E: Execute<String, S>>(
+    text: &'t str,
+    graph: &'g E,
+) -> Execution<'g, String, S, E, Map<Graphemes<'t>, fn(&str) -> String>> {
+    #[allow(trivial_casts, clippy::as_conversions)]
+    text.graphemes(true)
+        .map(str::to_owned as fn(&str) -> String)
+        .run(graph)
+}
@@ -9,7 +9,7 @@
 use crate::Merge;
 use core::{
     fmt,
-    iter::{once, Once},
+    iter::{once, Copied, Once},
 };
 use std::collections::{btree_set::Iter, BTreeSet};
 
@@ -17,13 +17,16 @@ use std::collections::{btree_set::Iter, BTreeSet};
 pub trait Indices<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord>:
     'static + fmt::Debug + Clone + Merge<A, S, Self> + PartialEq
 {
-    /// Iterator over references to elements without consuming the collection.
-    type View<'a>: Iterator<Item = &'a usize>
+    /// Iterator over elements without consuming the collection.
+    type View<'a>: Iterator<Item = usize>
     where
         Self: 'a;
-    /// Iterate over references to elements without consuming the collection.
+    /// Iterate over elements without consuming the collection.
     #[must_use]
     fn iter(&self) -> Self::View<'_>;
+    /// Whether this index is among these, without scanning every element.
+    #[must_use]
+    fn contains(&self, index: usize) -> bool;
     /// Apply a function to each index.
     fn map<F: FnMut(&mut usize)>(&mut self, f: F);
     /// Apply a function to each index, then synthesize the rest into this type again.
@@ -36,10 +39,14 @@ pub trait Indices<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord>:
 }
 
 impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Indices<A, S> for usize {
-    type View<'a> = Once<&'a usize>;
+    type View<'a> = Once<usize>;
     #[inline(always)]
     fn iter(&self) -> Self::View<'_> {
-        once(self)
+        once(*self)
+    }
+    #[inline(always)]
+    fn contains(&self, index: usize) -> bool {
+        *self == index
     }
     #[inline(always)]
     fn map<F: FnMut(&mut usize)>(&mut self, mut f: F) {
@@ -50,15 +57,15 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Indices<A, S> for usize {
         f(self)
     }
     #[inline]
+    #[allow(clippy::panic_in_result_fn)]
     fn collect<I: IntoIterator<Item = usize>>(iter: I) -> Result<Self, bool> {
         let mut i = iter.into_iter();
         let rtn = i.next().ok_or(false);
-        #[cfg(any(test, debug_assertions))]
+        #[cfg(any(test, debug_assertions, feature = "checked"))]
         {
-            let leftovers: Vec<_> = i.collect();
-            debug_assert_eq!(
-                leftovers,
-                vec![],
+            let leftovers: Vec<usize> = i.collect();
+            assert!(
+                leftovers.is_empty(),
                 "Tried to collect an iterator into a single index but there was {leftovers:?} left over"
             );
         }
@@ -67,10 +74,14 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Indices<A, S> for usize {
 }
 
 impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Indices<A, S> for BTreeSet<usize> {
-    type View<'a> = Iter<'a, usize>;
+    type View<'a> = Copied<Iter<'a, usize>>;
     #[inline(always)]
     fn iter(&self) -> Self::View<'_> {
-        self.iter()
+        self.iter().copied()
+    }
+    #[inline(always)]
+    fn contains(&self, index: usize) -> bool {
+        BTreeSet::contains(self, &index)
     }
     #[inline(always)]
     fn map<F: FnMut(&mut usize)>(&mut self, mut f: F) {
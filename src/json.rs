@@ -0,0 +1,519 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Dependency-free JSON interchange format for deterministic automata.
+//!
+//! ```text
+//! {"initial":0,"states":[{"accepting":true,"transitions":[
+//!   {"range":["(","("],"action":"push","push":"P","dst":0,"call":"|x| x"},
+//!   {"top":"P","range":[")",")"],"action":"pop","dst":0,"call":"|x| x"}]}]}
+//! ```
+//!
+//! Each transition is an object with
+//! - an optional `top`: missing for any stack, `null` for an empty stack, or a stack symbol;
+//! - a `range`: `null` for any token, or `[first, last]` (inclusive);
+//! - an `action`: `"push"` (with a `push` symbol), `"pop"`, `"local"`, or `"epsilon"`;
+//! - a destination `dst`; and
+//! - the `call`'s source (except for `epsilon`).
+//!
+//! Tokens and stack symbols are strings, written with `Display` and read with `FromStr`.
+//! Only calls' source survives: every call reads back as the identity function,
+//! so use `map_calls` with `Call::from_registry` to restore the real ones.
+
+use crate::{text::add, Call, CurryOpt, Deterministic, Edge, Range, Return, State, Wildcard};
+use core::{
+    fmt::{self, Write as _},
+    iter::Peekable,
+    str::{CharIndices, FromStr},
+};
+
+/// Failure to read the JSON format.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct JsonError {
+    /// What went wrong.
+    pub message: String,
+}
+
+/// Parsed JSON value.
+#[derive(Debug)]
+enum Json {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// Nonnegative integer (the only kind of number this format uses).
+    Number(usize),
+    /// String, already unescaped.
+    String(String),
+    /// Array.
+    Array(Vec<Json>),
+    /// Object, in order, without deduplicating keys.
+    Object(Vec<(String, Json)>),
+}
+
+impl<
+        A: 'static + fmt::Debug + fmt::Display + Clone + FromStr + Ord,
+        S: 'static + fmt::Debug + fmt::Display + Copy + FromStr + Ord,
+    > Deterministic<A, S>
+{
+    /// Read the JSON format described in this module's documentation.
+    /// # Errors
+    /// If the input isn't JSON, nests more than `MAX_DEPTH` arrays and objects deep,
+    /// doesn't describe an automaton, or describes an ill-formed one (e.g. with a transition to a state that doesn't exist).
+    #[inline]
+    pub fn from_json(json: &str) -> Result<Self, JsonError> {
+        let mut chars = json.char_indices().peekable();
+        let value = parse_value(json, &mut chars, MAX_DEPTH)?;
+        skip_whitespace(&mut chars);
+        if let Some(&(i, _)) = chars.peek() {
+            return Err(fail(&format!("trailing characters at byte {i}")));
+        }
+        let root = object(&value, "automaton")?;
+        let states = array(field(root, "states")?, "`states`")?
+            .iter()
+            .map(parse_state)
+            .collect::<Result<_, _>>()?;
+        let automaton = Self {
+            states,
+            initial: number(field(root, "initial")?, "`initial`")?,
+        };
+        automaton
+            .check()
+            .map_err(|e| fail(&format!("ill-formed automaton: {e:?}")))?;
+        Ok(automaton)
+    }
+
+    /// Write this automaton in the JSON format described in this module's documentation.
+    #[inline]
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let states: Vec<String> =
+            self.states
+                .iter()
+                .map(|state| {
+                    let keyed = state
+                        .transitions
+                        .wildcard
+                        .iter()
+                        .map(|wild| (String::new(), wild))
+                        .chain(
+                            state
+                                .transitions
+                                .none
+                                .iter()
+                                .map(|wild| ("\"top\":null,".to_owned(), wild)),
+                        )
+                        .chain(state.transitions.some.iter().map(|(s, wild)| {
+                            (format!("\"top\":{},", quote(&s.to_string())), wild)
+                        }));
+                    let mut transitions = vec![];
+                    for (top, wild) in keyed {
+                        match *wild {
+                            Wildcard::Any(Return(ref edge)) => {
+                                transitions.push(transition_json(&top, "null", edge));
+                            }
+                            Wildcard::Specific(ref v) => {
+                                for &(ref range, Return(ref edge)) in v {
+                                    let tokens = format!(
+                                        "[{},{}]",
+                                        quote(&range.first.to_string()),
+                                        quote(&range.last.to_string()),
+                                    );
+                                    transitions.push(transition_json(&top, &tokens, edge));
+                                }
+                            }
+                        }
+                    }
+                    format!(
+                        "{{\"accepting\":{},\"transitions\":[{}]}}",
+                        state.accepting,
+                        transitions.join(","),
+                    )
+                })
+                .collect();
+        format!(
+            "{{\"initial\":{},\"states\":[{}]}}",
+            self.initial,
+            states.join(","),
+        )
+    }
+}
+
+/// Write one transition, given its (already serialized) stack condition and tokens.
+#[inline]
+#[allow(clippy::missing_panics_doc)]
+fn transition_json<A: fmt::Debug + Ord, S: fmt::Debug + fmt::Display + Copy + Ord>(
+    top: &str,
+    range: &str,
+    edge: &Edge<A, S, usize>,
+) -> String {
+    let action = match *edge {
+        Edge::Call {
+            dst,
+            ref call,
+            push,
+        } => format!(
+            "\"action\":\"push\",\"push\":{},\"dst\":{dst},\"call\":{}",
+            quote(&push.to_string()),
+            quote(&call.src),
+        ),
+        Edge::Return { dst, ref call } => format!(
+            "\"action\":\"pop\",\"dst\":{dst},\"call\":{}",
+            quote(&call.src),
+        ),
+        Edge::Local { dst, ref call } => format!(
+            "\"action\":\"local\",\"dst\":{dst},\"call\":{}",
+            quote(&call.src),
+        ),
+        Edge::Epsilon { dst } => format!("\"action\":\"epsilon\",\"dst\":{dst}"),
+        Edge::Phantom(..) => never!(),
+    };
+    format!("{{{top}\"range\":{range},{action}}}")
+}
+
+/// Error with this message.
+#[inline]
+fn fail(message: &str) -> JsonError {
+    JsonError {
+        message: message.to_owned(),
+    }
+}
+
+/// Write a JSON string literal, escaping whatever needs it.
+#[inline]
+fn quote(s: &str) -> String {
+    let mut rtn = String::with_capacity(s.len().saturating_add(2));
+    rtn.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => rtn.push_str("\\\""),
+            '\\' => rtn.push_str("\\\\"),
+            '\n' => rtn.push_str("\\n"),
+            '\r' => rtn.push_str("\\r"),
+            '\t' => rtn.push_str("\\t"),
+            _ if c.is_control() => {
+                unwrap!(write!(rtn, "\\u{:04x}", u32::from(c)));
+            }
+            _ => rtn.push(c),
+        }
+    }
+    rtn.push('"');
+    rtn
+}
+
+/// Character iterator that remembers byte offsets.
+type Chars<'s> = Peekable<CharIndices<'s>>;
+
+/// Skip whitespace, if any.
+#[inline]
+fn skip_whitespace(chars: &mut Chars<'_>) {
+    while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+}
+
+/// Consume exactly this character (after any whitespace).
+#[inline]
+fn expect(chars: &mut Chars<'_>, expected: char) -> Result<(), JsonError> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some((_, c)) if c == expected => Ok(()),
+        Some((i, c)) => Err(fail(&format!(
+            "expected `{expected}` but found `{c}` at byte {i}"
+        ))),
+        None => Err(fail(&format!("expected `{expected}` but the input ended"))),
+    }
+}
+
+/// Deepest that arrays and objects may nest (the format itself needs only five levels),
+/// so that hostile input can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Parse any JSON value this format uses, with arrays and objects nested at most `depth` deep.
+#[inline]
+fn parse_value(json: &str, chars: &mut Chars<'_>, depth: usize) -> Result<Json, JsonError> {
+    skip_whitespace(chars);
+    let &(start, c) = chars
+        .peek()
+        .ok_or_else(|| fail("expected a value but the input ended"))?;
+    if matches!(c, '{' | '[') && depth == 0 {
+        return Err(fail(&format!("nested too deeply at byte {start}")));
+    }
+    let inner = depth.saturating_sub(1);
+    match c {
+        '{' => {
+            let _: Option<(usize, char)> = chars.next();
+            let mut fields = vec![];
+            skip_whitespace(chars);
+            if chars.next_if(|&(_, close)| close == '}').is_some() {
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                expect(chars, ':')?;
+                fields.push((key, parse_value(json, chars, inner)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some((_, ',')) => {}
+                    Some((_, '}')) => return Ok(Json::Object(fields)),
+                    _ => return Err(fail("expected `,` or `}` in an object")),
+                }
+            }
+        }
+        '[' => {
+            let _: Option<(usize, char)> = chars.next();
+            let mut elements = vec![];
+            skip_whitespace(chars);
+            if chars.next_if(|&(_, close)| close == ']').is_some() {
+                return Ok(Json::Array(elements));
+            }
+            loop {
+                elements.push(parse_value(json, chars, inner)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some((_, ',')) => {}
+                    Some((_, ']')) => return Ok(Json::Array(elements)),
+                    _ => return Err(fail("expected `,` or `]` in an array")),
+                }
+            }
+        }
+        '"' => parse_string(chars).map(Json::String),
+        '0'..='9' => {
+            let mut end = start;
+            while let Some((i, d)) = chars.next_if(|&(_, d)| d.is_ascii_digit()) {
+                end = i.saturating_add(d.len_utf8());
+            }
+            json.get(start..end)
+                .and_then(|digits| digits.parse().ok())
+                .map(Json::Number)
+                .ok_or_else(|| fail(&format!("bad number at byte {start}")))
+        }
+        _ => {
+            let rest = json.get(start..).unwrap_or_default();
+            for (word, value) in [
+                ("null", Json::Null),
+                ("true", Json::Bool(true)),
+                ("false", Json::Bool(false)),
+            ] {
+                if rest.starts_with(word) {
+                    for _ in 0..word.len() {
+                        let _: Option<(usize, char)> = chars.next();
+                    }
+                    return Ok(value);
+                }
+            }
+            Err(fail(&format!("unexpected `{c}` at byte {start}")))
+        }
+    }
+}
+
+/// Parse a string literal, unescaping as we go.
+#[inline]
+fn parse_string(chars: &mut Chars<'_>) -> Result<String, JsonError> {
+    expect(chars, '"')?;
+    let mut rtn = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(fail("unterminated string")),
+            Some((_, '"')) => return Ok(rtn),
+            Some((i, '\\')) => rtn.push(match chars.next() {
+                Some((_, '"')) => '"',
+                Some((_, '\\')) => '\\',
+                Some((_, '/')) => '/',
+                Some((_, 'b')) => '\u{8}',
+                Some((_, 'f')) => '\u{c}',
+                Some((_, 'n')) => '\n',
+                Some((_, 'r')) => '\r',
+                Some((_, 't')) => '\t',
+                Some((_, 'u')) => {
+                    let bad = || fail(&format!("bad `\\u` escape at byte {i}"));
+                    let high = parse_hex4(chars).ok_or_else(bad)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        // Half of a surrogate pair, so the other half has to follow as another `\u` escape
+                        if chars.next().map(|(_, c)| c) != Some('\\')
+                            || chars.next().map(|(_, c)| c) != Some('u')
+                        {
+                            return Err(bad());
+                        }
+                        let low = parse_hex4(chars)
+                            .filter(|low| (0xDC00..0xE000).contains(low))
+                            .ok_or_else(bad)?;
+                        (((high & 0x3FF) << 10_u32) | (low & 0x3FF)).saturating_add(0x1_0000)
+                    } else {
+                        high
+                    };
+                    char::from_u32(code).ok_or_else(bad)?
+                }
+                _ => return Err(fail(&format!("bad escape at byte {i}"))),
+            }),
+            Some((_, c)) => rtn.push(c),
+        }
+    }
+}
+
+/// Parse exactly four hex digits.
+#[inline]
+fn parse_hex4(chars: &mut Chars<'_>) -> Option<u32> {
+    let hex: String = (0..4_u8)
+        .filter_map(|_| chars.next().map(|(_, h)| h))
+        .collect();
+    if hex.len() != 4 || !hex.bytes().all(|h| h.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+/// Expect an object.
+#[inline]
+fn object<'j>(json: &'j Json, what: &str) -> Result<&'j [(String, Json)], JsonError> {
+    let Json::Object(ref fields) = *json else {
+        return Err(fail(&format!("expected {what} to be an object")));
+    };
+    Ok(fields)
+}
+
+/// Expect an array.
+#[inline]
+fn array<'j>(json: &'j Json, what: &str) -> Result<&'j [Json], JsonError> {
+    let Json::Array(ref elements) = *json else {
+        return Err(fail(&format!("expected {what} to be an array")));
+    };
+    Ok(elements)
+}
+
+/// Expect a nonnegative integer.
+#[inline]
+fn number(json: &Json, what: &str) -> Result<usize, JsonError> {
+    let Json::Number(n) = *json else {
+        return Err(fail(&format!("expected {what} to be a number")));
+    };
+    Ok(n)
+}
+
+/// Expect a string.
+#[inline]
+fn string<'j>(json: &'j Json, what: &str) -> Result<&'j str, JsonError> {
+    let Json::String(ref s) = *json else {
+        return Err(fail(&format!("expected {what} to be a string")));
+    };
+    Ok(s)
+}
+
+/// Expect a string and parse it with `FromStr`.
+#[inline]
+fn parsed<T: FromStr>(json: &Json, what: &str) -> Result<T, JsonError> {
+    string(json, what)?
+        .parse()
+        .ok()
+        .ok_or_else(|| fail(&format!("bad {what}")))
+}
+
+/// Look up a field by name, if it's there.
+#[inline]
+fn maybe_field<'j>(fields: &'j [(String, Json)], name: &str) -> Option<&'j Json> {
+    fields
+        .iter()
+        .find_map(|&(ref key, ref value)| (key == name).then_some(value))
+}
+
+/// Look up a field by name.
+#[inline]
+fn field<'j>(fields: &'j [(String, Json)], name: &str) -> Result<&'j Json, JsonError> {
+    maybe_field(fields, name).ok_or_else(|| fail(&format!("missing `{name}`")))
+}
+
+/// Read one state.
+#[inline]
+fn parse_state<
+    A: 'static + fmt::Debug + Clone + FromStr + Ord,
+    S: 'static + fmt::Debug + Copy + FromStr + Ord,
+>(
+    json: &Json,
+) -> Result<State<A, S, usize>, JsonError> {
+    let fields = object(json, "each state")?;
+    let Json::Bool(accepting) = *field(fields, "accepting")? else {
+        return Err(fail("expected `accepting` to be `true` or `false`"));
+    };
+    let mut state = State {
+        transitions: CurryOpt::default(),
+        accepting,
+    };
+    for transition in array(field(fields, "transitions")?, "`transitions`")? {
+        parse_transition(&mut state, object(transition, "each transition")?)?;
+    }
+    Ok(state)
+}
+
+/// Read one transition and add it to this state.
+#[inline]
+fn parse_transition<
+    A: 'static + fmt::Debug + Clone + FromStr + Ord,
+    S: 'static + fmt::Debug + Copy + FromStr + Ord,
+>(
+    state: &mut State<A, S, usize>,
+    fields: &[(String, Json)],
+) -> Result<(), JsonError> {
+    let range = match *field(fields, "range")? {
+        Json::Null => None,
+        Json::Array(ref bounds) => match *bounds.as_slice() {
+            [ref first, ref last] => {
+                let range = Range {
+                    first: parsed(first, "token")?,
+                    last: parsed(last, "token")?,
+                };
+                if range.first > range.last {
+                    return Err(fail("range ends before it starts"));
+                }
+                Some(range)
+            }
+            _ => return Err(fail("expected `range` to have exactly two tokens")),
+        },
+        Json::Bool(_) | Json::Number(_) | Json::String(_) | Json::Object(_) => {
+            return Err(fail("expected `range` to be `null` or an array"))
+        }
+    };
+    let dst = number(field(fields, "dst")?, "`dst`")?;
+    let call = || -> Result<Call<(), ()>, JsonError> {
+        Ok(Call::new(
+            |x| x,
+            string(field(fields, "call")?, "`call`")?.to_owned(),
+        ))
+    };
+    let edge = match string(field(fields, "action")?, "`action`")? {
+        "push" => Edge::Call {
+            dst,
+            call: call()?,
+            push: parsed(field(fields, "push")?, "stack symbol")?,
+        },
+        "pop" => Edge::Return { dst, call: call()? },
+        "local" => Edge::Local { dst, call: call()? },
+        "epsilon" => Edge::Epsilon { dst },
+        _ => {
+            return Err(fail(
+                "expected `action` to be `push`, `pop`, `local`, or `epsilon`",
+            ))
+        }
+    };
+    let added = match maybe_field(fields, "top") {
+        None => add(&mut state.transitions.wildcard, range, edge),
+        Some(&Json::Null) => add(&mut state.transitions.none, range, edge),
+        Some(top) => {
+            let s = parsed(top, "stack symbol")?;
+            let mut slot = state.transitions.some.remove(&s);
+            let added = add(&mut slot, range, edge);
+            if let Some(wild) = slot {
+                drop(state.transitions.some.insert(s, wild));
+            }
+            added
+        }
+    };
+    if !added {
+        return Err(fail(
+            "`null` range can't share a stack condition with any other tokens",
+        ));
+    }
+    Ok(())
+}
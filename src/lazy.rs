@@ -0,0 +1,148 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Determinization on demand: only the configurations some input actually reaches.
+
+use crate::{Execute as _, IllFormed, Nondeterministic};
+use core::{cmp::Ordering, fmt};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Nondeterministic automaton that remembers every set of states it has been in and every step between them,
+/// effectively building the reachable part of its determinization one input at a time.
+///
+/// Inputs that share long prefixes run at nearly the speed of a deterministic automaton,
+/// without determinizing everything up front (and risking exponentially many states).
+#[allow(clippy::type_complexity)]
+#[derive(Clone, Debug)]
+pub struct LazyDeterministic<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord> {
+    /// Automaton we're running.
+    graph: Nondeterministic<A, S>,
+    /// Every set of states reached so far, indexed by ID.
+    configurations: Vec<BTreeSet<usize>>,
+    /// Reverse of `configurations`.
+    ids: BTreeMap<BTreeSet<usize>, usize>,
+    /// Every step taken so far, from a configuration ID, stack top, and token,
+    /// to the next configuration ID and what happened to the stack (or `None` if the input was rejected).
+    transitions: BTreeMap<(usize, Option<S>, A), Option<(usize, Effect<S>)>>,
+}
+
+/// What one step did to the stack.
+#[derive(Clone, Copy, Debug)]
+enum Effect<S> {
+    /// Pushed this symbol.
+    Push(S),
+    /// Popped the top symbol.
+    Pop,
+    /// Left the stack alone.
+    Stay,
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> LazyDeterministic<A, S> {
+    /// Wrap this automaton without exploring anything yet.
+    #[inline]
+    #[must_use]
+    pub fn new(graph: Nondeterministic<A, S>) -> Self {
+        let initial = graph.initial.clone();
+        Self {
+            graph,
+            configurations: vec![initial.clone()],
+            ids: BTreeMap::from([(initial, 0)]),
+            transitions: BTreeMap::new(),
+        }
+    }
+
+    /// Automaton we're running.
+    #[inline(always)]
+    #[must_use]
+    pub const fn graph(&self) -> &Nondeterministic<A, S> {
+        &self.graph
+    }
+
+    /// Stop running and give back the automaton, forgetting everything explored.
+    #[inline]
+    #[must_use]
+    pub fn into_inner(self) -> Nondeterministic<A, S> {
+        self.graph
+    }
+
+    /// Number of distinct sets of states reached so far,
+    /// i.e. the number of states a full determinization would have needed for the inputs seen so far.
+    #[inline]
+    #[must_use]
+    pub const fn explored(&self) -> usize {
+        self.configurations.len()
+    }
+
+    /// Same as `accept`, but reusing (and extending) everything previous inputs explored.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept<I: IntoIterator<Item = A>>(
+        &mut self,
+        input: I,
+    ) -> Result<bool, IllFormed<A, S, BTreeSet<usize>>> {
+        let mut id = 0;
+        let mut stack = vec![];
+        for token in input {
+            let Some((next, effect)) = self.step(id, stack.last().copied(), token)? else {
+                return Ok(false);
+            };
+            match effect {
+                Effect::Push(symbol) => stack.push(symbol),
+                Effect::Pop => drop(stack.pop()),
+                Effect::Stay => {}
+            }
+            id = next;
+        }
+        Ok(self
+            .graph
+            .would_accept(get!(self.configurations, id), &stack))
+    }
+
+    /// Look up a step, or take it for real and remember it.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    fn step(
+        &mut self,
+        id: usize,
+        top: Option<S>,
+        token: A,
+    ) -> Result<Option<(usize, Effect<S>)>, IllFormed<A, S, BTreeSet<usize>>> {
+        if let Some(&memo) = self.transitions.get(&(id, top, token.clone())) {
+            return Ok(memo);
+        }
+        // Every step depends only on the top of the stack, so a stack holding just that is enough
+        let mut stack: Vec<S> = top.into_iter().collect();
+        let before = stack.len();
+        let ctrl = get!(self.configurations, id).clone();
+        let result = self
+            .graph
+            .step(ctrl, &mut stack, Some(&token))?
+            .ok()
+            .map(|next| {
+                let effect = match stack.len().cmp(&before) {
+                    Ordering::Greater => Effect::Push(*unwrap!(stack.last())),
+                    Ordering::Less => Effect::Pop,
+                    Ordering::Equal => Effect::Stay,
+                };
+                (self.intern(next), effect)
+            });
+        let _: Option<_> = self.transitions.insert((id, top, token), result);
+        Ok(result)
+    }
+
+    /// ID of this set of states, assigning a new one if we haven't seen it before.
+    #[inline]
+    fn intern(&mut self, ctrl: BTreeSet<usize>) -> usize {
+        if let Some(&id) = self.ids.get(&ctrl) {
+            return id;
+        }
+        let id = self.configurations.len();
+        self.configurations.push(ctrl.clone());
+        let _: Option<usize> = self.ids.insert(ctrl, id);
+        id
+    }
+}
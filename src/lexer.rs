@@ -0,0 +1,39 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Maximal-munch tokenizer running several automata side by side.
+
+use crate::{Deterministic, IllFormed};
+use core::fmt;
+
+/// Maximal-munch tokenizer: each rule is an automaton recognizing one kind of token,
+/// and the longest match wins, with ties going to whichever rule comes first.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Debug)]
+pub struct Lexer<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord, T> {
+    /// Token automata, each with the token it recognizes, in order of priority.
+    pub rules: Vec<(Deterministic<A, S>, T)>,
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, T> Lexer<A, S, T> {
+    /// Kind and length of the longest nonempty token at the start of this input,
+    /// with ties going to the earliest rule, or `None` if no rule matches.
+    /// # Errors
+    /// If any rule's automaton is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn next_token(&self, input: &[A]) -> Result<Option<(&T, usize)>, IllFormed<A, S, usize>> {
+        let mut best: Option<(&T, usize)> = None;
+        for &(ref automaton, ref token) in &self.rules {
+            if let Some(len) = automaton.longest_match(input)? {
+                if best.is_none_or(|(_, longest)| len > longest) {
+                    best = Some((token, len));
+                }
+            }
+        }
+        Ok(best)
+    }
+}
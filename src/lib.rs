@@ -5,6 +5,11 @@
  */
 
 //! Visibly pushdown automata.
+//!
+//! In release builds, internal invariants are assumed rather than checked:
+//! out-of-bounds indices and impossible branches are undefined behavior instead of panics.
+//! Enable the `checked` feature to keep every debug-mode assertion and bounds check in release,
+//! trading some speed (mostly in `step` and `get`) for a panic on a malformed automaton.
 
 #![deny(warnings)]
 #![allow(unknown_lints)]
@@ -86,16 +91,16 @@
     clippy::wildcard_imports
 )]
 
-/// Unwrap if we're debugging but `unwrap_unchecked` if we're not.
-#[cfg(any(debug_assertions, test))]
+/// Unwrap if we're debugging (or the `checked` feature is on) but `unwrap_unchecked` if we're not.
+#[cfg(any(debug_assertions, test, feature = "checked"))]
 macro_rules! unwrap {
     ($expr:expr) => {
         $expr.unwrap()
     };
 }
 
-/// Unwrap if we're debugging but `unwrap_unchecked` if we're not.
-#[cfg(not(any(debug_assertions, test)))]
+/// Unwrap if we're debugging (or the `checked` feature is on) but `unwrap_unchecked` if we're not.
+#[cfg(not(any(debug_assertions, test, feature = "checked")))]
 macro_rules! unwrap {
     ($expr:expr) => {{
         #[allow(unsafe_code)]
@@ -104,16 +109,16 @@ macro_rules! unwrap {
     }};
 }
 
-/// Unwrap if we're debugging but `unwrap_unchecked` if we're not.
-#[cfg(any(debug_assertions, test))]
+/// Unwrap if we're debugging (or the `checked` feature is on) but `unwrap_unchecked` if we're not.
+#[cfg(any(debug_assertions, test, feature = "checked"))]
 macro_rules! get {
     ($expr:expr, $index:expr) => {
         $expr.get($index).unwrap()
     };
 }
 
-/// Unwrap if we're debugging but `unwrap_unchecked` if we're not.
-#[cfg(not(any(debug_assertions, test)))]
+/// Unwrap if we're debugging (or the `checked` feature is on) but `unwrap_unchecked` if we're not.
+#[cfg(not(any(debug_assertions, test, feature = "checked")))]
 macro_rules! get {
     ($expr:expr, $index:expr) => {{
         #[allow(unsafe_code)]
@@ -122,16 +127,16 @@ macro_rules! get {
     }};
 }
 
-/// Unreachable state, but checked if we're debugging.
-#[cfg(any(debug_assertions, test))]
+/// Unreachable state, but checked if we're debugging (or the `checked` feature is on).
+#[cfg(any(debug_assertions, test, feature = "checked"))]
 macro_rules! never {
     () => {
         unreachable!()
     };
 }
 
-/// Unreachable state, but checked if we're debugging.
-#[cfg(not(any(debug_assertions, test)))]
+/// Unreachable state, but checked if we're debugging (or the `checked` feature is on).
+#[cfg(not(any(debug_assertions, test, feature = "checked")))]
 macro_rules! never {
     () => {{
         #[allow(unsafe_code)]
@@ -141,17 +146,30 @@ macro_rules! never {
     }};
 }
 
-/*
-/// Unwrap if we're debugging but `unwrap_unchecked` if we're not.
-#[cfg(any(debug_assertions, test))]
+/// Emit a structured `tracing` event if the feature is enabled; otherwise compile to nothing.
+#[cfg(feature = "tracing")]
+macro_rules! trace {
+    ($($tt:tt)*) => {
+        tracing::trace!($($tt)*)
+    };
+}
+
+/// Emit a structured `tracing` event if the feature is enabled; otherwise compile to nothing.
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace {
+    ($($tt:tt)*) => {};
+}
+
+/// Unwrap if we're debugging (or the `checked` feature is on) but `unwrap_unchecked` if we're not.
+#[cfg(any(debug_assertions, test, feature = "checked"))]
 macro_rules! get_mut {
     ($expr:expr, $index:expr) => {
         $expr.get_mut($index).unwrap()
     };
 }
 
-/// Unwrap if we're debugging but `unwrap_unchecked` if we're not.
-#[cfg(not(any(debug_assertions, test)))]
+/// Unwrap if we're debugging (or the `checked` feature is on) but `unwrap_unchecked` if we're not.
+#[cfg(not(any(debug_assertions, test, feature = "checked")))]
 macro_rules! get_mut {
     ($expr:expr, $index:expr) => {{
         #[allow(unsafe_code, unused_unsafe)]
@@ -159,7 +177,6 @@ macro_rules! get_mut {
         result
     }};
 }
-*/
 
 /// Call a function that will also be available to the compiled parser.
 #[macro_export]
@@ -169,19 +186,54 @@ macro_rules! call {
     };
 }
 
+mod acceptance;
 mod automaton;
+mod bitset;
+mod caching;
 mod call;
+mod compile;
+mod complement;
+mod coverage;
 mod curry_opt;
+mod dot;
 mod edge;
+mod enumerate;
+mod epsilon;
 mod exec;
 mod indices;
+mod json;
+mod lazy;
+mod lexer;
+mod lint;
 mod lookup;
 mod merge;
+mod nested;
+mod product;
+mod project;
 mod range;
+mod record;
+mod regex;
+mod reverse;
 mod run;
+mod size;
+mod stack;
 mod state;
+mod stream;
 mod subc;
+mod text;
+mod total;
+mod weight;
 mod wildcard;
+mod witness;
+
+#[cfg(feature = "graphemes")]
+mod grapheme;
+
+#[cfg(feature = "persistent")]
+mod persistent;
+
+#[cfg(feature = "proptest")]
+mod proptest_impl;
 
 #[cfg(feature = "quickcheck")]
 mod quickcheck_impl;
@@ -190,19 +242,41 @@ mod quickcheck_impl;
 mod test;
 
 pub use {
+    acceptance::Acceptance,
     automaton::{Automaton, Deterministic, Nondeterministic},
+    bitset::{BitSet, BitSetIter},
+    caching::CachingRun,
     call::Call,
+    compile::Compiled,
     curry_opt::CurryOpt,
     edge::Edge,
-    exec::{Execute, Execution, IllFormed},
+    exec::{Execute, Execution, IllFormed, PushExecution, Stack, Verdicts},
     indices::Indices,
+    json::JsonError,
+    lazy::LazyDeterministic,
+    lexer::Lexer,
+    lint::Lint,
     lookup::{Lookup, Return},
     merge::{merge, Merge},
-    range::Range,
+    nested::NestedWord,
+    range::{complement_ranges, refine, Alphabet, Discrete, HalfOpenRange, ParseRangeError, Range},
+    record::{replay, RunEvent},
     run::Run,
     state::State,
-    wildcard::Wildcard,
+    stream::RunState,
+    text::ParseError,
+    total::{TotalF32, TotalF64},
+    wildcard::{LookupPolicy, Wildcard},
 };
 
+#[cfg(feature = "graphemes")]
+pub use grapheme::grapheme_run;
+
+#[cfg(feature = "persistent")]
+pub use persistent::PersistentStack;
+
 #[cfg(all(test, not(feature = "quickcheck")))]
 use quickcheck as _;
+
+#[cfg(test)]
+use criterion as _;
@@ -0,0 +1,87 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Non-fatal warnings about automata that are well-formed but probably not what was intended.
+
+use crate::{Automaton, Indices};
+use core::fmt;
+
+/// Something suspicious but not ill-formed.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Lint<S: fmt::Debug + Copy + Ord> {
+    /// State that no run can ever reach.
+    UnreachableState {
+        /// Index of the state.
+        state: usize,
+    },
+    /// Transitions keyed on a stack condition that never holds when this state is reached:
+    /// `None` for transitions on an empty stack, or `Some(s)` for transitions with `s` on top.
+    UnreachableStackCondition {
+        /// Index of the state.
+        state: usize,
+        /// Which stack condition.
+        condition: Option<S>,
+    },
+    /// Reachable, non-accepting state that only ever leads back to itself, so every run reaching it rejects.
+    TrapState {
+        /// Index of the state.
+        state: usize,
+    },
+    /// `Return` edge that can only ever be taken on an empty stack, which always rejects.
+    UnmatchedReturn {
+        /// Index of the state.
+        state: usize,
+    },
+    /// No initial state at all, so every input is rejected.
+    EmptyInitialSet,
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>
+    Automaton<A, S, Ctrl>
+{
+    /// Everything suspicious about this automaton, state by state.
+    /// Unlike `check`, none of these affect whether the automaton can run,
+    /// and since reachability is approximated by whether the stack is empty, some problems may go unreported.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn lint(&self) -> Vec<Lint<S>> {
+        let reachable = self.stack_reachability();
+        let unmatched = self.find_unmatched_returns();
+        let mut lints = vec![];
+        if self.initial.iter().next().is_none() {
+            lints.push(Lint::EmptyInitialSet);
+        }
+        for (state, (s, &[empty, nonempty])) in self.states.iter().zip(&reachable).enumerate() {
+            if !empty && !nonempty {
+                lints.push(Lint::UnreachableState { state });
+                continue;
+            }
+            if s.is_trap(state) {
+                lints.push(Lint::TrapState { state });
+            }
+            if !empty && s.transitions.none.is_some() {
+                lints.push(Lint::UnreachableStackCondition {
+                    state,
+                    condition: None,
+                });
+            }
+            if !nonempty {
+                lints.extend(s.transitions.some.keys().map(|&symbol| {
+                    Lint::UnreachableStackCondition {
+                        state,
+                        condition: Some(symbol),
+                    }
+                }));
+            }
+            if unmatched.contains(&state) {
+                lints.push(Lint::UnmatchedReturn { state });
+            }
+        }
+        lints
+    }
+}
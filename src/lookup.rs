@@ -19,6 +19,9 @@ pub trait Lookup: fmt::Debug {
     /// Look up an element.
     #[must_use]
     fn get(&self, key: Self::Key<'_>) -> Option<&Self::Value>;
+    /// Look up an element for in-place editing.
+    #[must_use]
+    fn get_mut(&mut self, key: Self::Key<'_>) -> Option<&mut Self::Value>;
     /// Apply a function to each value.
     fn map_values<F: FnMut(&mut Self::Value)>(&mut self, f: F);
 }
@@ -30,6 +33,10 @@ impl<K: 'static + fmt::Debug + Ord, V: 'static + fmt::Debug> Lookup for BTreeMap
     fn get(&self, key: Self::Key<'_>) -> Option<&Self::Value> {
         BTreeMap::get(self, key)
     }
+    #[inline(always)]
+    fn get_mut(&mut self, key: Self::Key<'_>) -> Option<&mut Self::Value> {
+        BTreeMap::get_mut(self, key)
+    }
     #[inline]
     fn map_values<F: FnMut(&mut Self::Value)>(&mut self, mut f: F) {
         for v in self.values_mut() {
@@ -51,16 +58,25 @@ impl<T: 'static + fmt::Debug> Lookup for Return<T> {
         Some(&self.0)
     }
     #[inline(always)]
+    fn get_mut(&mut self, (): Self::Key<'_>) -> Option<&mut Self::Value> {
+        Some(&mut self.0)
+    }
+    #[inline(always)]
     fn map_values<F: FnMut(&mut Self::Value)>(&mut self, mut f: F) {
         f(&mut self.0);
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge<A, S, Ctrl>
-    for Return<Edge<A, S, Ctrl>>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for Return<Edge<A, S, Ctrl, In, Out>>
 {
     #[inline(always)]
-    fn merge(self, other: &Self) -> Result<Self, crate::IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, crate::IllFormed<A, S, Ctrl, In, Out>> {
         Ok(Self(self.0.merge(&other.0)?))
     }
 }
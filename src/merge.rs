@@ -11,20 +11,30 @@ use core::{borrow::Borrow, fmt};
 use std::collections::{BTreeMap, BTreeSet};
 
 /// Trait to fallibly combine multiple values into one value with identical semantics.
-pub trait Merge<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>:
-    Sized
+pub trait Merge<
+    A: fmt::Debug + Ord,
+    S: fmt::Debug + Copy + Ord,
+    Ctrl: Indices<A, S>,
+    In: 'static = (),
+    Out: 'static = (),
+>: Sized
 {
     /// Fallibly combine multiple values into one value with identical semantics.
     /// # Errors
     /// Implementation-defined: if the merge as we define it can't work.
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>>;
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>>;
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge<A, S, Ctrl>
-    for usize
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static,
+        Out: 'static,
+    > Merge<A, S, Ctrl, In, Out> for usize
 {
     #[inline]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
         if self == *other {
             Ok(self)
         } else {
@@ -33,11 +43,16 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge<A, S, Ctrl>
-    for Option<Return<Edge<A, S, Ctrl>>>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for Option<Return<Edge<A, S, Ctrl, In, Out>>>
 {
     #[inline(always)]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
         Ok(match (self, other) {
             (None, &None) => None,
             (Some(a), &None) => Some(a),
@@ -50,11 +65,13 @@ impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S
 impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
-        Ctrl: Indices<A, S>,
-    > Merge<A, S, Ctrl> for Option<Wildcard<A, Return<Edge<A, S, Ctrl>>>>
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for Option<Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>>
 {
     #[inline(always)]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
         Ok(match (self, other) {
             (None, &None) => None,
             (Some(a), &None) => Some(a),
@@ -64,16 +81,35 @@ impl<
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge<A, S, Ctrl>
-    for Vec<(Range<A>, Return<Edge<A, S, Ctrl>>)>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for Vec<(Range<A>, Return<Edge<A, S, Ctrl, In, Out>>)>
 {
     #[inline(always)]
-    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
         for &(ref rk, Return(ref rv)) in other {
-            for &(ref lk, _) in &self {
-                if let Some(union) = lk.union(rk) {
-                    return Err(IllFormed::VecMergeConflict(union));
-                }
+            // An identical key adds no new overlap, even if it already overlapped others on the left
+            if let Some(&mut (_, Return(ref mut lv))) =
+                self.iter_mut().find(|&&mut (ref lk, _)| lk == rk)
+            {
+                *lv = lv.clone().merge(rv).ok().ok_or_else(|| {
+                    IllFormed::RangeMergeConflict(rk.clone(), lv.clone(), rv.clone())
+                })?;
+                continue;
+            }
+            for &(ref lk, Return(ref lv)) in &self {
+                let Some(shared) = lk.clamp_to(rk) else {
+                    continue;
+                };
+                drop(lv.clone().merge(rv).ok().ok_or_else(|| {
+                    IllFormed::RangeMergeConflict(shared.clone(), lv.clone(), rv.clone())
+                })?);
+                // Merging these would need splitting ranges (see `Wildcard::merge_splitting`)
+                return Err(IllFormed::VecMergeConflict(shared));
             }
             self.push((rk.clone(), Return(rv.clone())));
         }
@@ -81,11 +117,11 @@ impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S
     }
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Merge<A, S, BTreeSet<usize>>
-    for BTreeSet<usize>
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, In: 'static, Out: 'static>
+    Merge<A, S, BTreeSet<usize>, In, Out> for BTreeSet<usize>
 {
     #[inline(always)]
-    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, BTreeSet<usize>>> {
+    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, BTreeSet<usize>, In, Out>> {
         self.extend(other.iter().copied());
         Ok(self)
     }
@@ -94,15 +130,23 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Merge<A, S, BTreeSet<usize
 impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
-        Ctrl: Indices<A, S>,
-    > Merge<A, S, Ctrl> for BTreeMap<S, Wildcard<A, Return<Edge<A, S, Ctrl>>>>
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for BTreeMap<S, Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>>
 {
     #[inline(always)]
-    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(mut self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
         for (k, v) in other {
-            if let Some(pre_v) = self.insert(*k, v.clone()) {
-                return Err(IllFormed::MapMergeConflict(*k, pre_v, v.clone()));
-            }
+            let merged = match self.remove(k) {
+                Some(pre_v) => pre_v
+                    .clone()
+                    .merge(v)
+                    .ok()
+                    .ok_or_else(|| IllFormed::MapMergeConflict(*k, pre_v, v.clone()))?,
+                None => v.clone(),
+            };
+            drop(self.insert(*k, merged));
         }
         Ok(self)
     }
@@ -110,19 +154,27 @@ impl<
 
 /// Merge an entire iterator into a value.
 #[inline]
+#[allow(clippy::type_complexity)]
 pub fn merge<
     A: fmt::Debug + Ord,
     S: fmt::Debug + Copy + Ord,
     Ctrl: Indices<A, S>,
-    M: Clone + Merge<A, S, Ctrl>,
+    In: 'static + fmt::Debug + Clone + Ord,
+    Out: 'static + fmt::Debug + Clone + Ord,
+    M: Clone + Merge<A, S, Ctrl, In, Out>,
     I: IntoIterator,
 >(
     i: I,
-) -> Option<Result<M, IllFormed<A, S, Ctrl>>>
+) -> Option<Result<M, IllFormed<A, S, Ctrl, In, Out>>>
 where
     I::Item: Borrow<M>,
 {
     let mut iter = i.into_iter();
     let first = iter.next()?;
-    Some(iter.try_fold(first.borrow().clone(), |acc, m| acc.merge(m.borrow())))
+    Some(iter.try_fold(first.borrow().clone(), |acc, m| {
+        acc.merge(m.borrow()).map_err(|e| {
+            trace!(error = ?e, "conflict detected");
+            e
+        })
+    }))
 }
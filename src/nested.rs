@@ -0,0 +1,101 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Parse trees: an accepted input split along its matched pushes and pops.
+
+use crate::{Deterministic, Execute as _, IllFormed};
+use core::{
+    cmp::Ordering,
+    fmt,
+    mem::{replace, take},
+};
+
+/// Input arranged by how it nests: every token that pushed wraps everything up to the token that popped it.
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NestedWord<A> {
+    /// Token that neither pushed nor popped.
+    Leaf(A),
+    /// Token that pushed, everything read in between, and the token that popped it.
+    Node {
+        /// Token that pushed.
+        open: A,
+        /// Everything read in between, in order.
+        children: Vec<NestedWord<A>>,
+        /// Token that popped.
+        close: A,
+    },
+    /// Whole input: everything at the outermost level, in order.
+    Root(Vec<NestedWord<A>>),
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// If this input is accepted, arrange it into a tree in which each push and its matching pop form a node
+    /// and every other token is a leaf; otherwise, `None`.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn parse_tree(&self, input: &[A]) -> Result<Option<NestedWord<A>>, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        let mut siblings = vec![];
+        let mut enclosing = vec![];
+        for token in input {
+            let depth = stack.len();
+            ctrl = match self.step_det(ctrl, &mut stack, Some(token))? {
+                Ok(ok) => ok,
+                Err(_) => return Ok(None),
+            };
+            match stack.len().cmp(&depth) {
+                Ordering::Greater => enclosing.push((token.clone(), take(&mut siblings))),
+                Ordering::Less => {
+                    let Some((open, outer)) = enclosing.pop() else {
+                        never!()
+                    };
+                    let children = replace(&mut siblings, outer);
+                    siblings.push(NestedWord::Node {
+                        open,
+                        children,
+                        close: token.clone(),
+                    });
+                }
+                Ordering::Equal => siblings.push(NestedWord::Leaf(token.clone())),
+            }
+        }
+        Ok(self
+            .would_accept(&ctrl, &stack)
+            .then_some(NestedWord::Root(siblings)))
+    }
+
+    /// Positions `(open, close)` of every push and the pop that matched it, in the order they were closed,
+    /// e.g. to highlight matching delimiters.
+    /// If the input is rejected partway through, this stops there, so unclosed pushes never appear.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn match_pairs(&self, input: &[A]) -> Result<Vec<(usize, usize)>, IllFormed<A, S, usize>> {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        let mut opens = vec![];
+        let mut pairs = vec![];
+        for (i, token) in input.iter().enumerate() {
+            let depth = stack.len();
+            ctrl = match self.step_det(ctrl, &mut stack, Some(token))? {
+                Ok(ok) => ok,
+                Err(_) => break,
+            };
+            match stack.len().cmp(&depth) {
+                Ordering::Greater => opens.push(i),
+                Ordering::Less => {
+                    let Some(open) = opens.pop() else { never!() };
+                    pairs.push((open, i));
+                }
+                Ordering::Equal => {}
+            }
+        }
+        Ok(pairs)
+    }
+}
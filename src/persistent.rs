@@ -0,0 +1,106 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Persistent stack whose clones share structure.
+
+use crate::Stack;
+use core::{fmt, iter::successors};
+use std::rc::Rc;
+
+/// Persistent stack: a linked list whose clones share every frame,
+/// so cloning (e.g. to snapshot an execution) is constant-time instead of linear in the depth.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone)]
+pub struct PersistentStack<S>(Option<Rc<Frame<S>>>);
+
+/// One symbol and everything below it.
+#[derive(Debug)]
+struct Frame<S> {
+    /// Symbol on top.
+    top: S,
+    /// Everything below.
+    below: Option<Rc<Frame<S>>>,
+    /// Number of symbols, including this one.
+    len: usize,
+}
+
+impl<S> Default for PersistentStack<S> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<S> Drop for PersistentStack<S> {
+    /// Unlink one frame at a time: the default would recurse once per frame and overflow on deep stacks.
+    #[inline]
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(frame) = next {
+            // Stop at the first frame another stack still shares, since that stack will drop the rest
+            next = Rc::try_unwrap(frame)
+                .ok()
+                .and_then(|mut only| only.below.take());
+        }
+    }
+}
+
+impl<S> PersistentStack<S> {
+    /// Iterate from the top down.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &S> {
+        successors(self.0.as_deref(), |frame| frame.below.as_deref()).map(|frame| &frame.top)
+    }
+}
+
+impl<S: PartialEq> PartialEq for PersistentStack<S> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<S: Eq> Eq for PersistentStack<S> {}
+
+impl<S: fmt::Debug> fmt::Debug for PersistentStack<S> {
+    /// Bottom to top, just like a `Vec`.
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut symbols: Vec<_> = self.iter().collect();
+        symbols.reverse();
+        symbols.fmt(f)
+    }
+}
+
+impl<S: Clone + fmt::Debug> Stack<S> for PersistentStack<S> {
+    #[inline]
+    fn push(&mut self, symbol: S) {
+        let len = self.len().saturating_add(1);
+        self.0 = Some(Rc::new(Frame {
+            top: symbol,
+            below: self.0.take(),
+            len,
+        }));
+    }
+    #[inline]
+    fn pop(&mut self) -> Option<S> {
+        let frame = self.0.take()?;
+        self.0.clone_from(&frame.below);
+        Some(frame.top.clone())
+    }
+    #[inline(always)]
+    fn last(&self) -> Option<&S> {
+        self.0.as_deref().map(|frame| &frame.top)
+    }
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.0.as_deref().map_or(0, |frame| frame.len)
+    }
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+}
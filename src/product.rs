@@ -0,0 +1,285 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Union and intersection of automata, with `|` and `&` as shorthand.
+
+use crate::{
+    Automaton, CurryOpt, Deterministic, Edge, Nondeterministic, Range, Return, State, Wildcard,
+};
+use core::{
+    fmt,
+    ops::{BitAnd, BitOr},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+impl<
+        A: 'static + fmt::Debug + Ord,
+        S: 'static + fmt::Debug + Copy + Ord,
+        In: 'static,
+        Out: 'static,
+    > Nondeterministic<A, S, In, Out>
+{
+    /// Automaton accepting every input either of these accepts.
+    /// Both run side by side on one stack, so wherever both are still alive on the same token,
+    /// they had better agree on what (if anything) to push or pop.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn union(mut self, other: Self) -> Self {
+        let offset = self.states.len();
+        let shifted = other.reindex(|set| set.into_iter().map(|i| i + offset).collect());
+        self.states.extend(shifted.states);
+        self.initial.extend(shifted.initial);
+        self
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Ord,
+        S: 'static + fmt::Debug + Copy + Ord,
+        In: 'static,
+        Out: 'static,
+    > BitOr for Nondeterministic<A, S, In, Out>
+{
+    type Output = Self;
+    /// Same as `union`.
+    /// ```rust
+    /// use vpa::*;
+    /// let parens = Nondeterministic::bounded_depth(2, 0_u8, &Range::unit('('), &Range::unit(')'));
+    /// let brackets = Nondeterministic::bounded_depth(2, 1_u8, &Range::unit('['), &Range::unit(']'));
+    /// let either = parens | brackets;
+    /// assert!(either.accept("(())".chars()).unwrap());
+    /// assert!(either.accept("[][]".chars()).unwrap());
+    /// assert!(!either.accept("([])".chars()).unwrap());
+    /// ```
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Clone + Ord,
+        S: 'static + fmt::Debug + Copy + Ord,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Deterministic<A, S, In, Out>
+{
+    /// Automaton accepting exactly the inputs both of these accept, stacking pairs of their stack symbols.
+    /// Like any product of visibly pushdown automata, this assumes both agree on which tokens push, pop, or neither;
+    /// wherever they disagree, the product has no transition.
+    /// Only `self`'s calls survive.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn intersection<T: 'static + fmt::Debug + Copy + Ord>(
+        &self,
+        other: &Deterministic<A, T, In, Out>,
+    ) -> Deterministic<A, (S, T), In, Out> {
+        let theirs = pushes(other);
+        let pairs: Vec<(S, T)> = pushes(self)
+            .into_iter()
+            .flat_map(|s| theirs.iter().map(move |&t| (s, t)))
+            .collect();
+        let mut index = BTreeMap::new();
+        let _: Option<usize> = index.insert((self.initial, other.initial), 0);
+        let mut queue = vec![(self.initial, other.initial)];
+        let mut states = vec![];
+        while let Some(&(lhs, rhs)) = queue.get(states.len()) {
+            let (l, r) = (get!(self.states, lhs), get!(other.states, rhs));
+            let mut visit = |key| {
+                let len = index.len();
+                *index.entry(key).or_insert_with(|| {
+                    queue.push(key);
+                    len
+                })
+            };
+            let none = pair_up(
+                &entries(&l.transitions, None),
+                &entries(&r.transitions, None),
+                &mut visit,
+            );
+            let some = pairs
+                .iter()
+                .filter_map(|&(s, t)| {
+                    pair_up(
+                        &entries(&l.transitions, Some(&s)),
+                        &entries(&r.transitions, Some(&t)),
+                        &mut visit,
+                    )
+                    .map(|wild| ((s, t), wild))
+                })
+                .collect();
+            states.push(State {
+                transitions: CurryOpt {
+                    wildcard: None,
+                    none,
+                    some,
+                },
+                accepting: l.accepting && r.accepting,
+            });
+        }
+        Automaton { states, initial: 0 }
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Clone + Ord,
+        S: 'static + fmt::Debug + Copy + Ord,
+        T: 'static + fmt::Debug + Copy + Ord,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > BitAnd<Deterministic<A, T, In, Out>> for Deterministic<A, S, In, Out>
+{
+    type Output = Deterministic<A, (S, T), In, Out>;
+    /// Same as `intersection`.
+    /// ```rust
+    /// use vpa::*;
+    /// let shallow = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'))
+    ///     .determinize()
+    ///     .unwrap();
+    /// let deep = Nondeterministic::bounded_depth(5, (), &Range::unit('('), &Range::unit(')'))
+    ///     .determinize()
+    ///     .unwrap();
+    /// let both = deep & shallow;
+    /// assert!(both.accept("()(())".chars()).unwrap());
+    /// assert!(!both.accept("((()))".chars()).unwrap());
+    /// assert!(!both.accept("(()".chars()).unwrap());
+    /// ```
+    #[inline]
+    fn bitand(self, rhs: Deterministic<A, T, In, Out>) -> Self::Output {
+        self.intersection(&rhs)
+    }
+}
+
+/// Every symbol this automaton could ever push.
+#[inline]
+pub(crate) fn pushes<
+    A: 'static + fmt::Debug + Clone + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    In: 'static + fmt::Debug + Clone + Ord,
+    Out: 'static + fmt::Debug + Clone + Ord,
+>(
+    automaton: &Deterministic<A, S, In, Out>,
+) -> BTreeSet<S> {
+    automaton
+        .states
+        .iter()
+        .flat_map(|state| state.transitions.values().flat_map(Wildcard::values))
+        .filter_map(|edge| {
+            if let Edge::Call { push, .. } = *edge {
+                Some(push)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Every edge that could fire with this symbol on top of the stack (or an empty stack),
+/// each with the tokens that lead there (or `None` for any token).
+#[inline]
+#[allow(clippy::type_complexity)]
+fn entries<
+    'e,
+    A: 'static + fmt::Debug + Clone + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    In: 'static,
+    Out: 'static,
+>(
+    transitions: &'e CurryOpt<S, Wildcard<A, Return<Edge<A, S, usize, In, Out>>>>,
+    top: Option<&S>,
+) -> Vec<(Option<Range<A>>, &'e Edge<A, S, usize, In, Out>)> {
+    let keyed = top.map_or(transitions.none.as_ref(), |s| transitions.some.get(s));
+    transitions
+        .wildcard
+        .iter()
+        .chain(keyed)
+        .flat_map(|wild| match *wild {
+            Wildcard::Any(Return(ref edge)) => vec![(None, edge)],
+            Wildcard::Specific(ref v) => v
+                .iter()
+                .map(|&(ref range, Return(ref edge))| (Some(range.clone()), edge))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Pair up edges reading overlapping tokens and taking the same kind of stack action,
+/// or `None` if no pair survives.
+#[inline]
+#[allow(clippy::type_complexity)]
+fn pair_up<
+    A: 'static + fmt::Debug + Clone + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    T: 'static + fmt::Debug + Copy + Ord,
+    In: 'static + fmt::Debug + Clone + Ord,
+    Out: 'static + fmt::Debug + Clone + Ord,
+    F: FnMut((usize, usize)) -> usize,
+>(
+    lhs: &[(Option<Range<A>>, &Edge<A, S, usize, In, Out>)],
+    rhs: &[(Option<Range<A>>, &Edge<A, T, usize, In, Out>)],
+    visit: &mut F,
+) -> Option<Wildcard<A, Return<Edge<A, (S, T), usize, In, Out>>>> {
+    let mut specific = vec![];
+    for &(ref l_range, l_edge) in lhs {
+        for &(ref r_range, r_edge) in rhs {
+            let range = match (l_range, r_range) {
+                (&None, &None) => None,
+                (&Some(ref only), &None) | (&None, &Some(ref only)) => Some(only.clone()),
+                (&Some(ref l), &Some(ref r)) => match l.clamp_to(r) {
+                    Some(both) => Some(both),
+                    None => continue,
+                },
+            };
+            let edge = match (l_edge, r_edge) {
+                (
+                    &Edge::Call {
+                        dst: l_dst,
+                        ref call,
+                        push: l_push,
+                    },
+                    &Edge::Call {
+                        dst: r_dst,
+                        push: r_push,
+                        ..
+                    },
+                ) => Edge::Call {
+                    dst: visit((l_dst, r_dst)),
+                    call: call.clone(),
+                    push: (l_push, r_push),
+                },
+                (
+                    &Edge::Return {
+                        dst: l_dst,
+                        ref call,
+                    },
+                    &Edge::Return { dst: r_dst, .. },
+                ) => Edge::Return {
+                    dst: visit((l_dst, r_dst)),
+                    call: call.clone(),
+                },
+                (
+                    &Edge::Local {
+                        dst: l_dst,
+                        ref call,
+                    },
+                    &Edge::Local { dst: r_dst, .. },
+                ) => Edge::Local {
+                    dst: visit((l_dst, r_dst)),
+                    call: call.clone(),
+                },
+                _ => continue,
+            };
+            match range {
+                // Both sides matched anything, so neither has any other edge here
+                None => return Some(Wildcard::Any(Return(edge))),
+                Some(tokens) => specific.push((tokens, Return(edge))),
+            }
+        }
+    }
+    (!specific.is_empty()).then_some(Wildcard::Specific(specific))
+}
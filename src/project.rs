@@ -0,0 +1,93 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Regular over-approximations that ignore the stack entirely.
+
+use crate::{CurryOpt, Deterministic, Edge, Nondeterministic, Return, State, Wildcard};
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet};
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Finite automaton that treats every push and pop as a local transition, ignoring the stack entirely.
+    /// It accepts everything this automaton accepts (and possibly more), so if it accepts nothing, neither does this.
+    ///
+    /// The result is nondeterministic, since a state may act differently on the same token depending on the stack:
+    /// each state is split into one copy per stack condition it handles, and each edge leads to every copy of its destination.
+    #[inline]
+    #[must_use]
+    pub fn project_to_regular(&self) -> Nondeterministic<A, ()> {
+        // Index of each state's first copy, plus one past the end
+        let mut offsets = Vec::with_capacity(self.states.len().saturating_add(1));
+        let mut n = 0_usize;
+        for state in &self.states {
+            offsets.push(n);
+            n = n.saturating_add(state.transitions.len().max(1));
+        }
+        offsets.push(n);
+        let copies = |i: usize| -> BTreeSet<usize> {
+            (*get!(offsets, i)..*get!(offsets, i.saturating_add(1))).collect()
+        };
+
+        let mut states = Vec::with_capacity(n);
+        for state in &self.states {
+            let mut wilds = state.transitions.values().peekable();
+            if wilds.peek().is_none() {
+                states.push(State {
+                    transitions: CurryOpt::default(),
+                    accepting: state.accepting,
+                });
+            }
+            for wild in wilds {
+                let projected = match *wild {
+                    Wildcard::Any(Return(ref edge)) => {
+                        Wildcard::Any(Return(project_edge(edge, &copies)))
+                    }
+                    Wildcard::Specific(ref v) => Wildcard::Specific(
+                        v.iter()
+                            .map(|&(ref range, Return(ref edge))| {
+                                (range.clone(), Return(project_edge(edge, &copies)))
+                            })
+                            .collect(),
+                    ),
+                };
+                states.push(State {
+                    transitions: CurryOpt {
+                        wildcard: Some(projected),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: state.accepting,
+                });
+            }
+        }
+        Nondeterministic {
+            states,
+            initial: copies(self.initial),
+        }
+    }
+}
+
+/// Rewrite a push or pop as a local transition to every copy of its destination.
+#[inline]
+fn project_edge<
+    A: fmt::Debug + Ord,
+    S: fmt::Debug + Copy + Ord,
+    F: Fn(usize) -> BTreeSet<usize>,
+>(
+    edge: &Edge<A, S, usize>,
+    copies: &F,
+) -> Edge<A, (), BTreeSet<usize>> {
+    match *edge {
+        Edge::Call { dst, ref call, .. }
+        | Edge::Return { dst, ref call }
+        | Edge::Local { dst, ref call } => Edge::Local {
+            dst: copies(dst),
+            call: call.clone(),
+        },
+        Edge::Epsilon { dst } => Edge::Epsilon { dst: copies(dst) },
+        Edge::Phantom(..) => never!(),
+    }
+}
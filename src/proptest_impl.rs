@@ -0,0 +1,207 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Implementations of `proptest::arbitrary::Arbitrary`.
+
+use crate::*;
+use core::{convert::identity, fmt};
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    collection::{btree_map, vec},
+    prop_oneof,
+    strategy::{BoxedStrategy, Strategy as _},
+};
+use std::collections::BTreeSet;
+
+/// Most states in a generated automaton.
+const MAX_STATES: usize = 8;
+
+/// Most ranges (or stack symbols) in a generated transition table.
+const MAX_BRANCHES: usize = 4;
+
+impl<
+        A: 'static + fmt::Debug + Arbitrary + Clone + Discrete + Ord,
+        S: 'static + fmt::Debug + Arbitrary + Copy + Ord,
+    > Arbitrary for Nondeterministic<A, S>
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        (
+            vec(any::<State<A, S, BTreeSet<usize>>>(), 1..MAX_STATES),
+            any::<BTreeSet<usize>>(),
+        )
+            .prop_map(|(states, initial)| {
+                let mut nd = Self { states, initial };
+                let _ = nd.deabsurdify();
+                nd
+            })
+            .boxed()
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Arbitrary + Clone + Discrete + Ord,
+        S: 'static + fmt::Debug + Arbitrary + Copy + Ord,
+    > Arbitrary for Deterministic<A, S>
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        (
+            vec(any::<State<A, S, usize>>(), 1..MAX_STATES),
+            any::<usize>(),
+        )
+            .prop_map(|(states, initial)| {
+                let mut d = Self { states, initial };
+                let _ = d.deabsurdify();
+                d
+            })
+            .boxed()
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Arbitrary + Clone + Ord,
+        S: 'static + fmt::Debug + Arbitrary + Copy + Ord,
+        Ctrl: 'static + Arbitrary + Indices<A, S>,
+    > Arbitrary for State<A, S, Ctrl>
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        (
+            any::<CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl>>>>>(),
+            any::<bool>(),
+        )
+            .prop_map(|(transitions, accepting)| Self {
+                transitions,
+                accepting,
+            })
+            .boxed()
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Arbitrary + Clone + Ord,
+        S: 'static + fmt::Debug + Arbitrary + Copy + Ord,
+        Ctrl: 'static + Arbitrary + Indices<A, S>,
+    > Arbitrary for CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl>>>>
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        (
+            any::<Option<Wildcard<A, Return<Edge<A, S, Ctrl>>>>>(),
+            any::<Option<Wildcard<A, Return<Edge<A, S, Ctrl>>>>>(),
+            btree_map(
+                any::<S>(),
+                any::<Wildcard<A, Return<Edge<A, S, Ctrl>>>>(),
+                0..MAX_BRANCHES,
+            ),
+        )
+            .prop_map(|(wildcard, mut none, mut some)| {
+                // Same shape as the `quickcheck` generator: a wildcard excludes everything else.
+                if wildcard.is_some() {
+                    none = None;
+                }
+                if wildcard.is_some() || none.is_some() {
+                    some.clear();
+                }
+                Self {
+                    wildcard,
+                    none,
+                    some,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Arbitrary + Clone + Ord,
+        S: 'static + fmt::Debug + Arbitrary + Copy + Ord,
+        Ctrl: 'static + Arbitrary + Indices<A, S>,
+    > Arbitrary for Wildcard<A, Return<Edge<A, S, Ctrl>>>
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        prop_oneof![
+            any::<Return<Edge<A, S, Ctrl>>>().prop_map(Self::Any),
+            vec(
+                any::<(Range<A>, Return<Edge<A, S, Ctrl>>)>(),
+                0..MAX_BRANCHES
+            )
+            .prop_map(Self::Specific),
+        ]
+        .boxed()
+    }
+}
+
+impl<
+        A: 'static + fmt::Debug + Clone + Ord,
+        S: 'static + fmt::Debug + Arbitrary + Copy + Ord,
+        Ctrl: 'static + Arbitrary + Indices<A, S>,
+    > Arbitrary for Edge<A, S, Ctrl>
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        prop_oneof![
+            (any::<Ctrl>(), any::<Call<(), ()>>(), any::<S>())
+                .prop_map(|(dst, call, push)| Self::Call { dst, call, push }),
+            (any::<Ctrl>(), any::<Call<(), ()>>())
+                .prop_map(|(dst, call)| Self::Return { dst, call }),
+            (any::<Ctrl>(), any::<Call<(), ()>>())
+                .prop_map(|(dst, call)| Self::Local { dst, call }),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Call<(), ()> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        any::<String>()
+            .prop_map(|src| Self { ptr: identity, src })
+            .boxed()
+    }
+}
+
+impl<T: 'static + fmt::Debug + Arbitrary + Ord> Arbitrary for Range<T> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        any::<(T, T)>()
+            .prop_map(|(a, b)| {
+                if a < b {
+                    Self { first: a, last: b }
+                } else {
+                    Self { first: b, last: a }
+                }
+            })
+            .boxed()
+    }
+}
+
+impl<T: 'static + fmt::Debug + Arbitrary> Arbitrary for Return<T> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+    #[inline]
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        any::<T>().prop_map(Self).boxed()
+    }
+}
@@ -7,7 +7,11 @@
 //! Implementations of `quickcheck::Arbitrary`.
 
 use crate::*;
-use core::{convert::identity, fmt};
+use core::{
+    convert::identity,
+    fmt,
+    iter::{once, repeat_with},
+};
 use quickcheck::{Arbitrary, Gen};
 use std::collections::BTreeMap;
 
@@ -170,6 +174,7 @@ impl<
                     .shrink()
                     .map(|(dst, call, push)| Self::Call { dst, call, push }),
             ),
+            Self::Epsilon { ref dst } => Box::new(dst.shrink().map(|dst| Self::Epsilon { dst })),
             Self::Phantom(..) => never!(),
         }
     }
@@ -189,28 +194,75 @@ impl Arbitrary for Call<(), ()> {
     }
 }
 
+/// Values drawn to estimate the extremes of a type we know nothing else about.
+const EXTREME_POOL: usize = 16;
+
+/// Draw `EXTREME_POOL` arbitrary values.
+#[inline]
+fn pool<T: Arbitrary>(g: &mut Gen) -> Vec<T> {
+    repeat_with(|| T::arbitrary(g)).take(EXTREME_POOL).collect()
+}
+
 impl<T: Arbitrary + Ord> Arbitrary for Range<T> {
+    /// Deliberately mix in unit ranges and ranges touching the smallest and/or largest values,
+    /// where range algebra is most likely to go wrong.
+    /// The extremes are estimated as those of a pool of draws,
+    /// which hits the true bounds often since `quickcheck` favors them for primitives.
     #[inline]
     fn arbitrary(g: &mut Gen) -> Self {
         let (a, b) = <(T, T)>::arbitrary(g);
-        if a < b {
-            Self { first: a, last: b }
-        } else {
-            Self { first: b, last: a }
+        let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+        match *unwrap!(g.choose(&[0_u8, 1, 2, 3, 4])) {
+            0 => Self {
+                first: lo.clone(),
+                last: lo,
+            },
+            1 => {
+                let draws = pool::<T>(g);
+                Self {
+                    first: unwrap!(draws.iter().min()).clone(),
+                    last: unwrap!(draws.into_iter().max()),
+                }
+            }
+            2 => Self {
+                first: unwrap!(pool(g).into_iter().chain(once(lo)).min()),
+                last: hi,
+            },
+            3 => Self {
+                first: lo,
+                last: unwrap!(pool(g).into_iter().chain(once(hi)).max()),
+            },
+            _ => Self {
+                first: lo,
+                last: hi,
+            },
         }
     }
+    /// Head toward unit ranges first, then shrink both ends.
     #[inline]
     fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let units: Vec<_> = if self.first == self.last {
+            vec![]
+        } else {
+            vec![
+                Self::unit(self.first.clone()),
+                Self::unit(self.last.clone()),
+            ]
+        };
         Box::new(
-            (self.first.clone(), self.last.clone())
-                .shrink()
-                .map(|(a, b)| {
-                    if a < b {
-                        Self { first: a, last: b }
-                    } else {
-                        Self { first: b, last: a }
-                    }
-                }),
+            units
+                .into_iter()
+                .chain(
+                    (self.first.clone(), self.last.clone())
+                        .shrink()
+                        .map(|(a, b)| {
+                            if a < b {
+                                Self { first: a, last: b }
+                            } else {
+                                Self { first: b, last: a }
+                            }
+                        }),
+                ),
         )
     }
 }
@@ -6,7 +6,8 @@
 
 //! Range of values that, unlike `core::ops::Range...`, implements `Ord`.
 
-use core::cmp::Ordering;
+use core::{cmp::Ordering, str::FromStr};
+use std::collections::BTreeSet;
 
 /// Range of values that, unlike `core::ops::Range...`, implements `Ord`.
 #[allow(clippy::exhaustive_structs)]
@@ -31,10 +32,22 @@ impl<T: Ord> Range<T> {
         }
     }
 
+    /// Check if a value lies within this range, when which side it fell on doesn't matter.
+    /// ```rust
+    /// use vpa::*;
+    /// let digits = Range { first: '0', last: '9' };
+    /// assert!(digits.has(&'5'));
+    /// assert!(!digits.has(&'a'));
+    /// ```
+    #[inline(always)]
+    pub fn has(&self, value: &T) -> bool {
+        self.contains(value).is_eq()
+    }
+
     /// Check if any value lies in both of these ranges simultaneously.
     #[inline]
     pub fn overlap(&self, other: &Self) -> bool {
-        other.last >= self.first || other.first <= self.last
+        other.last >= self.first && other.first <= self.last
     }
 }
 
@@ -57,4 +70,385 @@ impl<T: Clone + Ord> Range<T> {
             last: first_and_last,
         }
     }
+
+    /// Intersect this range with a bounded universe of values, or `None` if they don't overlap.
+    #[inline]
+    #[must_use]
+    pub fn clamp_to(&self, universe: &Self) -> Option<Self> {
+        let first = self.first.clone().max(universe.first.clone());
+        let last = self.last.clone().min(universe.last.clone());
+        (first <= last).then_some(Self { first, last })
+    }
+}
+
+impl<T: Clone + Discrete + Ord> Range<T> {
+    /// Split this range around another into the parts strictly before it, inside it, and strictly after it.
+    #[inline]
+    #[must_use]
+    pub fn split_on(&self, other: &Self) -> (Option<Self>, Option<Self>, Option<Self>) {
+        let before = other.first.pred().and_then(|last| {
+            (self.first <= last).then(|| Self {
+                first: self.first.clone(),
+                last: last.min(self.last.clone()),
+            })
+        });
+        let after = other.last.succ().and_then(|first| {
+            (first <= self.last).then(|| Self {
+                first: first.max(self.first.clone()),
+                last: self.last.clone(),
+            })
+        });
+        (before, self.clamp_to(other), after)
+    }
+}
+
+/// Values with well-defined neighbors, so that gaps between ranges can be named exactly.
+///
+/// Range algebra that depends on adjacency (splitting, complements, simplification) requires this;
+/// values without neighbors, like floats, should use `HalfOpenRange` instead.
+pub trait Discrete: Sized {
+    /// Next value up, or `None` if this is the maximum.
+    fn succ(&self) -> Option<Self>;
+    /// Next value down, or `None` if this is the minimum.
+    fn pred(&self) -> Option<Self>;
+}
+
+/// Token type with a declared universe of every token that can occur.
+///
+/// Operations that depend on totality (like `complete_declared` and `complement_declared`) then needn't be told each time.
+/// To restrict the universe, e.g. to printable ASCII, wrap tokens in a newtype that declares a smaller one.
+pub trait Alphabet: Clone + Discrete + Ord {
+    /// Every token that can occur.
+    #[must_use]
+    fn universe() -> Range<Self>;
+}
+
+/// Implement `Discrete` and `Alphabet` for primitive integers.
+macro_rules! discrete {
+    ($($t:ty),*) => {$(
+        impl Discrete for $t {
+            #[inline(always)]
+            fn succ(&self) -> Option<Self> {
+                self.checked_add(1)
+            }
+            #[inline(always)]
+            fn pred(&self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+        }
+        impl Alphabet for $t {
+            #[inline(always)]
+            fn universe() -> Range<Self> {
+                Range {
+                    first: <$t>::MIN,
+                    last: <$t>::MAX,
+                }
+            }
+        }
+    )*};
+}
+
+discrete!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl Discrete for char {
+    #[inline]
+    fn succ(&self) -> Option<Self> {
+        match *self {
+            '\u{d7ff}' => Some('\u{e000}'),
+            c => char::from_u32(u32::from(c).checked_add(1)?),
+        }
+    }
+    #[inline]
+    fn pred(&self) -> Option<Self> {
+        match *self {
+            '\u{e000}' => Some('\u{d7ff}'),
+            c => char::from_u32(u32::from(c).checked_sub(1)?),
+        }
+    }
+}
+
+impl Alphabet for char {
+    #[inline(always)]
+    fn universe() -> Range<Self> {
+        Range {
+            first: '\0',
+            last: char::MAX,
+        }
+    }
+}
+
+impl Alphabet for () {
+    #[inline(always)]
+    fn universe() -> Range<Self> {
+        Range::unit(())
+    }
+}
+
+impl Alphabet for bool {
+    #[inline(always)]
+    fn universe() -> Range<Self> {
+        Range {
+            first: false,
+            last: true,
+        }
+    }
+}
+
+impl Discrete for () {
+    #[inline(always)]
+    fn succ(&self) -> Option<Self> {
+        None
+    }
+    #[inline(always)]
+    fn pred(&self) -> Option<Self> {
+        None
+    }
+}
+
+impl Discrete for bool {
+    #[inline(always)]
+    fn succ(&self) -> Option<Self> {
+        (!*self).then_some(true)
+    }
+    #[inline(always)]
+    fn pred(&self) -> Option<Self> {
+        self.then_some(false)
+    }
+}
+
+/// Gaps left in a bounded universe of values by a sorted list of ranges.
+#[inline]
+#[must_use]
+pub fn complement_ranges<T: Clone + Discrete + Ord>(
+    sorted: &[Range<T>],
+    universe: &Range<T>,
+) -> Vec<Range<T>> {
+    let mut gaps = vec![];
+    // Smallest value not yet covered, or `None` once we've run off the end of the universe.
+    let mut cursor = Some(universe.first.clone());
+    for range in sorted.iter().filter_map(|r| r.clamp_to(universe)) {
+        let Some(uncovered) = cursor.take() else {
+            break;
+        };
+        if uncovered < range.first {
+            if let Some(before) = range.first.pred() {
+                gaps.push(Range {
+                    first: uncovered.clone(),
+                    last: before,
+                });
+            }
+        }
+        cursor = if uncovered > range.last {
+            Some(uncovered)
+        } else {
+            range.last.succ()
+        };
+    }
+    if let Some(first) = cursor {
+        if first <= universe.last {
+            gaps.push(Range {
+                first,
+                last: universe.last.clone(),
+            });
+        }
+    }
+    gaps
+}
+
+/// Coarsest set of disjoint ranges, in order, such that every range in either list is a union of some of them.
+///
+/// This splits two overlapping sets of transitions into pieces that each lie entirely inside or outside every range.
+/// Values covered by neither list are left out.
+#[inline]
+#[must_use]
+#[allow(clippy::missing_panics_doc)]
+pub fn refine<T: Clone + Discrete + Ord>(a: &[Range<T>], b: &[Range<T>]) -> Vec<Range<T>> {
+    let ranges: Vec<&Range<T>> = a.iter().chain(b).collect();
+    let Some(top) = ranges.iter().map(|r| r.last.clone()).max() else {
+        return vec![];
+    };
+    // Every value at which some range starts or just stopped
+    let boundaries: BTreeSet<T> = ranges
+        .iter()
+        .map(|r| r.first.clone())
+        .chain(ranges.iter().filter_map(|r| r.last.succ()))
+        .collect();
+    let mut pieces = vec![];
+    let mut cuts = boundaries.into_iter().peekable();
+    while let Some(first) = cuts.next() {
+        if first > top {
+            break;
+        }
+        let last = cuts
+            .peek()
+            .map_or_else(|| top.clone(), |next| unwrap!(next.pred()));
+        // No range starts or stops inside this piece, so checking its first value checks all of it
+        if ranges.iter().any(|r| r.has(&first)) {
+            pieces.push(Range { first, last });
+        }
+    }
+    pieces
+}
+
+impl<T: Clone + Ord> From<T> for Range<T> {
+    #[inline(always)]
+    fn from(value: T) -> Self {
+        Self::unit(value)
+    }
+}
+
+/// Failure to parse a `Range<char>` from a string like `a-z`.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ParseRangeError {
+    /// Nothing to parse.
+    Empty,
+    /// Neither a single character nor two characters separated by `-`.
+    Malformed,
+    /// Last character comes before the first.
+    Reversed(char, char),
+}
+
+impl FromStr for Range<char> {
+    type Err = ParseRangeError;
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next(), chars.next(), chars.next()) {
+            (None, ..) => Err(ParseRangeError::Empty),
+            (Some(c), None, ..) => Ok(Self::unit(c)),
+            (Some(first), Some('-'), Some(last), None) => {
+                if first <= last {
+                    Ok(Self { first, last })
+                } else {
+                    Err(ParseRangeError::Reversed(first, last))
+                }
+            }
+            _ => Err(ParseRangeError::Malformed),
+        }
+    }
+}
+
+/// Range of values whose endpoints may each be either inclusive or exclusive.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct HalfOpenRange<T: Ord> {
+    /// First value.
+    pub first: T,
+    /// Whether `first` itself lies within this range.
+    pub first_inclusive: bool,
+    /// Last value.
+    pub last: T,
+    /// Whether `last` itself lies within this range.
+    pub last_inclusive: bool,
+}
+
+impl<T: Ord> HalfOpenRange<T> {
+    /// Range including `first` but excluding `last`, i.e. `[first, last)`.
+    #[inline]
+    pub const fn right_open(first: T, last: T) -> Self {
+        Self {
+            first,
+            first_inclusive: true,
+            last,
+            last_inclusive: false,
+        }
+    }
+
+    /// Check if a value lies within this range.
+    #[inline]
+    pub fn contains(&self, value: &T) -> Ordering {
+        match value.cmp(&self.first) {
+            Ordering::Less => return Ordering::Less,
+            Ordering::Equal if !self.first_inclusive => return Ordering::Less,
+            Ordering::Equal | Ordering::Greater => {}
+        }
+        match value.cmp(&self.last) {
+            Ordering::Greater => Ordering::Greater,
+            Ordering::Equal if !self.last_inclusive => Ordering::Greater,
+            Ordering::Equal | Ordering::Less => Ordering::Equal,
+        }
+    }
+
+    /// Check if this range contains no values at all (at least none we can name without a successor function).
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        match self.first.cmp(&self.last) {
+            Ordering::Less => false,
+            Ordering::Equal => !(self.first_inclusive && self.last_inclusive),
+            Ordering::Greater => true,
+        }
+    }
+
+    /// Check if any value lies in both of these ranges simultaneously.
+    #[inline]
+    pub fn overlap(&self, other: &Self) -> bool {
+        let (first, first_inclusive) = tighter_first(
+            (&self.first, self.first_inclusive),
+            (&other.first, other.first_inclusive),
+        );
+        let (last, last_inclusive) = tighter_last(
+            (&self.last, self.last_inclusive),
+            (&other.last, other.last_inclusive),
+        );
+        match first.cmp(last) {
+            Ordering::Less => true,
+            Ordering::Equal => first_inclusive && last_inclusive,
+            Ordering::Greater => false,
+        }
+    }
+}
+
+impl<T: Clone + Ord> HalfOpenRange<T> {
+    /// Range of every value lying in both of these ranges simultaneously, if any exist.
+    #[inline]
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let (first, first_inclusive) = tighter_first(
+            (&self.first, self.first_inclusive),
+            (&other.first, other.first_inclusive),
+        );
+        let (last, last_inclusive) = tighter_last(
+            (&self.last, self.last_inclusive),
+            (&other.last, other.last_inclusive),
+        );
+        let rtn = Self {
+            first: first.clone(),
+            first_inclusive,
+            last: last.clone(),
+            last_inclusive,
+        };
+        (!rtn.is_empty()).then_some(rtn)
+    }
+}
+
+impl<T: Ord> From<Range<T>> for HalfOpenRange<T> {
+    #[inline]
+    fn from(value: Range<T>) -> Self {
+        Self {
+            first: value.first,
+            first_inclusive: true,
+            last: value.last,
+            last_inclusive: true,
+        }
+    }
+}
+
+/// Of two lower bounds, whichever excludes more values.
+#[inline]
+fn tighter_first<'a, T: Ord>(lhs: (&'a T, bool), rhs: (&'a T, bool)) -> (&'a T, bool) {
+    match lhs.0.cmp(rhs.0) {
+        Ordering::Less => rhs,
+        Ordering::Equal => (lhs.0, lhs.1 && rhs.1),
+        Ordering::Greater => lhs,
+    }
+}
+
+/// Of two upper bounds, whichever excludes more values.
+#[inline]
+fn tighter_last<'a, T: Ord>(lhs: (&'a T, bool), rhs: (&'a T, bool)) -> (&'a T, bool) {
+    match lhs.0.cmp(rhs.0) {
+        Ordering::Less => lhs,
+        Ordering::Equal => (lhs.0, lhs.1 && rhs.1),
+        Ordering::Greater => rhs,
+    }
 }
@@ -0,0 +1,108 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Replayable logs of everything a run did, e.g. to attach to a bug report.
+
+use crate::{Execute, Execution, IllFormed, Run as _, Stack};
+use core::{cmp::Ordering, fmt};
+
+/// One thing that happened during a run.
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RunEvent<A, S, Ctrl> {
+    /// Read this token.
+    Consumed(A),
+    /// Pushed this symbol.
+    Pushed(S),
+    /// Popped this symbol.
+    Popped(S),
+    /// Control flow moved here.
+    MovedTo(Ctrl),
+    /// Input ended and was accepted.
+    Accepted,
+    /// Input was rejected, either partway through or once it ended.
+    Rejected,
+}
+
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        E: Execute<A, S>,
+        Iter: Iterator<Item = A>,
+        St: Stack<S>,
+    > Execution<'_, A, S, E, Iter, St>
+where
+    E::Ctrl: fmt::Debug,
+{
+    /// Run to completion, logging every token read, every push and pop, and every move,
+    /// then whether the input was accepted. A rejection partway through ends the log without reading any further.
+    /// If the parser turns out to be ill-formed, the log shows everything up to the error.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn record(
+        mut self,
+    ) -> (
+        Result<bool, IllFormed<A, S, E::Ctrl>>,
+        Vec<RunEvent<A, S, E::Ctrl>>,
+    ) {
+        let mut events = vec![];
+        loop {
+            let (depth, top) = (self.stack.len(), self.stack.last().copied());
+            let token = match self.next() {
+                None => break,
+                Some(Err(e)) => return (Err(e), events),
+                Some(Ok(token)) => token,
+            };
+            events.push(RunEvent::Consumed(token));
+            match self.stack.len().cmp(&depth) {
+                Ordering::Greater => events.extend(self.stack.last().map(|&s| RunEvent::Pushed(s))),
+                Ordering::Less => events.extend(top.map(RunEvent::Popped)),
+                Ordering::Equal => {}
+            }
+            let Ok(ref ctrl) = self.ctrl else {
+                events.push(RunEvent::Rejected);
+                return (Ok(false), events);
+            };
+            events.push(RunEvent::MovedTo(ctrl.clone()));
+        }
+        let accepted = matches!(self.ctrl, Err(true));
+        events.push(if accepted {
+            RunEvent::Accepted
+        } else {
+            RunEvent::Rejected
+        });
+        (Ok(accepted), events)
+    }
+}
+
+/// Run the tokens in this log again and return the index of the first event that differs from it,
+/// or `None` if the run played out exactly the same way.
+/// # Errors
+/// If the parser itself is ill-formed and tries to take a nonsensical action.
+#[inline]
+#[allow(clippy::type_complexity)]
+pub fn replay<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, E: Execute<A, S>>(
+    graph: &E,
+    events: &[RunEvent<A, S, E::Ctrl>],
+) -> Result<Option<usize>, IllFormed<A, S, E::Ctrl>>
+where
+    E::Ctrl: fmt::Debug,
+{
+    let tokens = events.iter().filter_map(|event| {
+        if let RunEvent::Consumed(ref token) = *event {
+            Some(token.clone())
+        } else {
+            None
+        }
+    });
+    let (result, again) = tokens.run(graph).record();
+    let _: bool = result?;
+    Ok(events
+        .iter()
+        .zip(&again)
+        .position(|(lhs, rhs)| lhs != rhs)
+        .or_else(|| (events.len() != again.len()).then(|| events.len().min(again.len()))))
+}
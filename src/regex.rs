@@ -0,0 +1,159 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Regular-expression extraction for automata that never touch the stack.
+
+use crate::{Deterministic, Edge, Range, Return, Wildcard};
+use core::fmt;
+
+impl<A: fmt::Debug + fmt::Display + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// If this automaton never calls or returns (i.e. it's really a finite automaton),
+    /// convert it to an equivalent regular expression via state elimination.
+    /// Returns `None` if any `Call` or `Return` edges exist.
+    /// An automaton accepting nothing at all yields `[]`, which matches nothing.
+    #[inline]
+    #[must_use]
+    pub fn to_regex(&self) -> Option<String> {
+        let n = self.states.len();
+        let (start, end) = (n, n.checked_add(1)?);
+        let size = n.checked_add(2)?;
+        // `None` is the empty language; `Some("")` is the empty string.
+        let mut table: Vec<Vec<Option<String>>> = vec![vec![None; size]; size];
+        *get_mut!(get_mut!(table, start), self.initial) = Some(String::new());
+        for (i, state) in self.states.iter().enumerate() {
+            if state
+                .transitions
+                .values()
+                .flat_map(Wildcard::values)
+                .any(|edge| matches!(*edge, Edge::Call { .. } | Edge::Return { .. }))
+            {
+                return None;
+            }
+            if state.accepting {
+                *get_mut!(get_mut!(table, i), end) = Some(String::new());
+            }
+            // Local-only automata always run with an empty stack.
+            for wild in state
+                .transitions
+                .wildcard
+                .iter()
+                .chain(&state.transitions.none)
+            {
+                match *wild {
+                    Wildcard::Any(Return(ref edge)) => {
+                        let cell = get_mut!(get_mut!(table, i), *edge.dst());
+                        *cell = Some(union(cell.take(), ".".to_owned()));
+                    }
+                    Wildcard::Specific(ref v) => {
+                        for &(ref range, Return(ref edge)) in v {
+                            let cell = get_mut!(get_mut!(table, i), *edge.dst());
+                            *cell = Some(union(cell.take(), range_to_regex(range)));
+                        }
+                    }
+                }
+            }
+        }
+        // Eliminate states in order, so only those after `k` (and the endpoints) remain.
+        for k in 0..n {
+            let looped = get!(get!(table, k), k)
+                .as_deref()
+                .map_or_else(String::new, star);
+            let outs = get!(table, k).clone();
+            for i in k.saturating_add(1)..size {
+                let Some(into) = get_mut!(get_mut!(table, i), k).take() else {
+                    continue;
+                };
+                let prefix = format!("{into}{looped}");
+                for (j, maybe_out) in outs.iter().enumerate().skip(k.saturating_add(1)) {
+                    let Some(ref out) = *maybe_out else {
+                        continue;
+                    };
+                    let path = format!("{prefix}{out}");
+                    let cell = get_mut!(get_mut!(table, i), j);
+                    *cell = Some(union(cell.take(), path));
+                }
+            }
+        }
+        Some(
+            get_mut!(get_mut!(table, start), end)
+                .take()
+                .unwrap_or_else(|| "[]".to_owned()),
+        )
+    }
+}
+
+/// Alternation of two regular expressions, skipping duplicates.
+#[inline]
+fn union(maybe_lhs: Option<String>, rhs: String) -> String {
+    match maybe_lhs {
+        None => rhs,
+        Some(lhs) if lhs == rhs => lhs,
+        Some(lhs) => format!("({lhs}|{rhs})"),
+    }
+}
+
+/// Kleene star, parenthesizing only if necessary.
+#[inline]
+fn star(s: &str) -> String {
+    if s.is_empty() {
+        String::new()
+    } else if is_atom(s) {
+        format!("{s}*")
+    } else {
+        format!("({s})*")
+    }
+}
+
+/// Whether a star would bind to this whole expression without parentheses.
+#[inline]
+fn is_atom(s: &str) -> bool {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next_back()) {
+        (Some(_), None) => true,
+        (Some('\\'), Some(_)) => s.chars().count() == 2,
+        (Some('('), Some(')')) => {
+            let mut depth: usize = 0;
+            let mut escaped = false;
+            for (i, c) in s.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '(' => depth = depth.saturating_add(1),
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                if depth == 0 {
+                    return i.checked_add(1) == Some(s.len());
+                }
+            }
+            false
+        }
+        (Some('['), Some(']')) => s.matches(']').count() == 1,
+        _ => false,
+    }
+}
+
+/// Regular expression matching exactly the tokens in this range.
+#[inline]
+fn range_to_regex<A: fmt::Display + Ord>(range: &Range<A>) -> String {
+    if range.first == range.last {
+        escape(&range.first)
+    } else {
+        format!("[{}-{}]", escape(&range.first), escape(&range.last))
+    }
+}
+
+/// Display a token, escaping any regex metacharacters.
+#[inline]
+fn escape<A: fmt::Display>(token: &A) -> String {
+    token.to_string().chars().fold(String::new(), |mut acc, c| {
+        if "\\.+*?()|[]{}^$-".contains(c) {
+            acc.push('\\');
+        }
+        acc.push(c);
+        acc
+    })
+}
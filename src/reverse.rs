@@ -0,0 +1,312 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Reversal of visibly pushdown automata and validation from both ends at once.
+
+use crate::{
+    Automaton, CurryOpt, Deterministic, Discrete, Edge, IllFormed, Nondeterministic, Range, Return,
+    State, Wildcard,
+};
+use core::{fmt, iter::once, mem::take};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Edge in a nondeterministic automaton.
+type NdEdge<A, S> = Edge<A, S, BTreeSet<usize>>;
+
+/// Reversed edges waiting to be placed in a state, keyed by a token range (or `None` for any token).
+type Pending<A, S> = Vec<(Option<Range<A>>, NdEdge<A, S>)>;
+
+/// Pending edges for one state, keyed by stack symbol:
+/// `None` is the wildcard, `Some(None)` the empty stack, and `Some(Some(s))` a specific symbol.
+type Keyed<A, S> = BTreeMap<Option<Option<S>>, Pending<A, S>>;
+
+impl<A: 'static + fmt::Debug + Clone + Discrete + Ord, S: 'static + fmt::Debug + Copy + Ord>
+    Nondeterministic<A, S>
+{
+    /// Automaton accepting exactly the reverses of inputs this one accepts.
+    /// Every edge flips direction, and since a reversed push is a pop, `Call` and `Return` swap.
+    /// This requires that calls never depend on the top of the stack and returns always do.
+    /// # Errors
+    /// If some call depends on the stack, some return doesn't, or reversed edges conflict.
+    #[inline]
+    pub fn reverse(self) -> Result<Self, IllFormed<A, S, BTreeSet<usize>>> {
+        let mut pending: Vec<Keyed<A, S>> = vec![BTreeMap::new(); self.states.len()];
+        for (src, state) in self.states.iter().enumerate() {
+            let keyed = state
+                .transitions
+                .wildcard
+                .iter()
+                .map(|wild| (None, wild))
+                .chain(state.transitions.none.iter().map(|wild| (Some(None), wild)))
+                .chain(
+                    state
+                        .transitions
+                        .some
+                        .iter()
+                        .map(|(&s, wild)| (Some(Some(s)), wild)),
+                );
+            for (key, wild) in keyed {
+                let tokens = match *wild {
+                    Wildcard::Any(Return(ref edge)) => vec![(None, edge)],
+                    Wildcard::Specific(ref v) => v
+                        .iter()
+                        .map(|&(ref range, Return(ref edge))| (Some(range.clone()), edge))
+                        .collect::<Vec<_>>(),
+                };
+                for (token, edge) in tokens {
+                    let dst = once(src).collect();
+                    let (rev_key, rev_edge) = match (key, edge) {
+                        (_, &Edge::Local { ref call, .. }) => (
+                            key,
+                            Edge::Local {
+                                dst,
+                                call: call.clone(),
+                            },
+                        ),
+                        (_, &Edge::Epsilon { .. }) => (key, Edge::Epsilon { dst }),
+                        (None, &Edge::Call { ref call, push, .. }) => (
+                            Some(Some(push)),
+                            Edge::Return {
+                                dst,
+                                call: call.clone(),
+                            },
+                        ),
+                        (Some(Some(s)), &Edge::Return { ref call, .. }) => (
+                            None,
+                            Edge::Call {
+                                dst,
+                                call: call.clone(),
+                                push: s,
+                            },
+                        ),
+                        // Popping an empty stack always rejects, so there's nothing to reverse
+                        (Some(None), &Edge::Return { .. }) => continue,
+                        (_, &Edge::Call { .. } | &Edge::Return { .. }) => {
+                            return Err(IllFormed::Irreversible)
+                        }
+                        (_, &Edge::Phantom(..)) => never!(),
+                    };
+                    for q in edge.dst() {
+                        insert(
+                            get_mut!(pending, *q).entry(rev_key).or_default(),
+                            token.clone(),
+                            rev_edge.clone(),
+                        )?;
+                    }
+                }
+            }
+        }
+        let states = pending
+            .into_iter()
+            .enumerate()
+            .map(|(i, keyed)| finish(keyed, self.initial.contains(&i)))
+            .collect::<Result<_, _>>()?;
+        Ok(Automaton {
+            states,
+            initial: self
+                .states
+                .iter()
+                .enumerate()
+                .filter_map(|(i, state)| state.accepting.then_some(i))
+                .collect(),
+        })
+    }
+}
+
+impl<A: 'static + fmt::Debug + Clone + Discrete + Ord, S: 'static + fmt::Debug + Copy + Ord>
+    Deterministic<A, S>
+{
+    /// Run forward over the first half of the input and the reversed automaton backward over the second,
+    /// then check whether they meet in the same state with the same stack.
+    /// The halves are independent, so for palindrome-like or matched-delimiter languages this halves the latency.
+    ///
+    /// This is sound only when the reversed automaton is itself deterministic,
+    /// since otherwise the backward half would have to track many stacks at once. In particular:
+    /// - calls must never depend on the top of the stack, and returns must always do so;
+    /// - there must be exactly one accepting state; and
+    /// - no two edges may lead into the same state on overlapping tokens.
+    /// # Errors
+    /// `IllFormed::Irreversible` if this automaton doesn't fit the above, or
+    /// any other error if the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn accept_bidirectional(&self, input: &[A]) -> Result<bool, IllFormed<A, S, usize>> {
+        let Ok(reversed) = self.clone().generalize().reverse() else {
+            return Err(IllFormed::Irreversible);
+        };
+        if !reversed.is_deterministic() {
+            return Err(IllFormed::Irreversible);
+        }
+        let Some(backward) = reversed.into_deterministic_unchecked() else {
+            return Err(IllFormed::Irreversible);
+        };
+        let (lhs, rhs) = input.split_at(input.len() >> 1_u32);
+        let Some(fwd) = self.run_det(lhs.iter())? else {
+            return Ok(false);
+        };
+        let Some(bwd) = backward.run_det(rhs.iter().rev())? else {
+            return Ok(false);
+        };
+        Ok(fwd == bwd)
+    }
+
+    /// Run without checking acceptance at the end. Return the final state and stack, or `None` if rejected along the way.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    fn run_det<'i, I: Iterator<Item = &'i A>>(
+        &self,
+        tokens: I,
+    ) -> Result<Option<(usize, Vec<S>)>, IllFormed<A, S, usize>>
+    where
+        A: 'i,
+    {
+        let mut ctrl = self.initial;
+        let mut stack = vec![];
+        for token in tokens {
+            ctrl = match self.step_det(ctrl, &mut stack, Some(token))? {
+                Ok(ok) => ok,
+                Err(_) => return Ok(None),
+            };
+        }
+        Ok(Some((ctrl, stack)))
+    }
+}
+
+/// Assemble a state from its pending reversed edges.
+/// # Errors
+/// If the wildcard stack key and a specific one overlap on some token.
+#[inline]
+#[allow(clippy::type_complexity)]
+fn finish<A: 'static + fmt::Debug + Clone + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    mut keyed: Keyed<A, S>,
+    accepting: bool,
+) -> Result<State<A, S, BTreeSet<usize>>, IllFormed<A, S, BTreeSet<usize>>> {
+    let wildcard = keyed.remove(&None);
+    if let Some(ref wild) = wildcard {
+        for others in keyed.values() {
+            if wild.iter().any(|&(ref lhs, _)| {
+                others.iter().any(|&(ref rhs, _)| match (lhs, rhs) {
+                    (&Some(ref l), &Some(ref r)) => l.overlap(r),
+                    _ => true,
+                })
+            }) {
+                return Err(IllFormed::Irreversible);
+            }
+        }
+    }
+    Ok(State {
+        transitions: CurryOpt {
+            wildcard: wildcard.map(to_wildcard),
+            none: keyed.remove(&Some(None)).map(to_wildcard),
+            some: keyed
+                .into_iter()
+                .filter_map(|(key, v)| Some((key??, to_wildcard(v))))
+                .collect(),
+        },
+        accepting,
+    })
+}
+
+/// Add a reversed edge, splitting ranges so that overlapping tokens lead to the union of their destinations.
+#[inline]
+fn insert<
+    A: 'static + fmt::Debug + Clone + Discrete + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+>(
+    v: &mut Pending<A, S>,
+    token: Option<Range<A>>,
+    edge: NdEdge<A, S>,
+) -> Result<(), IllFormed<A, S, BTreeSet<usize>>> {
+    let Some(range) = token else {
+        return match v.pop() {
+            None => {
+                v.push((None, edge));
+                Ok(())
+            }
+            Some((None, existing)) => {
+                v.push((None, join(existing, &edge)?));
+                Ok(())
+            }
+            Some(_) => Err(IllFormed::Irreversible),
+        };
+    };
+    let mut fresh = vec![range.clone()];
+    for (maybe_existing, old) in take(v) {
+        let Some(existing) = maybe_existing else {
+            return Err(IllFormed::Irreversible);
+        };
+        let (before, mid, after) = existing.split_on(&range);
+        v.extend(
+            before
+                .into_iter()
+                .chain(after)
+                .map(|r| (Some(r), old.clone())),
+        );
+        if let Some(shared) = mid {
+            fresh = fresh
+                .into_iter()
+                .flat_map(|piece| {
+                    let (b, _, a) = piece.split_on(&shared);
+                    b.into_iter().chain(a)
+                })
+                .collect();
+            v.push((Some(shared), join(old, &edge)?));
+        }
+    }
+    v.extend(fresh.into_iter().map(|r| (Some(r), edge.clone())));
+    Ok(())
+}
+
+/// Combine two edges of the same kind by taking the union of their destinations.
+#[inline]
+fn join<A: 'static + fmt::Debug + Clone + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    mut lhs: NdEdge<A, S>,
+    rhs: &NdEdge<A, S>,
+) -> Result<NdEdge<A, S>, IllFormed<A, S, BTreeSet<usize>>> {
+    let compatible = match (&lhs, rhs) {
+        (
+            &Edge::Call { ref call, push, .. },
+            &Edge::Call {
+                call: ref rcall,
+                push: rpush,
+                ..
+            },
+        ) => call == rcall && push == rpush,
+        (
+            &Edge::Return { ref call, .. },
+            &Edge::Return {
+                call: ref rcall, ..
+            },
+        )
+        | (
+            &Edge::Local { ref call, .. },
+            &Edge::Local {
+                call: ref rcall, ..
+            },
+        ) => call == rcall,
+        (&Edge::Epsilon { .. }, &Edge::Epsilon { .. }) => true,
+        _ => false,
+    };
+    if !compatible {
+        return Err(IllFormed::EdgeMergeConflict(lhs, rhs.clone()));
+    }
+    lhs.dst_mut().extend(rhs.dst().iter().copied());
+    Ok(lhs)
+}
+
+/// Turn pending edges into a wildcard.
+#[inline]
+fn to_wildcard<A: 'static + fmt::Debug + Clone + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    v: Pending<A, S>,
+) -> Wildcard<A, Return<NdEdge<A, S>>> {
+    let mut specific = vec![];
+    for (token, edge) in v {
+        match token {
+            None => return Wildcard::Any(Return(edge)),
+            Some(range) => specific.push((range, Return(edge))),
+        }
+    }
+    Wildcard::Specific(specific)
+}
@@ -6,31 +6,56 @@
 
 //! Trait to run a visibly pushdown automaton on an input sequence.
 
-use crate::{Execute, Execution};
-use core::fmt;
+use crate::{Execute, Execution, Stack};
+use core::{fmt, iter::Map};
 
 /// Trait to run a visibly pushdown automaton on an input sequence.
 pub trait Run<A: fmt::Debug + Ord>: Iterator<Item = A> + Sized {
     /// Run a visibly pushdown automaton on this input sequence.
-    #[must_use]
     fn run<S: fmt::Debug + Copy + Ord, E: Execute<A, S>>(
         self,
         graph: &E,
     ) -> Execution<'_, A, S, E, Self>;
+    /// Run a visibly pushdown automaton on this input sequence, starting from this stack
+    /// (e.g. an empty persistent stack, to make snapshots cheap).
+    fn run_on_stack<S: fmt::Debug + Copy + Ord, E: Execute<A, S>, St: Stack<S>>(
+        self,
+        graph: &E,
+        stack: St,
+    ) -> Execution<'_, A, S, E, Self, St>;
+    /// Run a visibly pushdown automaton on this input sequence, normalizing each token before lookup
+    /// (e.g. `char::to_ascii_lowercase` for case-insensitive matching).
+    /// The automaton's ranges must be expressed in normalized space: with lowercasing, `'A'` matches `'a'..='z'`, but nothing matches `'A'..='Z'`.
+    #[allow(clippy::type_complexity)]
+    fn run_normalized<S: fmt::Debug + Copy + Ord, E: Execute<A, S>>(
+        self,
+        graph: &E,
+        normalize: fn(A) -> A,
+    ) -> Execution<'_, A, S, E, Map<Self, fn(A) -> A>>;
 }
 
 impl<A: fmt::Debug + Ord, Iter: Iterator<Item = A>> Run<A> for Iter {
     #[inline]
-    #[must_use]
     fn run<S: fmt::Debug + Copy + Ord, E: Execute<A, S>>(
         self,
         graph: &E,
     ) -> Execution<'_, A, S, E, Self> {
-        Execution {
-            graph,
-            iter: self,
-            ctrl: Ok(graph.initial()),
-            stack: vec![],
-        }
+        self.run_on_stack(graph, vec![])
+    }
+    #[inline]
+    fn run_on_stack<S: fmt::Debug + Copy + Ord, E: Execute<A, S>, St: Stack<S>>(
+        self,
+        graph: &E,
+        stack: St,
+    ) -> Execution<'_, A, S, E, Self, St> {
+        Execution::new(graph, self, stack)
+    }
+    #[inline]
+    fn run_normalized<S: fmt::Debug + Copy + Ord, E: Execute<A, S>>(
+        self,
+        graph: &E,
+        normalize: fn(A) -> A,
+    ) -> Execution<'_, A, S, E, Map<Self, fn(A) -> A>> {
+        self.map(normalize).run(graph)
     }
 }
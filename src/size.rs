@@ -0,0 +1,65 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Rough size metrics for capacity planning.
+
+use crate::{Automaton, Edge, Indices, Return, State, Wildcard};
+use core::{fmt, mem::size_of};
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>
+    Automaton<A, S, Ctrl>
+{
+    /// Approximate heap footprint in bytes: the state vector, each state's transition tables, and every call's source string.
+    /// Allocator overhead and B-tree node slack aren't counted, and every destination index counts as one `usize`.
+    #[inline]
+    #[must_use]
+    pub fn approx_size_bytes(&self) -> usize {
+        let mut bytes = self
+            .states
+            .capacity()
+            .saturating_mul(size_of::<State<A, S, Ctrl>>());
+        for state in &self.states {
+            bytes = bytes.saturating_add(state.transitions.some.len().saturating_mul(
+                size_of::<S>().saturating_add(size_of::<Wildcard<A, Return<Edge<A, S, Ctrl>>>>()),
+            ));
+            for wild in state.transitions.values() {
+                if let Wildcard::Specific(ref v) = *wild {
+                    bytes = bytes.saturating_add(v.capacity().saturating_mul(size_of::<(
+                        A,
+                        A,
+                        Return<Edge<A, S, Ctrl>>,
+                    )>(
+                    )));
+                }
+                for edge in wild.values() {
+                    bytes = bytes
+                        .saturating_add(edge.call().map_or(0, |call| call.src.capacity()))
+                        .saturating_add(
+                            edge.dst().iter().count().saturating_mul(size_of::<usize>()),
+                        );
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Largest number of edges out of any one state.
+    #[inline]
+    #[must_use]
+    pub fn max_fanout(&self) -> usize {
+        self.states
+            .iter()
+            .map(|state| {
+                state
+                    .transitions
+                    .values()
+                    .map(|wild| wild.values().count())
+                    .sum()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
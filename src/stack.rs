@@ -0,0 +1,99 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Optimizations over the stack alphabet.
+
+use crate::{Automaton, Edge, Indices, Lookup as _, Return, Wildcard};
+use core::{fmt, mem::take};
+use std::collections::{BTreeMap, BTreeSet};
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S> + Ord>
+    Automaton<A, S, Ctrl>
+{
+    /// Unify stack symbols that no state can tell apart, then return a map from each removed symbol to its replacement.
+    /// Analogous to state minimization: start by assuming every symbol is interchangeable,
+    /// then split any two symbols that some state treats differently when popping them
+    /// (where "differently" is judged after renaming pushes according to the current guess),
+    /// until nothing changes.
+    #[inline]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn coalesce_stack_symbols(&mut self) -> BTreeMap<S, S> {
+        let mut symbols = BTreeSet::new();
+        for state in &self.states {
+            symbols.extend(state.transitions.some.keys().copied());
+            for edge in state.transitions.values().flat_map(Wildcard::values) {
+                if let Edge::Call { push, .. } = *edge {
+                    let _ = symbols.insert(push);
+                }
+            }
+        }
+
+        // Each symbol's class's representative (its smallest member)
+        let mut rep: BTreeMap<S, S> = symbols
+            .iter()
+            .map(|&s| (s, *unwrap!(symbols.first())))
+            .collect();
+        loop {
+            let mut classes: BTreeMap<_, S> = BTreeMap::new();
+            let mut refined = BTreeMap::new();
+            for &s in &symbols {
+                let signature = (
+                    *unwrap!(rep.get(&s)),
+                    self.states
+                        .iter()
+                        .map(|state| {
+                            state.transitions.some.get(&s).map(|wild| {
+                                let mut renamed = wild.clone();
+                                rename(&mut renamed, &rep);
+                                renamed
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                let _ = refined.insert(s, *classes.entry(signature).or_insert(s));
+            }
+            if refined == rep {
+                break;
+            }
+            rep = refined;
+        }
+
+        // Rewrite every state in terms of representatives only
+        for state in &mut self.states {
+            state.transitions.some = take(&mut state.transitions.some)
+                .into_iter()
+                .filter(|&(s, _)| *unwrap!(rep.get(&s)) == s)
+                .collect();
+            for wild in state
+                .transitions
+                .wildcard
+                .iter_mut()
+                .chain(&mut state.transitions.none)
+                .chain(state.transitions.some.values_mut())
+            {
+                rename(wild, &rep);
+            }
+        }
+        rep.into_iter().filter(|&(s, r)| s != r).collect()
+    }
+}
+
+/// Replace every pushed symbol with its representative.
+#[inline]
+fn rename<
+    A: 'static + fmt::Debug + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    Ctrl: Indices<A, S>,
+>(
+    wild: &mut Wildcard<A, Return<Edge<A, S, Ctrl>>>,
+    rep: &BTreeMap<S, S>,
+) {
+    wild.map_values(|edge| {
+        if let Edge::Call { ref mut push, .. } = *edge {
+            *push = *unwrap!(rep.get(&*push));
+        }
+    });
+}
@@ -6,7 +6,7 @@
 
 //! A state in a visibly pushdown automaton.
 
-use crate::{CurryOpt, Edge, IllFormed, Indices, Merge, Return, Wildcard};
+use crate::{CurryOpt, Discrete, Edge, IllFormed, Indices, Merge, Return, Wildcard};
 use core::{fmt, num::NonZeroUsize};
 
 /// A state in a visibly pushdown automaton.
@@ -16,15 +16,22 @@ pub struct State<
     A: 'static + fmt::Debug + Ord,
     S: 'static + fmt::Debug + Copy + Ord,
     Ctrl: fmt::Debug + Indices<A, S>,
+    In: 'static = (),
+    Out: 'static = (),
 > {
     /// State transitions.
-    pub transitions: CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl>>>>,
+    pub transitions: CurryOpt<S, Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>>,
     /// Whether an automaton in this state should accept when input ends.
     pub accepting: bool,
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Default
-    for State<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static,
+        Out: 'static,
+    > Default for State<A, S, Ctrl, In, Out>
 {
     #[inline]
     #[allow(clippy::default_trait_access)]
@@ -36,8 +43,13 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Defau
     }
 }
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices<A, S>> fmt::Debug
-    for State<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: fmt::Debug + Indices<A, S>,
+        In: 'static,
+        Out: 'static,
+    > fmt::Debug for State<A, S, Ctrl, In, Out>
 {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -49,11 +61,19 @@ impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, Ctrl: fmt::Debug + Indices
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>> Merge<A, S, Ctrl>
-    for State<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for State<A, S, Ctrl, In, Out>
 {
     #[inline]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
+        if self == *other {
+            return Ok(self);
+        }
         Ok(Self {
             transitions: self.transitions.merge(&other.transitions)?,
             accepting: self.accepting || other.accepting,
@@ -61,20 +81,44 @@ impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S
     }
 }
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord, Ctrl: Indices<A, S>>
-    State<A, S, Ctrl>
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > State<A, S, Ctrl, In, Out>
 {
     /// Check for structural errors.
     /// # Errors
     /// If this automaton is not well-formed.
     #[inline]
-    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl>> {
+    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
         self.transitions.check(size)
     }
 
+    /// Whether this state, sitting at index `self_index`, is a trap (a.k.a. sink):
+    /// non-accepting, with every transition leading right back to itself, so no run that reaches it can ever accept.
+    #[inline]
+    #[must_use]
+    pub fn is_trap(&self, self_index: usize) -> bool {
+        !self.accepting
+            && self
+                .transitions
+                .values()
+                .flat_map(Wildcard::values)
+                .all(|edge| edge.dst().iter().all(|i| i == self_index))
+    }
+
     /// Eliminate absurd relations like transitions to non-existing states.
+    /// # Errors
+    /// See `CurryOpt::deabsurdify`.
     #[inline]
-    pub fn deabsurdify(&mut self, size: NonZeroUsize) {
-        self.transitions.deabsurdify(size);
+    pub fn deabsurdify(&mut self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl, In, Out>>
+    where
+        A: Discrete,
+        Ctrl: Merge<A, S, Ctrl, In, Out>,
+    {
+        self.transitions.deabsurdify(size)
     }
 }
@@ -0,0 +1,61 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Feeding huge inputs to a deterministic automaton one chunk at a time.
+
+use crate::{Deterministic, Execute as _, IllFormed};
+use core::fmt;
+
+/// Everything a paused run needs to pick up where it left off.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunState<Ctrl, S> {
+    /// Current state, or whether the input was already decided early.
+    pub ctrl: Result<Ctrl, bool>,
+    /// Stack so far.
+    pub stack: Vec<S>,
+}
+
+impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+    /// Run that hasn't read anything yet, to be driven with `feed_chunk`.
+    #[inline]
+    #[must_use]
+    pub const fn start(&self) -> RunState<usize, S> {
+        RunState {
+            ctrl: Ok(self.initial),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Read one more chunk of input, picking up where the last one left off.
+    /// Once the input has been rejected, later chunks are ignored.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    pub fn feed_chunk(
+        &self,
+        state: &mut RunState<usize, S>,
+        chunk: &[A],
+    ) -> Result<(), IllFormed<A, S, usize>> {
+        for token in chunk {
+            let Ok(ctrl) = state.ctrl else {
+                return Ok(());
+            };
+            state.ctrl = self.step_det(ctrl, &mut state.stack, Some(token))?;
+        }
+        Ok(())
+    }
+
+    /// Whether everything fed so far would be accepted if the input ended here.
+    #[inline]
+    #[must_use]
+    pub fn finish(&self, state: &RunState<usize, S>) -> bool {
+        match state.ctrl {
+            Ok(ctrl) => self.would_accept(&ctrl, &state.stack),
+            Err(b) => b,
+        }
+    }
+}
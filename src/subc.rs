@@ -7,234 +7,467 @@
 //! Subset construction algorithm for determinizing nondeterministic automata.
 
 use crate::{
-    merge, CurryOpt, Deterministic, Edge, IllFormed, Nondeterministic, Return, State, Wildcard,
+    merge, Automaton, CurryOpt, Deterministic, Discrete, Edge, IllFormed, Indices, Merge,
+    Nondeterministic, Range, Return, State, Wildcard,
 };
-use core::{borrow::Borrow, fmt, iter::once};
+use core::{fmt, iter::once, mem::take};
 use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Deterministic<A, S> {
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, In: 'static, Out: 'static>
+    Deterministic<A, S, In, Out>
+{
     /// Generalize a deterministic automaton to an identical but nominally nondeterministic automaton.
     #[inline]
     #[must_use]
-    pub fn generalize(self) -> Nondeterministic<A, S> {
-        Nondeterministic {
+    pub fn generalize(self) -> Nondeterministic<A, S, In, Out> {
+        self.reindex(|i| once(i).collect())
+    }
+}
+
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Nondeterministic<A, S, In, Out>
+{
+    /// Whether every reachable configuration has at most one next state (and there's exactly one initial state),
+    /// i.e. whether this automaton is already deterministic in all but name.
+    #[inline]
+    #[must_use]
+    pub fn is_deterministic(&self) -> bool {
+        if self.initial.len() != 1 {
+            return false;
+        }
+        let mut seen = self.initial.clone();
+        let mut frontier: Vec<usize> = seen.iter().copied().collect();
+        while let Some(i) = frontier.pop() {
+            for edge in get!(self.states, i)
+                .transitions
+                .values()
+                .flat_map(Wildcard::values)
+            {
+                if matches!(*edge, Edge::Epsilon { .. }) || edge.dst().len() > 1 {
+                    return false;
+                }
+                frontier.extend(edge.dst().iter().filter(|&&j| seen.insert(j)));
+            }
+        }
+        true
+    }
+
+    /// Convert to a deterministic automaton without subset construction,
+    /// or `None` if there's no initial state at all.
+    /// Only meaningful if `is_deterministic` holds: edges to no state are dropped, and
+    /// any state with more than one initial or destination state keeps only the smallest.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn into_deterministic_unchecked(mut self) -> Option<Deterministic<A, S, In, Out>> {
+        if self.initial.is_empty() {
+            return None;
+        }
+        for state in &mut self.states {
+            state
+                .transitions
+                .retain_edges(|edge| !edge.dst().is_empty());
+        }
+        // Every set left is nonempty
+        Some(self.reindex(|set| unwrap!(set.first().copied())))
+    }
+}
+
+/// Generalize a deterministic automaton to an identical but nominally nondeterministic automaton.
+/// ```rust
+/// use core::iter::once;
+/// use vpa::*;
+/// let d: Deterministic<char, ()> = Automaton {
+///     states: vec![State {
+///         transitions: CurryOpt {
+///             wildcard: Some(Wildcard::Specific(vec![(
+///                 Range::unit('('),
+///                 Return(Edge::Call { dst: 0, call: call!(|x| x), push: () }),
+///             )])),
+///             none: None,
+///             some: once((
+///                 (),
+///                 Wildcard::Specific(vec![(
+///                     Range::unit(')'),
+///                     Return(Edge::Return { dst: 0, call: call!(|x| x) }),
+///                 )]),
+///             ))
+///             .collect(),
+///         },
+///         accepting: true,
+///     }],
+///     initial: 0,
+/// };
+/// let nd: Nondeterministic<char, ()> = d.clone().into();
+/// for input in ["", "()", "(()())", "(", ")(", "())"] {
+///     assert_eq!(nd.accept(input.chars()), Ok(d.accept(input.chars()).unwrap()));
+/// }
+/// ```
+impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord, In: 'static, Out: 'static>
+    From<Deterministic<A, S, In, Out>> for Nondeterministic<A, S, In, Out>
+{
+    #[inline(always)]
+    fn from(value: Deterministic<A, S, In, Out>) -> Self {
+        value.generalize()
+    }
+}
+
+impl<
+        A: fmt::Debug + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S>,
+        In: 'static,
+        Out: 'static,
+    > Automaton<A, S, Ctrl, In, Out>
+{
+    /// Translate every index (or set of indices) into another control type, leaving everything else untouched.
+    #[inline]
+    #[must_use]
+    pub fn reindex<C: Indices<A, S>, F: FnMut(Ctrl) -> C>(
+        self,
+        mut f: F,
+    ) -> Automaton<A, S, C, In, Out> {
+        Automaton {
             states: self
                 .states
                 .into_iter()
                 .map(|state| State {
-                    transitions: generalize_curry_opt(state.transitions),
+                    transitions: reindex_curry_opt(state.transitions, &mut f),
                     accepting: state.accepting,
                 })
                 .collect(),
-            initial: once(self.initial).collect(),
+            initial: f(self.initial),
         }
     }
 }
 
-/// Generalize a deterministic automaton to an identical but nominally nondeterministic automaton.
+/// Translate every index (or set of indices) into another control type.
 #[inline]
 #[allow(clippy::type_complexity)]
-fn generalize_curry_opt<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
-    d: CurryOpt<S, Wildcard<A, Return<Edge<A, S, usize>>>>,
-) -> CurryOpt<S, Wildcard<A, Return<Edge<A, S, BTreeSet<usize>>>>> {
+fn reindex_curry_opt<
+    A: 'static + fmt::Debug + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    C1: Indices<A, S>,
+    C2: Indices<A, S>,
+    In: 'static,
+    Out: 'static,
+    F: FnMut(C1) -> C2,
+>(
+    curry: CurryOpt<S, Wildcard<A, Return<Edge<A, S, C1, In, Out>>>>,
+    f: &mut F,
+) -> CurryOpt<S, Wildcard<A, Return<Edge<A, S, C2, In, Out>>>> {
     CurryOpt {
-        wildcard: d.wildcard.map(generalize_wildcard),
-        none: d.none.map(generalize_wildcard),
-        some: d
+        wildcard: curry.wildcard.map(|wild| reindex_wildcard(wild, f)),
+        none: curry.none.map(|none| reindex_wildcard(none, f)),
+        some: curry
             .some
             .into_iter()
-            .map(|(arg, etc)| (arg, generalize_wildcard(etc)))
+            .map(|(arg, etc)| (arg, reindex_wildcard(etc, f)))
             .collect(),
     }
 }
 
-/// Generalize a deterministic automaton to an identical but nominally nondeterministic automaton.
+/// Translate every index (or set of indices) into another control type.
 #[inline]
-fn generalize_wildcard<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
-    d: Wildcard<A, Return<Edge<A, S, usize>>>,
-) -> Wildcard<A, Return<Edge<A, S, BTreeSet<usize>>>> {
-    match d {
-        Wildcard::Any(Return(edge)) => Wildcard::Any(Return(generalize_edge(edge))),
+#[allow(clippy::type_complexity)]
+fn reindex_wildcard<
+    A: 'static + fmt::Debug + Ord,
+    S: 'static + fmt::Debug + Copy + Ord,
+    C1: Indices<A, S>,
+    C2: Indices<A, S>,
+    In: 'static,
+    Out: 'static,
+    F: FnMut(C1) -> C2,
+>(
+    wildcard: Wildcard<A, Return<Edge<A, S, C1, In, Out>>>,
+    f: &mut F,
+) -> Wildcard<A, Return<Edge<A, S, C2, In, Out>>> {
+    match wildcard {
+        Wildcard::Any(Return(edge)) => Wildcard::Any(Return(reindex_edge(edge, f))),
         Wildcard::Specific(v) => Wildcard::Specific(
             v.into_iter()
-                .map(|(k, Return(edge))| (k, Return(generalize_edge(edge))))
+                .map(|(k, Return(edge))| (k, Return(reindex_edge(edge, f))))
                 .collect(),
         ),
     }
 }
 
-/// Generalize a deterministic automaton to an identical but nominally nondeterministic automaton.
+/// Translate every index (or set of indices) into another control type.
 #[inline]
-fn generalize_edge<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord>(
-    d: Edge<A, S, usize>,
-) -> Edge<A, S, BTreeSet<usize>> {
-    match d {
+fn reindex_edge<
+    A: fmt::Debug + Ord,
+    S: fmt::Debug + Copy + Ord,
+    C1: Indices<A, S>,
+    C2: Indices<A, S>,
+    In: 'static,
+    Out: 'static,
+    F: FnMut(C1) -> C2,
+>(
+    edge: Edge<A, S, C1, In, Out>,
+    f: &mut F,
+) -> Edge<A, S, C2, In, Out> {
+    match edge {
         Edge::Call { dst, call, push } => Edge::Call {
-            dst: once(dst).collect(),
+            dst: f(dst),
             call,
             push,
         },
-        Edge::Return { dst, call } => Edge::Return {
-            dst: once(dst).collect(),
-            call,
-        },
-        Edge::Local { dst, call } => Edge::Local {
-            dst: once(dst).collect(),
-            call,
-        },
+        Edge::Return { dst, call } => Edge::Return { dst: f(dst), call },
+        Edge::Local { dst, call } => Edge::Local { dst: f(dst), call },
+        Edge::Epsilon { dst } => Edge::Epsilon { dst: f(dst) },
         Edge::Phantom(..) => never!(),
     }
 }
 
-/// Use an ordering on subsets to translate each subset into a specific state.
-#[inline]
-#[allow(clippy::type_complexity)]
-fn fix_indices_curry_opt<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
-    nd: CurryOpt<S, Wildcard<A, Return<Edge<A, S, BTreeSet<usize>>>>>,
-    ordering: &[BTreeSet<usize>],
-) -> CurryOpt<S, Wildcard<A, Return<Edge<A, S, usize>>>> {
-    CurryOpt {
-        wildcard: nd.wildcard.map(|wild| fix_indices_wildcard(wild, ordering)),
-        none: nd.none.map(|none| fix_indices_wildcard(none, ordering)),
-        some: nd
-            .some
-            .into_iter()
-            .map(|(arg, etc)| (arg, fix_indices_wildcard(etc, ordering)))
-            .collect(),
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + FromIterator<usize> + Merge<A, S, Ctrl> + Ord,
+    > Automaton<A, S, Ctrl>
+{
+    /// Determinize, then run the result on this input,
+    /// returning the deterministic automaton too so that later inputs needn't pay for subset construction again.
+    /// # Errors
+    /// If there's an ambiguity (which would have crashed the nondeterministic automaton anyway).
+    #[inline]
+    #[allow(clippy::missing_panics_doc, clippy::type_complexity)]
+    pub fn determinize_and_accept<I: IntoIterator<Item = A>>(
+        &self,
+        input: I,
+    ) -> Result<(Deterministic<A, S>, bool), IllFormed<A, S, Ctrl>> {
+        let d = self.determinize()?;
+        // `accept_det` can only fail on an epsilon transition, and subset construction leaves none
+        let accepted = match d.accept_det(input) {
+            Ok(b) => b,
+            Err(IllFormed::Epsilon) => return Err(IllFormed::Epsilon),
+            Err(..) => never!(),
+        };
+        Ok((d, accepted))
     }
 }
 
-/// Use an ordering on subsets to translate each subset into a specific state.
-#[inline]
-fn fix_indices_wildcard<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
-    nd: Wildcard<A, Return<Edge<A, S, BTreeSet<usize>>>>,
-    ordering: &[BTreeSet<usize>],
-) -> Wildcard<A, Return<Edge<A, S, usize>>> {
-    match nd {
-        Wildcard::Any(Return(edge)) => Wildcard::Any(Return(fix_indices_edge(edge, ordering))),
-        Wildcard::Specific(v) => Wildcard::Specific(
-            v.into_iter()
-                .map(|(k, Return(edge))| (k, Return(fix_indices_edge(edge, ordering))))
-                .collect(),
-        ),
+impl<
+        A: fmt::Debug + Clone + Ord,
+        S: fmt::Debug + Copy + Ord,
+        Ctrl: Indices<A, S> + FromIterator<usize> + Merge<A, S, Ctrl, In, Out> + Ord,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Automaton<A, S, Ctrl, In, Out>
+{
+    /// Subset construction algorithm for determinizing nondeterministic automata.
+    /// States unreachable from `initial` are pruned first, so they're never checked.
+    /// # Errors
+    /// If there's an ambiguity (which would have crashed the nondeterministic automaton anyway).
+    #[inline]
+    #[allow(
+        clippy::missing_panics_doc,
+        clippy::type_complexity,
+        clippy::unwrap_in_result
+    )]
+    pub fn determinize(
+        &self,
+    ) -> Result<Deterministic<A, S, In, Out>, IllFormed<A, S, Ctrl, In, Out>> {
+        // Epsilon transitions don't survive subset construction, so get rid of them first
+        if self.has_epsilon() {
+            return self.clone().into_deterministic();
+        }
+        self.determinize_epsilon_free(&mut |states| merge(states))
     }
-}
 
-/// Use an ordering on subsets to translate each subset into a specific state.
-#[inline]
-fn fix_indices_edge<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord>(
-    nd: Edge<A, S, BTreeSet<usize>>,
-    ordering: &[BTreeSet<usize>],
-) -> Edge<A, S, usize> {
-    match nd {
-        Edge::Call { dst, call, push } => Edge::Call {
-            dst: unwrap!(ordering.binary_search(&dst)),
-            call,
-            push,
-        },
-        Edge::Return { dst, call } => Edge::Return {
-            dst: unwrap!(ordering.binary_search(&dst)),
-            call,
-        },
-        Edge::Local { dst, call } => Edge::Local {
-            dst: unwrap!(ordering.binary_search(&dst)),
-            call,
-        },
-        Edge::Phantom(..) => never!(),
+    /// Same as `determinize`, but consuming this automaton,
+    /// so that epsilon transitions are eliminated and unreachable states pruned in place instead of on a copy,
+    /// and every state that ends up alone in its subset is moved into the result instead of cloned.
+    /// # Errors
+    /// If there's an ambiguity (which would have crashed the nondeterministic automaton anyway).
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn into_deterministic(
+        mut self,
+    ) -> Result<Deterministic<A, S, In, Out>, IllFormed<A, S, Ctrl, In, Out>> {
+        if self.has_epsilon() {
+            self.eliminate_reachable_epsilon()?;
+        }
+        self.into_deterministic_epsilon_free(&mut |states| merge(states))
     }
-}
 
-impl<A: fmt::Debug + Ord, S: fmt::Debug + Copy + Ord> Nondeterministic<A, S> {
-    /// Turn an iterator over indices into an iterator over references to states.
+    /// Same as `determinize`, but instead of failing when a subset of states pushes on some tokens
+    /// and leaves the stack alone on the same tokens, keep the push, drop the local edges on just the tokens
+    /// and stack conditions where the two meet, and record the conflict, so that the result is a best-effort automaton to inspect.
+    /// Any other conflict still fails.
+    /// # Errors
+    /// If there's an ambiguity other than a push against a local edge,
+    /// or if a local edge on any token at all would have to give up only some tokens.
     #[inline]
-    fn get_states<I: IntoIterator>(
+    #[allow(clippy::type_complexity)]
+    pub fn determinize_lenient(
         &self,
-        i: I,
-    ) -> impl Iterator<Item = &State<A, S, BTreeSet<usize>>>
+    ) -> Result<
+        (
+            Deterministic<A, S, In, Out>,
+            Vec<IllFormed<A, S, Ctrl, In, Out>>,
+        ),
+        IllFormed<A, S, Ctrl, In, Out>,
+    >
     where
-        I::Item: Borrow<usize>,
+        A: Discrete,
     {
-        i.into_iter().map(|j| get!(self.states, *j.borrow()))
+        let mut diagnostics = vec![];
+        let mut merger = |states: Vec<&State<A, S, Ctrl, In, Out>>| {
+            merge_preferring_calls(states.into_iter().cloned().collect(), &mut diagnostics)
+        };
+        let d = if self.has_epsilon() {
+            let mut eliminated = self.clone();
+            eliminated.eliminate_reachable_epsilon()?;
+            eliminated.into_deterministic_epsilon_free(&mut merger)?
+        } else {
+            self.determinize_epsilon_free(&mut merger)?
+        };
+        Ok((d, diagnostics))
     }
-}
 
-impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Nondeterministic<A, S> {
-    /// Subset construction algorithm for determinizing nondeterministic automata.
-    /// # Errors
-    /// If there's an ambiguity (which would have crashed the nondeterministic automaton anyway).
-    #[inline]
-    #[allow(clippy::missing_panics_doc, clippy::unwrap_in_result)]
-    pub fn determinize(&self) -> Result<Deterministic<A, S>, IllFormed<A, S, BTreeSet<usize>>> {
+    /// Prune unreachable states (as `determinize_epsilon_free` would have, so they're never checked),
+    /// then eliminate epsilon transitions.
+    #[allow(clippy::type_complexity)]
+    fn eliminate_reachable_epsilon(&mut self) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
+        let reachable = self.reachable();
+        if reachable.contains(&false) {
+            self.retain_states(&reachable);
+        }
+        self.eliminate_epsilon()
+    }
+
+    /// Subset construction on an automaton already known to have no epsilon transitions,
+    /// merging each subset of states with `merger`.
+    #[allow(clippy::type_complexity)]
+    fn determinize_epsilon_free<
+        F: FnMut(Vec<&State<A, S, Ctrl, In, Out>>) -> MergedState<A, S, Ctrl, In, Out>,
+    >(
+        &self,
+        merger: &mut F,
+    ) -> Result<Deterministic<A, S, In, Out>, IllFormed<A, S, Ctrl, In, Out>> {
+        // Unreachable states would never become subsets anyway, but they'd still be checked
+        let reachable = self.reachable();
+        if reachable.contains(&false) {
+            let mut pruned = self.clone();
+            pruned.retain_states(&reachable);
+            return pruned.into_deterministic_epsilon_free(merger);
+        }
+        let subsets_as_states = self.subsets(merger)?;
+        Ok(Self::assemble(subsets_as_states, &self.initial, |i| {
+            get!(self.states, i).clone()
+        }))
+    }
+
+    /// Same as `determinize_epsilon_free`, but pruning in place and moving lone states instead of cloning them.
+    #[allow(clippy::type_complexity)]
+    fn into_deterministic_epsilon_free<
+        F: FnMut(Vec<&State<A, S, Ctrl, In, Out>>) -> MergedState<A, S, Ctrl, In, Out>,
+    >(
+        mut self,
+        merger: &mut F,
+    ) -> Result<Deterministic<A, S, In, Out>, IllFormed<A, S, Ctrl, In, Out>> {
+        let reachable = self.reachable();
+        if reachable.contains(&false) {
+            self.retain_states(&reachable);
+        }
+        let subsets_as_states = self.subsets(merger)?;
+        // Each lone state is a key of its own exactly once, so it's never taken twice
+        Ok(Self::assemble(subsets_as_states, &self.initial, |i| {
+            take(get_mut!(self.states, i))
+        }))
+    }
+
+    /// Check this automaton, then associate each reachable subset of states with its merged state,
+    /// or with `None` if the subset holds a single state, which is its own merge.
+    #[allow(clippy::type_complexity)]
+    fn subsets<F: FnMut(Vec<&State<A, S, Ctrl, In, Out>>) -> MergedState<A, S, Ctrl, In, Out>>(
+        &self,
+        merger: &mut F,
+    ) -> Result<BTreeMap<Ctrl, Option<State<A, S, Ctrl, In, Out>>>, IllFormed<A, S, Ctrl, In, Out>>
+    {
         // Check that the source graph is well-formed
         self.check()?;
 
-        // Associate each subset of states with a merged state
-        let mut subsets_as_states: BTreeMap<BTreeSet<usize>, State<A, S, BTreeSet<usize>>> =
-            BTreeMap::new();
-        self.explore(&mut subsets_as_states, self.initial.clone())?;
+        let mut subsets_as_states = BTreeMap::new();
+        self.explore(&mut subsets_as_states, self.initial.clone(), merger)?;
+        Ok(subsets_as_states)
+    }
 
-        // Fix an ordering on those subsets
-        let mut ordering: Vec<BTreeSet<usize>> = subsets_as_states.keys().cloned().collect();
-        ordering.sort_unstable();
-        ordering.dedup();
+    /// Number the subsets found by `subsets` and point every edge at those numbers,
+    /// asking `lone` for the state behind each subset of a single state.
+    #[allow(clippy::type_complexity, clippy::unwrap_in_result)]
+    fn assemble<F: FnMut(usize) -> State<A, S, Ctrl, In, Out>>(
+        subsets_as_states: BTreeMap<Ctrl, Option<State<A, S, Ctrl, In, Out>>>,
+        initial: &Ctrl,
+        mut lone: F,
+    ) -> Deterministic<A, S, In, Out> {
+        // Fix an ordering on those subsets (already sorted and deduplicated, since they're map keys)
+        let ordering: Vec<Ctrl> = subsets_as_states.keys().cloned().collect();
 
-        Ok(Deterministic {
-            initial: unwrap!(ordering.binary_search(&self.initial)),
-            states: ordering
-                .iter()
-                .map(|set| {
-                    let State {
-                        transitions,
-                        accepting,
-                    } = unwrap!(subsets_as_states.remove(set));
-                    State {
-                        transitions: fix_indices_curry_opt(transitions, &ordering),
-                        accepting,
-                    }
-                })
-                .collect(),
-        })
+        let mut d =
+            Deterministic::with_capacity(ordering.len(), unwrap!(ordering.binary_search(initial)));
+        for (subset, merged) in subsets_as_states {
+            let State {
+                transitions,
+                accepting,
+            } = merged.unwrap_or_else(|| lone(unwrap!(subset.iter().next())));
+            d.states.push(State {
+                transitions: reindex_curry_opt(transitions, &mut |dst| {
+                    unwrap!(ordering.binary_search(&dst))
+                }),
+                accepting,
+            });
+        }
+        d
     }
 
-    /// Associate each subset of states with a merged state.
-    fn explore(
+    /// Associate each subset of states with a merged state (`None` for a lone state).
+    #[allow(clippy::type_complexity)]
+    fn explore<F: FnMut(Vec<&State<A, S, Ctrl, In, Out>>) -> MergedState<A, S, Ctrl, In, Out>>(
         &self,
-        subsets_as_states: &mut BTreeMap<BTreeSet<usize>, State<A, S, BTreeSet<usize>>>,
-        subset: BTreeSet<usize>,
-    ) -> Result<(), IllFormed<A, S, BTreeSet<usize>>> {
+        subsets_as_states: &mut BTreeMap<Ctrl, Option<State<A, S, Ctrl, In, Out>>>,
+        subset: Ctrl,
+        merger: &mut F,
+    ) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
         // Check if we've seen this subset already
-        let Entry::Vacant(entry) = subsets_as_states.entry(subset.clone()) else {
+        let Entry::Vacant(entry) = subsets_as_states.entry(subset) else {
             return Ok(());
         };
 
-        #[allow(clippy::print_stdout, clippy::use_debug)]
-        {
-            println!("Merging {subset:?}");
-        }
+        trace!(subset = ?entry.key(), "subset discovered");
 
-        // Merge this subset of states into one (most of the heavy lifting)
-        let mega_state: State<A, S, BTreeSet<usize>> = match merge(self.get_states(subset)) {
-            // If there were no states in the subset, reject immediately without a transition
-            None => State {
-                transitions: CurryOpt {
-                    wildcard: Some(Wildcard::Any(Return(Edge::Local {
-                        dst: BTreeSet::new(),
-                        call: call!(|x| x),
-                    }))),
-                    none: None,
-                    some: BTreeMap::new(),
-                },
-                accepting: false,
-            },
-            // If they successfully merged, return the merged state
-            Some(Ok(ok)) => ok,
-            // If they didn't successfully merge, something's wrong with the original automaton
-            Some(Err(e)) => return Err(e),
+        // A lone state is its own merge, so put off cloning it until we know whether we can move it instead
+        let lone = {
+            let mut indices = entry.key().iter();
+            match (indices.next(), indices.next()) {
+                (Some(only), None) => Some(only),
+                _ => None,
+            }
+        };
+        let merged = if let Some(only) = lone {
+            Err(get!(self.states, only))
+        } else {
+            // Merge this subset of states into one (most of the heavy lifting)
+            let states = entry.key().iter().map(|i| get!(self.states, i)).collect();
+            Ok(match merger(states) {
+                // If there were no states in the subset, reject immediately without a transition
+                None => State::default(),
+                // If they successfully merged, return the merged state
+                Some(Ok(ok)) => ok,
+                // If they didn't successfully merge, something's wrong with the original automaton
+                Some(Err(e)) => return Err(e),
+            })
         };
+        trace!(subset = ?entry.key(), "states merged");
 
         // Cache all possible next states
-        #[allow(clippy::needless_collect)] // <-- false positive: can't move `mega_state` below
-        let dsts: BTreeSet<BTreeSet<usize>> = mega_state
+        let dsts: BTreeSet<Ctrl> = merged
+            .as_ref()
+            .unwrap_or_else(|&only| only)
             .transitions
             .values()
             .flat_map(Wildcard::values)
@@ -242,10 +475,186 @@ impl<A: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord> Nondeterministic<A
             .collect();
 
         // Associate this subset of states with the merged state
-        let _ = entry.insert(mega_state);
+        let _ = entry.insert(merged.ok());
 
         // Recurse on all destinations
         dsts.into_iter()
-            .try_fold((), |(), dst| self.explore(subsets_as_states, dst))
+            .try_fold((), |(), dst| self.explore(subsets_as_states, dst, merger))
+    }
+}
+
+/// Result of merging a subset of states: `None` if the subset was empty.
+type MergedState<A, S, Ctrl, In, Out> =
+    Option<Result<State<A, S, Ctrl, In, Out>, IllFormed<A, S, Ctrl, In, Out>>>;
+
+/// Merge states, but whenever a push conflicts with a local edge, drop local edges where they meet pushes and try again,
+/// recording each conflict resolved this way.
+#[inline]
+#[allow(clippy::type_complexity)]
+fn merge_preferring_calls<
+    A: fmt::Debug + Clone + Discrete + Ord,
+    S: fmt::Debug + Copy + Ord,
+    Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+    In: 'static + fmt::Debug + Clone + Ord,
+    Out: 'static + fmt::Debug + Clone + Ord,
+>(
+    mut states: Vec<State<A, S, Ctrl, In, Out>>,
+    diagnostics: &mut Vec<IllFormed<A, S, Ctrl, In, Out>>,
+) -> MergedState<A, S, Ctrl, In, Out> {
+    loop {
+        let e = match merge(&states)? {
+            Ok(ok) => return Some(Ok(ok)),
+            Err(e) => e,
+        };
+        // `Some(tokens)` if this is a push against a local edge on those tokens (or on any token, if `None`)
+        let resolvable = if let IllFormed::RangeMergeConflict(ref shared, ref lhs, ref rhs) = e {
+            push_against_local(lhs, rhs).then(|| Some(shared.clone()))
+        } else if let IllFormed::EdgeMergeConflict(ref lhs, ref rhs) = e {
+            push_against_local(lhs, rhs).then_some(None)
+        } else {
+            None
+        };
+        // Give up if nothing could be dropped, or else this would loop forever
+        let Some(tokens) = resolvable else {
+            return Some(Err(e));
+        };
+        if drop_locals(&mut states, tokens.as_ref()) != Some(true) {
+            return Some(Err(e));
+        }
+        diagnostics.push(e);
+    }
+}
+
+/// Whether one of these edges pushes and the other is local.
+#[inline]
+const fn push_against_local<
+    A: fmt::Debug + Ord,
+    S: fmt::Debug + Copy + Ord,
+    Ctrl: Indices<A, S>,
+    In: 'static,
+    Out: 'static,
+>(
+    lhs: &Edge<A, S, Ctrl, In, Out>,
+    rhs: &Edge<A, S, Ctrl, In, Out>,
+) -> bool {
+    matches!(
+        (lhs, rhs),
+        (&Edge::Call { .. }, &Edge::Local { .. }) | (&Edge::Local { .. }, &Edge::Call { .. })
+    )
+}
+
+/// Wherever a local edge and a push in any of these states match the same token (among `tokens`, or any token if `None`)
+/// under stack conditions that can hold at once, remove that token from the local edge, and leave everything else alone.
+/// Return whether anything changed, or `None` (changing nothing) if a local edge on any token at all
+/// would have to lose only some tokens, since there's no universe to carve them out of.
+#[inline]
+#[allow(clippy::type_complexity)]
+fn drop_locals<
+    A: fmt::Debug + Clone + Discrete + Ord,
+    S: fmt::Debug + Copy + Ord,
+    Ctrl: Indices<A, S>,
+    In: 'static + fmt::Debug + Clone + Ord,
+    Out: 'static + fmt::Debug + Clone + Ord,
+>(
+    states: &mut Vec<State<A, S, Ctrl, In, Out>>,
+    tokens: Option<&Range<A>>,
+) -> Option<bool> {
+    // Every push as its stack condition (`None` for any stack) and the tokens it's taken on (`None` for any token),
+    // cut down to `tokens`
+    let mut pushes: Vec<(Option<Option<S>>, Option<Range<A>>)> = vec![];
+    for state in &*states {
+        let transitions = &state.transitions;
+        for (condition, wild) in transitions
+            .wildcard
+            .iter()
+            .map(|wild| (None, wild))
+            .chain(transitions.none.iter().map(|wild| (Some(None), wild)))
+            .chain(
+                transitions
+                    .some
+                    .iter()
+                    .map(|(&arg, wild)| (Some(Some(arg)), wild)),
+            )
+        {
+            match *wild {
+                Wildcard::Any(Return(Edge::Call { .. })) => {
+                    pushes.push((condition, tokens.cloned()));
+                }
+                Wildcard::Any(..) => {}
+                Wildcard::Specific(ref v) => {
+                    for &(ref range, Return(ref edge)) in v {
+                        if !matches!(*edge, Edge::Call { .. }) {
+                            continue;
+                        }
+                        let shared = tokens.map_or(Some(range.clone()), |t| range.clamp_to(t));
+                        pushes.extend(shared.map(|r| (condition, Some(r))));
+                    }
+                }
+            }
+        }
+    }
+
+    // Work on a copy, since an `Any` that can't be carved means leaving everything as it was
+    let mut pruned = states.clone();
+    for state in &mut pruned {
+        let transitions = &mut state.transitions;
+        for (condition, wild) in transitions
+            .wildcard
+            .iter_mut()
+            .map(|wild| (None, wild))
+            .chain(transitions.none.iter_mut().map(|wild| (Some(None), wild)))
+            .chain(
+                transitions
+                    .some
+                    .iter_mut()
+                    .map(|(&arg, wild)| (Some(Some(arg)), wild)),
+            )
+        {
+            // Tokens on which a push can fire alongside this entry
+            let against: Vec<&Option<Range<A>>> = pushes
+                .iter()
+                .filter(|&&(other, _)| condition.is_none() || other.is_none() || other == condition)
+                .map(|&(_, ref range)| range)
+                .collect();
+            match *wild {
+                Wildcard::Any(Return(Edge::Local { .. })) => {
+                    if against.iter().any(|range| range.is_some()) {
+                        return None;
+                    }
+                    if !against.is_empty() {
+                        *wild = Wildcard::Specific(vec![]);
+                    }
+                }
+                Wildcard::Any(..) => {}
+                Wildcard::Specific(ref mut v) => {
+                    *v = take(v)
+                        .into_iter()
+                        .flat_map(|(range, Return(edge))| {
+                            let mut kept = vec![range];
+                            if matches!(edge, Edge::Local { .. }) {
+                                for push in &against {
+                                    kept = push.as_ref().map_or_else(Vec::new, |shadow| {
+                                        kept.iter()
+                                            .flat_map(|piece| {
+                                                let (before, _, after) = piece.split_on(shadow);
+                                                before.into_iter().chain(after)
+                                            })
+                                            .collect()
+                                    });
+                                }
+                            }
+                            kept.into_iter()
+                                .map(move |k| (k, Return(edge.clone())))
+                                .collect::<Vec<_>>()
+                        })
+                        .collect();
+                }
+            }
+        }
+    }
+    if pruned == *states {
+        return Some(false);
     }
+    *states = pruned;
+    Some(true)
 }
@@ -14,12 +14,46 @@
     unreachable_code
 )]
 
+use crate::*;
+use core::iter::once;
+
+/// Deterministic parser accepting exactly the balanced parenthesizations.
+fn parens() -> Deterministic<char, ()> {
+    Automaton {
+        states: vec![State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Specific(vec![(
+                    Range::unit('('),
+                    Return(Edge::Call {
+                        dst: 0,
+                        call: call!(|x| x),
+                        push: (),
+                    }),
+                )])),
+                none: None,
+                some: once((
+                    (),
+                    Wildcard::Specific(vec![(
+                        Range::unit(')'),
+                        Return(Edge::Return {
+                            dst: 0,
+                            call: call!(|x| x),
+                        }),
+                    )]),
+                ))
+                .collect(),
+            },
+            accepting: true,
+        }],
+        initial: 0,
+    }
+}
+
 #[cfg(feature = "quickcheck")]
 mod prop {
     use crate::*;
-    use core::fmt;
-    use quickcheck::{quickcheck, TestResult};
-    use std::panic;
+    use core::{fmt, iter::repeat_with};
+    use quickcheck::{quickcheck, Arbitrary, TestResult};
 
     #[inline]
     fn determinization_implies_no_runtime_errors<
@@ -50,6 +84,33 @@ mod prop {
         TestResult::passed()
     }
 
+    #[test]
+    fn arbitrary_ranges_cover_edge_cases() {
+        let mut g = quickcheck::Gen::new(100);
+        let ranges: Vec<Range<u8>> = repeat_with(|| Arbitrary::arbitrary(&mut g))
+            .take(1000)
+            .collect();
+        let count = |f: fn(&Range<u8>) -> bool| ranges.iter().filter(|r| f(r)).count();
+        assert!(count(|r| r.first == r.last) > 100);
+        assert!(count(|r| r.first == u8::MIN && r.last == u8::MAX) > 10);
+        assert!(count(|r| r.first == u8::MIN) > 50);
+        assert!(count(|r| r.last == u8::MAX) > 50);
+        assert!(count(|r| r.first < r.last) > 300);
+    }
+
+    #[test]
+    fn arbitrary_ranges_shrink_to_units_first() {
+        let shrunk: Vec<_> = Range {
+            first: 3_u8,
+            last: 9,
+        }
+        .shrink()
+        .take(2)
+        .collect();
+        assert_eq!(shrunk, vec![Range::unit(3), Range::unit(9)]);
+        assert!(Range::unit(0_u8).shrink().next().is_none());
+    }
+
     quickcheck! {
         fn range_overlap_commutativity(a: Range<u8>, b: Range<u8>) -> bool {
             a.overlap(&b) == b.overlap(&a)
@@ -80,13 +141,46 @@ mod prop {
             if !d.deabsurdify() {
                 return true;
             }
-            panic::catch_unwind(|| d.clone().generalize().determinize()).is_ok()
+            d.generalize().determinize().is_ok()
         }
 
         fn subset_construction_bool_bool(nd: Nondeterministic<bool, bool>, input: Vec<bool>) -> TestResult {
             subset_construction(&nd, &input)
         }
 
+        fn bitset_determinization_identical(nd: Nondeterministic<bool, bool>) -> bool {
+            let mut nd = nd;
+            if !nd.deabsurdify() {
+                return true;
+            }
+            let bits = nd.clone().reindex(|set| set.into_iter().collect::<BitSet>());
+            nd.determinize().ok() == bits.determinize().ok()
+        }
+
+        fn deabsurdify_splits_overlapping_ranges(nd: Nondeterministic<u8, bool>) -> bool {
+            let mut nd = nd;
+            if !nd.deabsurdify() {
+                return true;
+            }
+            nd.check().is_ok()
+                && nd.states.iter().all(|state| {
+                    state.transitions.values().all(|wild| match *wild {
+                        Wildcard::Any(..) => true,
+                        Wildcard::Specific(ref v) => v.iter().enumerate().all(|(i, &(ref lhs, _))| {
+                            v.iter().take(i).all(|&(ref rhs, _)| !lhs.overlap(rhs))
+                        }),
+                    })
+                })
+        }
+
+        fn step_det_identical(d: Deterministic<bool, bool>, input: Vec<bool>) -> bool {
+            let mut d = d;
+            if !d.deabsurdify() {
+                return true;
+            }
+            d.accept(input.iter().copied()) == d.accept_det(input)
+        }
+
         // fn subset_construction_bool_u8(nd: Nondeterministic<bool, u8>, input: Vec<bool>) -> TestResult {
         //     subset_construction(&nd, &input)
         // }
@@ -101,6 +195,31 @@ mod prop {
     }
 }
 
+#[cfg(feature = "proptest")]
+mod strategies {
+    use crate::*;
+    use proptest::{prop_assert_eq, prop_assume, proptest, test_runner::Config};
+
+    proptest! {
+        // Only about a quarter of arbitrary automata determinize, so skipping the rest takes more than the default allows
+        #![proptest_config(Config {
+            max_global_rejects: 4096,
+            ..Config::default()
+        })]
+
+        #[test]
+        fn subset_construction_bool_bool(nd: Nondeterministic<bool, bool>, input: Vec<bool>) {
+            let maybe_d = nd.determinize();
+            prop_assume!(maybe_d.is_ok());
+            let d = maybe_d.unwrap();
+            prop_assert_eq!(
+                nd.accept(input.iter().copied()).unwrap(),
+                d.accept(input.iter().copied()).unwrap(),
+            );
+        }
+    }
+}
+
 mod reduced {
     use crate::*;
     use core::{fmt, iter};
@@ -112,7 +231,10 @@ mod reduced {
     //     IdenticalBehavior,
     // }
 
-    fn deabsurdify_implies_check<K: fmt::Debug + Clone + Ord, S: fmt::Debug + Copy + Ord>(
+    fn deabsurdify_implies_check<
+        K: fmt::Debug + Clone + Discrete + Ord,
+        S: fmt::Debug + Copy + Ord,
+    >(
         mut nd: Nondeterministic<K, S>,
     ) {
         let pre = nd.clone();
@@ -131,24 +253,19 @@ mod reduced {
         nd: &Nondeterministic<K, S>,
         input: &[K],
     ) {
-        println!("Original nondeterministic automaton: {nd:?}");
-        println!();
         let Ok(d) = nd.determinize() else {
             return;
         };
-        println!("Successfully determinized: {d:?}");
-        println!();
-        println!("Running the nondeterministic automaton...");
         let mut run_nd = input.iter().copied().run(nd);
-        println!("    {run_nd:?}");
-        while let Some(r) = run_nd.next() {
+        for r in &mut run_nd {
             if let Err(e) = r {
-                panic!("Nondeterministic automaton panicked (but determinization didn't): {e:?}");
+                panic!("Nondeterministic automaton failed at runtime (but determinization didn't): {e:?} in {nd:?}, determinized as {d:?}");
             }
-            println!("    {run_nd:?}");
         }
-        let _ = run_nd.ctrl.unwrap_err();
-        panic!("Ran to completion: is something wrong with this test?");
+        assert!(
+            run_nd.ctrl.is_err(),
+            "Ran out of input without a verdict: {run_nd:?} in {nd:?}",
+        );
     }
 
     // #[inline]
@@ -386,4 +503,3167 @@ mod reduced {
             &[],
         );
     }
+
+    #[test]
+    fn determinization_implies_no_runtime_errors_2() {
+        let nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        for input in ["", "()", "(()", "((()))", ")", "x"] {
+            determinization_implies_no_runtime_errors(&nd, &input.chars().collect::<Vec<_>>());
+        }
+    }
+}
+
+mod range {
+    use crate::*;
+    use core::cmp::Ordering;
+
+    #[test]
+    fn half_open_excludes_last() {
+        let r = HalfOpenRange::right_open(2_u8, 5);
+        assert_eq!(r.contains(&1), Ordering::Less);
+        assert_eq!(r.contains(&2), Ordering::Equal);
+        assert_eq!(r.contains(&4), Ordering::Equal);
+        assert_eq!(r.contains(&5), Ordering::Greater);
+        assert_eq!(r.contains(&6), Ordering::Greater);
+    }
+
+    #[test]
+    fn half_open_touching_ranges_do_not_overlap() {
+        let lhs = HalfOpenRange::right_open(0_u8, 5);
+        let rhs = HalfOpenRange::right_open(5_u8, 10);
+        assert!(!lhs.overlap(&rhs));
+        assert!(!rhs.overlap(&lhs));
+        assert_eq!(lhs.intersect(&rhs), None);
+        let closed = HalfOpenRange::from(Range {
+            first: 4_u8,
+            last: 5,
+        });
+        assert!(lhs.overlap(&closed));
+        assert_eq!(
+            lhs.intersect(&closed),
+            Some(HalfOpenRange::right_open(4, 5))
+        );
+    }
+
+    #[test]
+    fn from_value_is_unit() {
+        assert_eq!(Range::from('a'), Range::unit('a'));
+    }
+
+    #[test]
+    fn float_containment() {
+        let unit = Range {
+            first: TotalF64(0.0),
+            last: TotalF64(1.0),
+        };
+        assert_eq!(unit.contains(&TotalF64(-0.5)), Ordering::Less);
+        assert_eq!(unit.contains(&TotalF64(0.0)), Ordering::Equal);
+        assert_eq!(unit.contains(&TotalF64(0.5)), Ordering::Equal);
+        assert_eq!(unit.contains(&TotalF64(1.0)), Ordering::Equal);
+        assert_eq!(unit.contains(&TotalF64(1.5)), Ordering::Greater);
+        let everything = Range {
+            first: TotalF64(f64::NEG_INFINITY),
+            last: TotalF64(f64::INFINITY),
+        };
+        assert!(unit.contains(&TotalF64(f64::NAN)).is_ne());
+        assert!(everything.contains(&TotalF64(f64::NAN)).is_ne());
+        assert!(everything.contains(&TotalF64(-f64::NAN)).is_ne());
+        assert!(Range::unit(TotalF32(0.5)).contains(&0.5.into()).is_eq());
+    }
+
+    #[test]
+    fn complement_within_alphabet() {
+        let covered = [
+            Range {
+                first: 'a',
+                last: 'c',
+            },
+            Range {
+                first: 'f',
+                last: 'h',
+            },
+        ];
+        let universe = Range {
+            first: 'a',
+            last: 'z',
+        };
+        assert_eq!(
+            complement_ranges(&covered, &universe),
+            vec![
+                Range {
+                    first: 'd',
+                    last: 'e',
+                },
+                Range {
+                    first: 'i',
+                    last: 'z',
+                },
+            ],
+        );
+        assert_eq!(
+            Range {
+                first: 'X',
+                last: 'c',
+            }
+            .clamp_to(&universe),
+            Some(Range {
+                first: 'a',
+                last: 'c',
+            }),
+        );
+        assert_eq!(complement_ranges(&[universe], &universe), vec![]);
+    }
+
+    #[test]
+    fn refine_overlapping_ranges() {
+        fn range<T: Ord>(first: T, last: T) -> Range<T> {
+            Range { first, last }
+        }
+        assert_eq!(
+            refine(&[range('a', 'm')], &[range('f', 'z')]),
+            vec![range('a', 'e'), range('f', 'm'), range('n', 'z')],
+        );
+        assert_eq!(
+            refine(&[range('a', 'c'), range('x', 'z')], &[range('b', 'b')]),
+            vec![
+                range('a', 'a'),
+                range('b', 'b'),
+                range('c', 'c'),
+                range('x', 'z')
+            ],
+        );
+        assert_eq!(
+            refine(&[range(0_u8, 255)], &[range(255, 255)]),
+            vec![range(0, 254), range(255, 255)],
+        );
+        assert_eq!(refine::<char>(&[], &[]), vec![]);
+    }
+
+    #[test]
+    fn parse_dash_separated() {
+        assert_eq!(
+            "a-z".parse(),
+            Ok(Range {
+                first: 'a',
+                last: 'z',
+            }),
+        );
+        assert_eq!("x".parse(), Ok(Range::unit('x')));
+        assert_eq!("-".parse(), Ok(Range::unit('-')));
+        assert_eq!("".parse::<Range<char>>(), Err(ParseRangeError::Empty));
+        assert_eq!(
+            "z-a".parse::<Range<char>>(),
+            Err(ParseRangeError::Reversed('z', 'a')),
+        );
+        for malformed in ["ab", "a-", "a-zz", "a+z"] {
+            assert_eq!(
+                malformed.parse::<Range<char>>(),
+                Err(ParseRangeError::Malformed),
+            );
+        }
+    }
+
+    #[test]
+    fn discrete_neighbors_at_boundaries() {
+        assert_eq!(u8::MAX.succ(), None);
+        assert_eq!(u8::MAX.pred(), Some(254_u8));
+        assert_eq!(0_u8.pred(), None);
+        assert_eq!(i32::MIN.pred(), None);
+        assert_eq!(i32::MAX.succ(), None);
+        assert_eq!((-1_i32).succ(), Some(0_i32));
+        assert_eq!('\u{10FFFF}'.succ(), None);
+        assert_eq!('\u{10FFFF}'.pred(), Some('\u{10FFFE}'));
+        assert_eq!('\u{0}'.pred(), None);
+        assert_eq!('\u{d7ff}'.succ(), Some('\u{e000}'));
+        assert_eq!('\u{e000}'.pred(), Some('\u{d7ff}'));
+        assert_eq!('c'.succ(), Some('d'));
+    }
+}
+
+mod wildcard {
+    use crate::*;
+    use core::num::NonZeroUsize;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn full_coverage_simplifies_to_any() {
+        let universe = Range {
+            first: '\u{0}',
+            last: '\u{10FFFF}',
+        };
+        let edge: Return<Edge<char, (), usize>> = Return(Edge::Local {
+            dst: 0,
+            call: call!(|x| x),
+        });
+        let mut whole = Wildcard::Specific(vec![(universe, edge.clone())]);
+        whole.simplify(&universe);
+        assert_eq!(whole, Wildcard::Any(edge.clone()));
+
+        let mut split = Wildcard::Specific(vec![
+            (
+                Range {
+                    first: 'a',
+                    last: '\u{10FFFF}',
+                },
+                edge.clone(),
+            ),
+            (
+                Range {
+                    first: '\u{0}',
+                    last: '`',
+                },
+                edge.clone(),
+            ),
+        ]);
+        split.simplify(&universe);
+        assert_eq!(split, Wildcard::Any(edge.clone()));
+
+        let partial = Wildcard::Specific(vec![(Range::unit('a'), edge)]);
+        let mut simplified = partial.clone();
+        simplified.simplify(&universe);
+        assert_eq!(simplified, partial);
+    }
+
+    #[test]
+    fn remove_from_any_is_refused() {
+        let edge: Return<Edge<char, (), usize>> = Return(Edge::Local {
+            dst: 0,
+            call: call!(|x| x),
+        });
+        let mut any = Wildcard::Any(edge.clone());
+        assert!(!any.remove(&Range::unit('a')));
+        assert_eq!(any, Wildcard::Any(edge.clone()));
+        let mut specific = Wildcard::Specific(vec![(Range::unit('a'), edge)]);
+        assert!(!specific.remove(&Range::unit('b')));
+        assert!(specific.remove(&Range::unit('a')));
+        assert_eq!(specific, Wildcard::Specific(vec![]));
+    }
+
+    #[test]
+    fn deabsurdify_carves_out_only_what_the_wildcard_shadows() {
+        let local = |dst: usize| {
+            Return(Edge::<char, (), usize>::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let mut partial = CurryOpt {
+            wildcard: Some(Wildcard::Specific(vec![(
+                Range {
+                    first: 'a',
+                    last: 'c',
+                },
+                local(0),
+            )])),
+            none: Some(Wildcard::Specific(vec![(
+                Range {
+                    first: 'b',
+                    last: 'z',
+                },
+                local(1),
+            )])),
+            some: BTreeMap::from([((), Wildcard::Specific(vec![(Range::unit('b'), local(1))]))]),
+        };
+        let size = NonZeroUsize::new(2).unwrap();
+        assert_eq!(partial.deabsurdify(size), Ok(()));
+        assert_eq!(
+            partial.none,
+            Some(Wildcard::Specific(vec![(
+                Range {
+                    first: 'd',
+                    last: 'z'
+                },
+                local(1)
+            )])),
+        );
+        assert_eq!(partial.some, BTreeMap::new());
+        assert_eq!(partial.check(size), Ok(()));
+
+        // An `Any` can't lose just the shadowed tokens, so it's left alone and reported
+        let mut any = CurryOpt {
+            none: Some(Wildcard::Any(local(1))),
+            some: BTreeMap::new(),
+            ..partial
+        };
+        assert_eq!(
+            any.deabsurdify(size),
+            Err(IllFormed::CurryOptMergeConflict(
+                None,
+                Some(Range {
+                    first: 'a',
+                    last: 'c'
+                }),
+            )),
+        );
+        assert_eq!(any.none, Some(Wildcard::Any(local(1))));
+    }
+
+    #[test]
+    fn deabsurdify_merges_overlapping_edges_unless_they_conflict() {
+        let range = |first, last| Range { first, last };
+        let merging = |dst| {
+            Return(Edge::<char, (), BTreeSet<usize>>::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let mut mergeable = Wildcard::Specific(vec![
+            (range('a', 'm'), merging(BTreeSet::from([0]))),
+            (range('h', 'z'), merging(BTreeSet::from([1]))),
+        ]);
+        mergeable.deabsurdify(NonZeroUsize::new(2));
+        assert_eq!(
+            mergeable,
+            Wildcard::Specific(vec![
+                (range('h', 'm'), merging(BTreeSet::from([0, 1]))),
+                (range('a', 'g'), merging(BTreeSet::from([0]))),
+                (range('n', 'z'), merging(BTreeSet::from([1]))),
+            ]),
+        );
+
+        let local = |dst| {
+            Return(Edge::<char, (), usize>::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let mut conflicting = Wildcard::Specific(vec![
+            (range('a', 'm'), local(0)),
+            (range('h', 'z'), local(1)),
+        ]);
+        conflicting.deabsurdify(NonZeroUsize::new(2));
+        assert_eq!(
+            conflicting,
+            Wildcard::Specific(vec![
+                (range('a', 'm'), local(0)),
+                (range('n', 'z'), local(1))
+            ]),
+        );
+    }
+
+    #[test]
+    fn check_rejects_overlap_but_not_disorder() {
+        let size = NonZeroUsize::MIN;
+        let local = |dst| {
+            Return(Edge::<char, (), usize>::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let unsorted = Wildcard::Specific(vec![
+            (
+                Range {
+                    first: 'x',
+                    last: 'z',
+                },
+                local(0),
+            ),
+            (
+                Range {
+                    first: 'a',
+                    last: 'c',
+                },
+                local(0),
+            ),
+        ]);
+        assert_eq!(unsorted.check(size), Ok(()));
+        let overlapping = Wildcard::Specific(vec![
+            (
+                Range {
+                    first: 'x',
+                    last: 'z',
+                },
+                local(0),
+            ),
+            (
+                Range {
+                    first: 'a',
+                    last: 'y',
+                },
+                local(0),
+            ),
+        ]);
+        assert_eq!(
+            overlapping.check(size),
+            Err(IllFormed::VecMergeConflict(Range {
+                first: 'x',
+                last: 'y'
+            })),
+        );
+    }
+}
+
+mod merge {
+    use crate::*;
+    use core::mem::discriminant;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn local<Ctrl: Indices<char, ()>>(dst: Ctrl) -> Return<Edge<char, (), Ctrl>> {
+        Return(Edge::Local {
+            dst,
+            call: call!(|x| x),
+        })
+    }
+
+    #[test]
+    fn merging_locals_stays_local() {
+        let lhs: Edge<char, (), BTreeSet<usize>> = Edge::Local {
+            dst: BTreeSet::from([0]),
+            call: call!(|x| x),
+        };
+        let rhs = Edge::Local {
+            dst: BTreeSet::from([1]),
+            call: call!(|x| x),
+        };
+        assert_eq!(
+            lhs.merge(&rhs),
+            Ok(Edge::Local {
+                dst: BTreeSet::from([0, 1]),
+                call: call!(|x| x),
+            }),
+        );
+    }
+
+    #[test]
+    fn same_variant_merges_preserve_variant() {
+        let dst = |i| BTreeSet::from([i]);
+        let pairs: [(Edge<char, (), BTreeSet<usize>>, _); 4] = [
+            (
+                Edge::Call {
+                    dst: dst(0),
+                    call: call!(|x| x),
+                    push: (),
+                },
+                Edge::Call {
+                    dst: dst(1),
+                    call: call!(|x| x),
+                    push: (),
+                },
+            ),
+            (
+                Edge::Return {
+                    dst: dst(0),
+                    call: call!(|x| x),
+                },
+                Edge::Return {
+                    dst: dst(1),
+                    call: call!(|x| x),
+                },
+            ),
+            (
+                Edge::Local {
+                    dst: dst(0),
+                    call: call!(|x| x),
+                },
+                Edge::Local {
+                    dst: dst(1),
+                    call: call!(|x| x),
+                },
+            ),
+            (Edge::Epsilon { dst: dst(0) }, Edge::Epsilon { dst: dst(1) }),
+        ];
+        for (lhs, rhs) in pairs {
+            let variant = discriminant(&lhs);
+            let merged = lhs.merge(&rhs).unwrap();
+            assert_eq!(discriminant(&merged), variant, "{merged:?}");
+            assert_eq!(merged.dst(), &BTreeSet::from([0, 1]));
+        }
+    }
+
+    #[test]
+    fn identical_ranges_merge_their_edges() {
+        let range = Range {
+            first: 'a',
+            last: 'm',
+        };
+        assert_eq!(
+            vec![(range, local(BTreeSet::from([0])))]
+                .merge(&vec![(range, local(BTreeSet::from([1])))]),
+            Ok(vec![(range, local(BTreeSet::from([0, 1])))]),
+        );
+        assert_eq!(
+            vec![(range, local(0))].merge(&vec![(range, local(1))]),
+            Err(IllFormed::RangeMergeConflict(range, local(0).0, local(1).0)),
+        );
+    }
+
+    #[test]
+    fn merging_more_never_turns_success_into_failure() {
+        let first = |last| Range { first: 'a', last };
+        let map = BTreeMap::from([((), Wildcard::Specific(vec![(first('m'), local(0))]))]);
+        let more = BTreeMap::from([(
+            (),
+            Wildcard::Specific(vec![
+                (first('m'), local(0)),
+                (
+                    Range {
+                        first: 'n',
+                        last: 'z',
+                    },
+                    local(1),
+                ),
+            ]),
+        )]);
+        assert_eq!(map.clone().merge(&map), Ok(map.clone()));
+        assert_eq!(map.clone().merge(&more), Ok(more));
+        let other = BTreeMap::from([((), Wildcard::Specific(vec![(first('m'), local(1))]))]);
+        assert_eq!(
+            map.merge(&other),
+            Err(IllFormed::MapMergeConflict(
+                (),
+                Wildcard::Specific(vec![(first('m'), local(0))]),
+                Wildcard::Specific(vec![(first('m'), local(1))]),
+            )),
+        );
+
+        // Ranges that already overlapped on one side aren't a new conflict
+        let overlapping = vec![(first('m'), local(0)), (first('z'), local(0))];
+        let mut extended = overlapping.clone();
+        extended.push((Range::unit('0'), local(1)));
+        assert_eq!(
+            overlapping.clone().merge(&overlapping),
+            Ok(overlapping.clone())
+        );
+        assert_eq!(overlapping.merge(&extended), Ok(extended));
+    }
+
+    #[test]
+    fn partial_overlap_needs_splitting() {
+        let lhs = vec![(
+            Range {
+                first: 'a',
+                last: 'm',
+            },
+            local(0),
+        )];
+        let rhs = vec![(
+            Range {
+                first: 'h',
+                last: 'z',
+            },
+            local(0),
+        )];
+        assert_eq!(
+            lhs.merge(&rhs),
+            Err(IllFormed::VecMergeConflict(Range {
+                first: 'h',
+                last: 'm'
+            })),
+        );
+    }
+
+    #[test]
+    fn compatible_overlap_merges() {
+        let same = Wildcard::Specific(vec![(
+            Range {
+                first: 'a',
+                last: 'm',
+            },
+            local(0),
+        )])
+        .merge_splitting(&Wildcard::Specific(vec![(
+            Range {
+                first: 'h',
+                last: 'z',
+            },
+            local(0),
+        )]));
+        assert_eq!(
+            same,
+            Ok(Wildcard::Specific(vec![
+                (
+                    Range {
+                        first: 'a',
+                        last: 'm'
+                    },
+                    local(0)
+                ),
+                (
+                    Range {
+                        first: 'n',
+                        last: 'z'
+                    },
+                    local(0)
+                ),
+            ])),
+        );
+
+        let lhs = Wildcard::Specific(vec![(
+            Range {
+                first: 'a',
+                last: 'm',
+            },
+            local(BTreeSet::from([0])),
+        )]);
+        let rhs = Wildcard::Specific(vec![(
+            Range {
+                first: 'h',
+                last: 'z',
+            },
+            local(BTreeSet::from([1])),
+        )]);
+        assert_eq!(
+            lhs.merge_splitting(&rhs),
+            Ok(Wildcard::Specific(vec![
+                (
+                    Range {
+                        first: 'a',
+                        last: 'g'
+                    },
+                    local(BTreeSet::from([0]))
+                ),
+                (
+                    Range {
+                        first: 'h',
+                        last: 'm'
+                    },
+                    local(BTreeSet::from([0, 1]))
+                ),
+                (
+                    Range {
+                        first: 'n',
+                        last: 'z'
+                    },
+                    local(BTreeSet::from([1]))
+                ),
+            ])),
+        );
+    }
+
+    #[test]
+    fn incompatible_overlap_reports_both_edges() {
+        let lhs = Wildcard::Specific(vec![(
+            Range {
+                first: 'a',
+                last: 'm',
+            },
+            local(0),
+        )]);
+        let rhs = Wildcard::Specific(vec![(
+            Range {
+                first: 'h',
+                last: 'z',
+            },
+            local(1),
+        )]);
+        assert_eq!(
+            lhs.merge_splitting(&rhs),
+            Err(IllFormed::RangeMergeConflict(
+                Range {
+                    first: 'h',
+                    last: 'm'
+                },
+                local(0).0,
+                local(1).0,
+            )),
+        );
+    }
+}
+
+mod curry_opt {
+    use crate::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn len_counts_every_entry() {
+        let local: Wildcard<char, Return<Edge<char, u8, usize>>> =
+            Wildcard::Any(Return(Edge::Local {
+                dst: 0,
+                call: call!(|x| x),
+            }));
+        let mut curry = CurryOpt::default();
+        assert!(curry.is_empty());
+        assert_eq!(curry.len(), 0);
+        curry.wildcard = Some(local.clone());
+        drop(curry.some.insert(1_u8, local.clone()));
+        drop(curry.some.insert(2_u8, local.clone()));
+        assert!(!curry.is_empty());
+        assert_eq!(curry.len(), 3);
+        curry.none = Some(local);
+        assert_eq!(curry.len(), 4);
+    }
+
+    #[test]
+    fn only_new_overlaps_conflict() {
+        let local = |dst| -> Wildcard<char, Return<Edge<char, u8, usize>>> {
+            Wildcard::Any(Return(Edge::Local {
+                dst,
+                call: call!(|x| x),
+            }))
+        };
+        // Already shadowed on one side, so merging it with anything compatible shouldn't fail
+        let shadowed = CurryOpt {
+            wildcard: Some(local(0)),
+            none: Some(local(1)),
+            some: BTreeMap::new(),
+        };
+        let mut more = shadowed.clone();
+        drop(more.some.insert(1_u8, local(0)));
+        assert_eq!(shadowed.clone().merge(&shadowed), Ok(shadowed.clone()));
+        assert_eq!(shadowed.merge(&more), Ok(more));
+
+        // But a wildcard from one side shadowing transitions from the other still does
+        let keyed = CurryOpt {
+            wildcard: None,
+            none: Some(local(0)),
+            some: BTreeMap::new(),
+        };
+        let wild = CurryOpt {
+            wildcard: Some(local(0)),
+            none: None,
+            some: BTreeMap::new(),
+        };
+        assert_eq!(
+            keyed.merge(&wild),
+            Err(IllFormed::CurryOptMergeConflict(None, None)),
+        );
+    }
+}
+
+mod automaton {
+    use super::parens;
+    use crate::*;
+    use core::iter::once;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn trim_removes_dead_and_unreachable_states() {
+        let mut d = parens();
+        // Reachable on 'x' but can never accept
+        d.states.push(State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Any(Return(Edge::Local {
+                    dst: 1,
+                    call: call!(|x| x),
+                }))),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: false,
+        });
+        // Accepting but unreachable
+        d.states.push(State {
+            transitions: CurryOpt::default(),
+            accepting: true,
+        });
+        if let Some(Wildcard::Specific(ref mut v)) =
+            d.states.first_mut().unwrap().transitions.wildcard
+        {
+            v.push((
+                Range::unit('x'),
+                Return(Edge::Local {
+                    dst: 1,
+                    call: call!(|x| x),
+                }),
+            ));
+        }
+        let pre = d.clone();
+        d.trim();
+        assert_eq!(d.check(), Ok(()));
+        assert_eq!(d.states.len(), 1);
+        for input in ["", "()", "(())()", "(x)", "x", "(()", ")("] {
+            assert_eq!(
+                d.accept(input.chars()),
+                pre.accept(input.chars()),
+                "{input:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn map_calls_rewrites_only_calls() {
+        let mut d = parens();
+        let pre = d.clone();
+        d.map_calls(|call| call.src = "log".to_owned());
+        let mut srcs = vec![];
+        for state in &d.states {
+            for edge in state.transitions.values().flat_map(Wildcard::values) {
+                srcs.push(edge.call().unwrap().src.clone());
+            }
+        }
+        assert_eq!(srcs, ["log", "log"]);
+        assert_ne!(d, pre);
+        d.map_calls(|call| call.src = "|x| x".to_owned());
+        assert_eq!(d, pre);
+    }
+
+    #[test]
+    fn acceptance_conventions() {
+        let d = parens();
+        assert_eq!(d.accept_configuration("((".chars(), true), Ok(false));
+        assert_eq!(d.accept_configuration("((".chars(), false), Ok(true));
+        assert_eq!(d.accept("((".chars()), Ok(false));
+        assert_eq!(d.accept_configuration("(())".chars(), true), Ok(true));
+        assert_eq!(d.accept_configuration("())".chars(), false), Ok(false));
+    }
+
+    #[test]
+    fn relabel_reversal_preserves_language() {
+        let mut d = parens();
+        d.states.push(State {
+            transitions: CurryOpt::default(),
+            accepting: false,
+        });
+        let original = d.clone();
+        d.relabel_states(&[1, 0]).unwrap();
+        assert_eq!(d.initial, 1);
+        for input in ["", "()", "(()())", "(", ")(", "())"] {
+            assert_eq!(d.accept(input.chars()), original.accept(input.chars()));
+        }
+        assert_eq!(d.relabel_states(&[0, 0]), Err(IllFormed::NotAPermutation));
+        assert_eq!(d.relabel_states(&[0]), Err(IllFormed::NotAPermutation));
+    }
+
+    #[test]
+    fn unmatched_return_after_local() {
+        let d: Deterministic<char, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range::unit('a'),
+                            Return(Edge::Local {
+                                dst: 1,
+                                call: call!(|x| x),
+                            }),
+                        )])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range::unit(')'),
+                            Return(Edge::Return {
+                                dst: 0,
+                                call: call!(|x| x),
+                            }),
+                        )])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: true,
+                },
+            ],
+            initial: 0,
+        };
+        assert_eq!(d.find_unmatched_returns(), vec![1]);
+        assert_eq!(parens().find_unmatched_returns(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn larger_automaton_is_larger() {
+        let small = Nondeterministic::bounded_depth(1, (), &Range::unit('('), &Range::unit(')'));
+        let large = Nondeterministic::bounded_depth(8, (), &Range::unit('('), &Range::unit(')'));
+        assert!(small.approx_size_bytes() < large.approx_size_bytes());
+        assert_eq!(small.max_fanout(), 1);
+        assert_eq!(large.max_fanout(), 2);
+        assert_eq!(parens().max_fanout(), 2);
+    }
+
+    #[test]
+    fn bounded_depth_caps_nesting() {
+        let nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        assert_eq!(nd.check(), Ok(()));
+        for input in ["", "()", "(())", "()(())", "(()())"] {
+            assert_eq!(nd.accept(input.chars()), Ok(true), "{input:?}");
+        }
+        for input in ["((()))", "(", ")", "(()", "())", "(((", ")("] {
+            assert_eq!(nd.accept(input.chars()), Ok(false), "{input:?}");
+        }
+        let flat = Nondeterministic::bounded_depth(0, (), &Range::unit('('), &Range::unit(')'));
+        assert_eq!(flat.accept("".chars()), Ok(true));
+        assert_eq!(flat.accept("()".chars()), Ok(false));
+    }
+
+    #[test]
+    fn lints_on_hand_crafted_automaton() {
+        let local = |token, dst| {
+            (
+                Range::unit(token),
+                Return(Edge::Local {
+                    dst,
+                    call: call!(|x| x),
+                }),
+            )
+        };
+        let d: Deterministic<char, ()> = Automaton {
+            states: vec![
+                // Only ever reached with an empty stack, so popping `()` can't happen
+                State {
+                    transitions: CurryOpt {
+                        wildcard: None,
+                        none: Some(Wildcard::Specific(vec![local('a', 1)])),
+                        some: once(((), Wildcard::Specific(vec![local('b', 0)]))).collect(),
+                    },
+                    accepting: true,
+                },
+                // Pops whatever the stack holds, which is always nothing
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range::unit(')'),
+                            Return(Edge::Return {
+                                dst: 0,
+                                call: call!(|x| x),
+                            }),
+                        )])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                // Nothing leads here
+                State::default(),
+            ],
+            initial: 0,
+        };
+        assert_eq!(
+            d.lint(),
+            vec![
+                Lint::UnreachableStackCondition {
+                    state: 0,
+                    condition: Some(()),
+                },
+                Lint::UnmatchedReturn { state: 1 },
+                Lint::UnreachableState { state: 2 },
+            ],
+        );
+        assert_eq!(parens().lint(), vec![]);
+    }
+
+    #[test]
+    fn empty_initial_set_rejects_everything() {
+        let nd: Nondeterministic<char, ()> = Automaton {
+            states: vec![State {
+                transitions: CurryOpt::default(),
+                accepting: true,
+            }],
+            initial: BTreeSet::new(),
+        };
+        assert_eq!(
+            nd.lint(),
+            vec![Lint::EmptyInitialSet, Lint::UnreachableState { state: 0 },],
+        );
+        assert_eq!(nd.accept("".chars()), Ok(false));
+        assert_eq!(
+            Nondeterministic::new(nd.states.clone(), BTreeSet::new()),
+            Err(IllFormed::EmptyInitialSet),
+        );
+        assert_eq!(
+            Nondeterministic::new(nd.states, once(0).collect()).map(|ok| ok.lint()),
+            Ok(vec![]),
+        );
+    }
+
+    #[test]
+    fn first_match_policy_prefers_earlier_ranges() {
+        let local = |first, last, dst| {
+            (
+                Range { first, last },
+                Return(Edge::Local {
+                    dst,
+                    call: call!(|x| x),
+                }),
+            )
+        };
+        let prioritized = |ranges| -> Deterministic<char, ()> {
+            Automaton {
+                states: vec![
+                    State {
+                        transitions: CurryOpt {
+                            wildcard: Some(Wildcard::Specific(ranges)),
+                            none: None,
+                            some: BTreeMap::new(),
+                        },
+                        accepting: false,
+                    },
+                    State {
+                        transitions: CurryOpt::default(),
+                        accepting: true,
+                    },
+                    State::default(),
+                ],
+                initial: 0,
+            }
+        };
+        let keywords_first = prioritized(vec![local('a', 'c', 1), local('a', 'z', 2)]);
+        let letters_first = prioritized(vec![local('a', 'z', 2), local('a', 'c', 1)]);
+        assert_eq!(
+            keywords_first.accept_with_policy("b".chars(), LookupPolicy::FirstMatch),
+            Ok(true),
+        );
+        assert_eq!(
+            letters_first.accept_with_policy("b".chars(), LookupPolicy::FirstMatch),
+            Ok(false),
+        );
+        assert_eq!(
+            keywords_first.accept_with_policy("x".chars(), LookupPolicy::FirstMatch),
+            Ok(false),
+        );
+        assert_eq!(
+            keywords_first.accept_with_policy("x".chars(), LookupPolicy::Exclusive),
+            Ok(false),
+        );
+    }
+
+    #[test]
+    fn trap_states() {
+        let trap: State<char, (), usize> = State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Any(Return(Edge::Local {
+                    dst: 1,
+                    call: call!(|x| x),
+                }))),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: false,
+        };
+        assert!(trap.is_trap(1));
+        assert!(!trap.is_trap(0));
+        assert!(!get!(parens().states, 0).is_trap(0));
+        let d = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range::unit('a'),
+                            Return(Edge::Local {
+                                dst: 1,
+                                call: call!(|x| x),
+                            }),
+                        )])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: true,
+                },
+                trap,
+            ],
+            initial: 0,
+        };
+        assert_eq!(d.lint(), vec![Lint::TrapState { state: 1 }]);
+    }
+
+    #[test]
+    fn interchangeable_stack_symbols_coalesce() {
+        let close = Wildcard::Specific(vec![(
+            Range::unit(')'),
+            Return(Edge::Return {
+                dst: 0,
+                call: call!(|x| x),
+            }),
+        )]);
+        let open = |token, push| {
+            (
+                Range::unit(token),
+                Return(Edge::Call {
+                    dst: 0,
+                    call: call!(|x| x),
+                    push,
+                }),
+            )
+        };
+        let mut d: Deterministic<char, bool> = Automaton {
+            states: vec![State {
+                transitions: CurryOpt {
+                    wildcard: Some(Wildcard::Specific(vec![open('(', false), open('[', true)])),
+                    none: None,
+                    some: [(false, close.clone()), (true, close)]
+                        .into_iter()
+                        .collect(),
+                },
+                accepting: true,
+            }],
+            initial: 0,
+        };
+        let pre = d.clone();
+        assert_eq!(d.coalesce_stack_symbols(), BTreeMap::from([(true, false)]));
+        assert_eq!(d.check(), Ok(()));
+        assert_eq!(d.states.first().unwrap().transitions.some.len(), 1);
+        for input in ["", "()", "[)", "([))", "(", "[", ")", "(]"] {
+            assert_eq!(d.accept(input.chars()), pre.accept(input.chars()));
+        }
+        assert!(parens().coalesce_stack_symbols().is_empty());
+    }
+
+    #[test]
+    fn default_nondeterministic_is_well_formed() {
+        let nd = Nondeterministic::<char, ()>::default();
+        assert_eq!(nd.check(), Ok(()));
+        assert_eq!(nd.accept("".chars()), Ok(false));
+        assert_eq!(nd.accept("()".chars()), Ok(false));
+        assert_eq!(nd.determinize(), Ok(Deterministic::default()));
+    }
+
+    #[test]
+    fn classify_each_rejection_on_parens() {
+        let d = parens();
+        assert_eq!(d.classify("(())".chars()), Ok(Acceptance::Accepted));
+        assert_eq!(
+            d.classify("(()".chars()),
+            Ok(Acceptance::RejectedNonEmptyStack(1))
+        );
+        assert_eq!(
+            d.classify("())(".chars()),
+            Ok(Acceptance::RejectedDeadState(2))
+        );
+        assert_eq!(
+            d.classify("(x)".chars()),
+            Ok(Acceptance::RejectedDeadState(1))
+        );
+        // Same, but with a trailing `!` leading to a non-accepting state
+        let mut shouting = parens();
+        shouting.states.push(State::default());
+        get_mut!(shouting.states, 0).transitions.wildcard = Some(Wildcard::Specific(vec![
+            (
+                Range::unit('('),
+                Return(Edge::Call {
+                    dst: 0,
+                    call: call!(|x| x),
+                    push: (),
+                }),
+            ),
+            (
+                Range::unit('!'),
+                Return(Edge::Local {
+                    dst: 1,
+                    call: call!(|x| x),
+                }),
+            ),
+        ]));
+        assert_eq!(
+            shouting.classify("()!".chars()),
+            Ok(Acceptance::RejectedNonAccepting)
+        );
+        assert_eq!(
+            shouting.classify("()!(".chars()),
+            Ok(Acceptance::RejectedDeadState(3))
+        );
+    }
+
+    #[test]
+    fn derivative_by_first_local_token() {
+        // "ab" or "ac*"
+        let d = Deterministic::<char, char>::from_text(
+            "initial 0
+            state 0
+            on 'a' local -> 1
+            state 1
+            on 'b' local -> 2
+            on 'c' local -> 3
+            state 2 accepting
+            state 3 accepting
+            on 'c' local -> 3",
+        )
+        .unwrap();
+        let after_a = d.derivative(&'a').unwrap();
+        for (input, expected) in [("b", true), ("ccc", true), ("", false), ("ab", false)] {
+            assert_eq!(after_a.accept(input.chars()), Ok(expected), "{input:?}");
+        }
+        let after_ac = after_a.derivative(&'c').unwrap();
+        assert_eq!(after_ac.accept("".chars()), Ok(true));
+        assert_eq!(d.derivative(&'b'), None);
+        assert_eq!(parens().derivative(&'('), None);
+    }
+
+    #[test]
+    fn parens_transduce_to_nesting_events() {
+        let mut d = parens();
+        *get_mut!(d.states, 0)
+            .transitions
+            .get_mut((None, (&'(', ())))
+            .unwrap()
+            .call_mut()
+            .unwrap() = Call::new(|()| (), "open".to_owned());
+        *get_mut!(d.states, 0)
+            .transitions
+            .get_mut((Some(&()), (&')', ())))
+            .unwrap()
+            .call_mut()
+            .unwrap() = Call::new(|()| (), "close".to_owned());
+        let events = |call: &Call<(), ()>| call.src.clone();
+        assert_eq!(
+            d.transduce("(())".chars(), events),
+            Ok(Some(vec![
+                "open".to_owned(),
+                "open".to_owned(),
+                "close".to_owned(),
+                "close".to_owned(),
+            ])),
+        );
+        assert_eq!(d.transduce("(()".chars(), events), Ok(None));
+        assert_eq!(d.transduce("())".chars(), events), Ok(None));
+    }
+
+    #[test]
+    fn parens_project_to_any_parens() {
+        let regular = parens().project_to_regular();
+        assert_eq!(regular.check(), Ok(()));
+        for input in ["", "()", ")(", "))((", "(((", ")"] {
+            assert_eq!(regular.accept(input.chars()), Ok(true), "{input:?}");
+        }
+        assert_eq!(regular.accept("(x)".chars()), Ok(false));
+        assert_eq!(
+            regular.determinize().map(|d| d.accept(")(".chars())),
+            Ok(Ok(true))
+        );
+    }
+
+    #[test]
+    fn parens_dot_clusters_at_depth_zero() {
+        let d = parens();
+        assert_eq!(d.min_stack_depths(), vec![Some(0)]);
+        let dot = d.to_dot_clustered();
+        assert!(dot.starts_with("digraph {"), "{dot}");
+        assert!(dot.contains("subgraph cluster_depth_0 {\n    label=\"stack depth 0\";\n    0 [shape=doublecircle];"), "{dot}");
+        assert!(!dot.contains("cluster_depth_1"), "{dot}");
+        assert!(dot.contains("0 -> 0 [label=\"'(' push ()\"];"), "{dot}");
+        assert!(
+            dot.contains("0 -> 0 [label=\"')' when top () pop\"];"),
+            "{dot}"
+        );
+
+        let nested = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        assert_eq!(nested.min_stack_depths(), vec![Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn dedup_collapses_identical_sinks() {
+        let to = |dst| {
+            Return(Edge::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let sink = State {
+            transitions: CurryOpt::default(),
+            accepting: true,
+        };
+        let mut d: Deterministic<char, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![
+                            (Range::unit('a'), to(1)),
+                            (Range::unit('b'), to(2)),
+                        ])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                sink.clone(),
+                sink,
+            ],
+            initial: 0,
+        };
+        let pre = d.clone();
+        d.dedup_states();
+        assert_eq!(d.check(), Ok(()));
+        assert_eq!(d.states.len(), 2);
+        for input in ["", "a", "b", "ab", "c"] {
+            assert_eq!(d.accept(input.chars()), pre.accept(input.chars()));
+        }
+    }
+
+    #[test]
+    fn check_rejects_wildcard_shadowing_specific() {
+        let edge = |dst| {
+            Return(Edge::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let d: Deterministic<char, u8> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range {
+                                first: 'a',
+                                last: 'm',
+                            },
+                            edge(0),
+                        )])),
+                        none: None,
+                        some: once((
+                            7,
+                            Wildcard::Specific(vec![(
+                                Range {
+                                    first: 'k',
+                                    last: 'z',
+                                },
+                                edge(1),
+                            )]),
+                        ))
+                        .collect(),
+                    },
+                    accepting: true,
+                },
+                State {
+                    transitions: CurryOpt {
+                        wildcard: None,
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: true,
+                },
+            ],
+            initial: 0,
+        };
+        assert_eq!(
+            d.check(),
+            Err(IllFormed::WildcardShadowsSpecific {
+                state: 0,
+                condition: Some(7),
+                tokens: Some(Range {
+                    first: 'k',
+                    last: 'm'
+                }),
+            }),
+        );
+    }
+}
+
+mod epsilon {
+    use crate::*;
+    use core::iter::once;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    /// State whose only transition is an epsilon transition to `dst`.
+    fn epsilon_to(dst: usize) -> State<char, (), BTreeSet<usize>> {
+        State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Any(Return(Edge::Epsilon {
+                    dst: once(dst).collect(),
+                }))),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: false,
+        }
+    }
+
+    #[test]
+    fn epsilon_chain_accepts_empty_string() {
+        let mut nd = Nondeterministic {
+            states: vec![
+                epsilon_to(1),
+                epsilon_to(2),
+                State {
+                    transitions: CurryOpt::default(),
+                    accepting: true,
+                },
+            ],
+            initial: once(0).collect(),
+        };
+        assert!(nd.has_epsilon());
+        assert_eq!(nd.epsilon_closure(0), Some([0, 1, 2].into_iter().collect()));
+        assert_eq!(nd.epsilon_closure(3), None);
+        let d = nd.determinize().unwrap();
+        assert_eq!(d.accept("".chars()), Ok(true));
+        assert_eq!(d.accept("x".chars()), Ok(false));
+        nd.eliminate_epsilon().unwrap();
+        assert!(!nd.has_epsilon());
+        assert_eq!(nd.accept("".chars()), Ok(true));
+    }
+
+    #[test]
+    fn epsilon_cycle_errors_instead_of_hanging() {
+        let nd = Nondeterministic {
+            states: vec![epsilon_to(1), epsilon_to(0)],
+            initial: once(0).collect(),
+        };
+        // Every step consumes a token, so a run refuses epsilon edges outright instead of looping on them
+        assert_eq!(nd.accept("x".chars()), Err(IllFormed::Epsilon));
+        assert_eq!(nd.epsilon_closure(0), Some([0, 1].into_iter().collect()));
+        let d = nd.determinize().unwrap();
+        assert_eq!(d.accept("".chars()), Ok(false));
+        assert_eq!(d.accept("x".chars()), Ok(false));
+    }
+
+    #[test]
+    fn epsilon_out_of_bounds_is_checked_before_elimination() {
+        let mut nd = Nondeterministic {
+            states: vec![epsilon_to(1)],
+            initial: once(0).collect(),
+        };
+        assert_eq!(nd.epsilon_closure(0), None);
+        assert_eq!(nd.determinize(), Err(IllFormed::OutOfBounds));
+        assert_eq!(nd.eliminate_epsilon(), Err(IllFormed::OutOfBounds));
+    }
+}
+
+mod indices {
+    use crate::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn contains_for_every_control_type() {
+        assert!(Indices::<(), ()>::contains(&3_usize, 3));
+        assert!(!Indices::<(), ()>::contains(&3_usize, 4));
+        let tree = BTreeSet::from([0, 5, 100]);
+        assert!(Indices::<(), ()>::contains(&tree, 100));
+        assert!(!Indices::<(), ()>::contains(&tree, 6));
+        assert!(!Indices::<(), ()>::contains(&BTreeSet::new(), 0));
+        let bits: BitSet = tree.iter().copied().collect();
+        assert!(Indices::<(), ()>::contains(&bits, 5));
+        assert!(!Indices::<(), ()>::contains(&bits, 64));
+    }
+}
+
+mod bitset {
+    use crate::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn bitset_matches_btreeset() {
+        let indices = [130, 0, 64, 63, 1, 64];
+        let bits: BitSet = indices.into_iter().collect();
+        let tree: BTreeSet<usize> = indices.into_iter().collect();
+        assert_eq!(
+            bits.iter().collect::<Vec<_>>(),
+            tree.iter().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(bits.len(), tree.len());
+        assert!(bits.contains(130));
+        assert!(!bits.contains(129));
+        assert_eq!(format!("{bits:?}"), format!("{tree:?}"));
+        let mut shrunk = bits.clone();
+        assert!(shrunk.remove(130));
+        assert!(!shrunk.remove(130));
+        assert_eq!(shrunk, [0, 1, 63, 64].into_iter().collect());
+        assert!(shrunk < bits);
+        assert_eq!(<BitSet as Indices<(), ()>>::collect([]), Err(false));
+    }
+}
+
+mod run {
+    use super::parens;
+    use crate::*;
+    use core::iter::once;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn verdicts_after_each_token() {
+        let d = parens();
+        let verdicts: Result<Vec<_>, _> = "(())".chars().run(&d).verdicts().collect();
+        assert_eq!(verdicts, Ok(vec![false, false, false, true]));
+        let flipped: Result<Vec<_>, _> = "())(".chars().run(&d).verdicts().collect();
+        assert_eq!(flipped, Ok(vec![false, true, false, false]));
+    }
+
+    #[test]
+    fn normalized_tokens_match_lowercase_ranges() {
+        let word: Deterministic<char, ()> = Automaton {
+            states: vec![State {
+                transitions: CurryOpt {
+                    wildcard: Some(Wildcard::Specific(vec![(
+                        Range {
+                            first: 'a',
+                            last: 'z',
+                        },
+                        Return(Edge::Local {
+                            dst: 0,
+                            call: call!(|x| x),
+                        }),
+                    )])),
+                    none: None,
+                    some: BTreeMap::new(),
+                },
+                accepting: true,
+            }],
+            initial: 0,
+        };
+        assert_eq!(word.accept("HeLLo".chars()), Ok(false));
+        assert_eq!(
+            word.accept_normalized("HeLLo".chars(), |c| c.to_ascii_lowercase()),
+            Ok(true),
+        );
+        let verdicts: Result<Vec<_>, _> = "Hi!"
+            .chars()
+            .run_normalized(&word, |c| c.to_ascii_lowercase())
+            .verdicts()
+            .collect();
+        assert_eq!(verdicts, Ok(vec![true, true, false]));
+    }
+
+    #[test]
+    fn fallible_input_propagates_its_error() {
+        let d = parens();
+        let lexed = |tokens: &[Result<char, &'static str>]| d.accept_try(tokens.iter().copied());
+        assert_eq!(
+            lexed(&[Ok('('), Ok(')'), Err("bad byte"), Ok('(')]),
+            Ok(Err("bad byte")),
+        );
+        assert_eq!(lexed(&[Ok('('), Ok(')')]), Ok(Ok(true)));
+        assert_eq!(lexed(&[Ok('('), Ok('(')]), Ok(Ok(false)));
+        assert_eq!(lexed(&[Ok(')'), Err("never read")]), Ok(Ok(false)));
+    }
+
+    #[test]
+    fn borrowed_string_tokens() {
+        let edge = |word: &str, edge| (Range::unit(word.to_owned()), Return(edge));
+        let d: Deterministic<String, ()> = Automaton {
+            states: vec![State {
+                transitions: CurryOpt {
+                    wildcard: Some(Wildcard::Specific(vec![edge(
+                        "begin",
+                        Edge::Call {
+                            dst: 0,
+                            call: call!(|x| x),
+                            push: (),
+                        },
+                    )])),
+                    none: None,
+                    some: once((
+                        (),
+                        Wildcard::Specific(vec![edge(
+                            "end",
+                            Edge::Return {
+                                dst: 0,
+                                call: call!(|x| x),
+                            },
+                        )]),
+                    ))
+                    .collect(),
+                },
+                accepting: true,
+            }],
+            initial: 0,
+        };
+        let tokens: Vec<String> = ["begin", "begin", "end", "end"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(d.accept(&tokens), Ok(true));
+        assert_eq!(d.accept(get!(tokens, ..3)), Ok(false));
+        assert_eq!(d.accept(tokens.iter().rev()), Ok(false));
+    }
+
+    #[test]
+    fn owned_and_borrowed_tokens() {
+        let d = parens();
+        let owned: Vec<char> = "(()())".chars().collect();
+        assert_eq!(d.accept(owned.clone()), Ok(true));
+        assert_eq!(d.accept(&owned), Ok(true));
+        assert_eq!(d.accept(owned.as_slice()), Ok(true));
+        assert_eq!(d.accept(get!(owned, ..5)), Ok(false));
+        assert_eq!(d.accept(owned.iter().rev()), Ok(false));
+        let nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        assert_eq!(nd.accept(&owned), Ok(true));
+        assert_eq!(nd.accept(owned), Ok(true));
+    }
+
+    #[test]
+    fn caching_run_steps_once_per_new_token() {
+        use core::cell::Cell;
+
+        /// Count every step taken.
+        struct Counting<'d> {
+            inner: &'d Deterministic<char, ()>,
+            steps: Cell<usize>,
+        }
+
+        impl Execute<char, ()> for Counting<'_> {
+            type Ctrl = usize;
+            fn initial(&self) -> usize {
+                self.inner.initial()
+            }
+            fn step(
+                &self,
+                ctrl: usize,
+                stack: &mut Vec<()>,
+                maybe_token: Option<&char>,
+            ) -> Result<Result<usize, bool>, IllFormed<char, (), usize>> {
+                self.steps.set(self.steps.get() + 1);
+                self.inner.step(ctrl, stack, maybe_token)
+            }
+            fn would_accept(&self, ctrl: &usize, stack: &[()]) -> bool {
+                self.inner.would_accept(ctrl, stack)
+            }
+        }
+
+        let d = parens();
+        assert!(!d.is_dead(&1));
+        let nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        assert!(nd.is_dead(&BTreeSet::new()));
+        assert!(!nd.is_dead(&nd.initial));
+        let counting = Counting {
+            inner: &d,
+            steps: Cell::new(0),
+        };
+        let mut run: CachingRun<'_, char, (), _> = CachingRun::new(&counting);
+        assert!(run.accepts());
+        for (i, token) in "(())".chars().enumerate() {
+            run.push(token).unwrap();
+            assert_eq!(counting.steps.get(), i + 1);
+        }
+        assert!(run.accepts());
+        let edited: Vec<char> = "(()".chars().collect();
+        run.set_input(&edited).unwrap();
+        assert_eq!(counting.steps.get(), 4);
+        assert!(!run.accepts());
+        let longer: Vec<char> = "(())()".chars().collect();
+        run.set_input(&longer).unwrap();
+        assert_eq!(counting.steps.get(), 7);
+        assert!(run.accepts());
+        run.push(')').unwrap();
+        run.push('(').unwrap();
+        assert_eq!(counting.steps.get(), 8);
+        assert!(!run.accepts());
+    }
+
+    #[test]
+    fn push_tokens_one_at_a_time() {
+        let d = parens();
+        let mut exec = PushExecution::new(&d);
+        for token in ['(', '(', ')', ')'] {
+            exec.feed(&token).unwrap();
+        }
+        assert_eq!(exec.finish(), Ok(true));
+        let mut early = PushExecution::new(&d);
+        early.feed(&')').unwrap();
+        early.feed(&'(').unwrap();
+        assert_eq!(early.finish(), Ok(false));
+    }
+
+    #[test]
+    fn unbalanced_input_leaves_stack() {
+        let d = parens();
+        assert_eq!(d.run_to_end("(((".chars()), Ok((false, vec![(), (), ()])));
+        assert_eq!(d.run_to_end("(()".chars()), Ok((false, vec![()])));
+        assert_eq!(d.run_to_end("()".chars()), Ok((true, vec![])));
+    }
+
+    #[test]
+    fn ambiguity_is_reported_per_input() {
+        let local = Edge::Local {
+            dst: BTreeSet::from([0]),
+            call: call!(|x| x),
+        };
+        let push = Edge::Call {
+            dst: BTreeSet::from([1]),
+            call: call!(|x| x),
+            push: (),
+        };
+        let on_a = |edge: &Edge<char, (), BTreeSet<usize>>| State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Specific(vec![(
+                    Range::unit('a'),
+                    Return(edge.clone()),
+                )])),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: true,
+        };
+        let nd = Nondeterministic {
+            states: vec![on_a(&local), on_a(&push)],
+            initial: BTreeSet::from([0, 1]),
+        };
+        assert_eq!(nd.check(), Ok(()));
+        assert_eq!(nd.accept("".chars()), Ok(true));
+        assert_eq!(
+            nd.accept("a".chars()),
+            Err(IllFormed::AmbiguousInput(
+                'a',
+                Box::new(IllFormed::EdgeMergeConflict(local, push)),
+            )),
+        );
+    }
+
+    #[test]
+    fn configuration_trace() {
+        let d = parens();
+        let depths: Vec<usize> = "(())"
+            .chars()
+            .run(&d)
+            .trace()
+            .unwrap()
+            .into_iter()
+            .map(|(ctrl, stack)| {
+                assert_eq!(ctrl, 0);
+                stack.len()
+            })
+            .collect();
+        assert_eq!(depths, vec![1, 2, 1, 0]);
+        assert_eq!("())(".chars().run(&d).trace().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dynamic_token_sources() {
+        let d = parens();
+        assert_eq!(d.accept_dyn(&mut vec!['(', ')'].into_iter()), Ok(true));
+        assert_eq!(d.accept_dyn(&mut "(()".chars()), Ok(false));
+        assert_eq!(d.accept_dyn(&mut "(())".chars()), Ok(true));
+    }
+
+    #[test]
+    fn longest_match_munches_maximally() {
+        let d = parens();
+        assert_eq!(d.longest_match(&['(', ')', '(']), Ok(Some(2)));
+        assert_eq!(d.longest_match(&['(', ')', '(', ')', ')']), Ok(Some(4)));
+        assert_eq!(d.longest_match(&[')']), Ok(None));
+        assert_eq!(d.longest_match(&[]), Ok(None));
+    }
+
+    #[test]
+    fn complement_needs_alphabet_unless_total() {
+        let d = parens();
+        let universe = Range {
+            first: '(',
+            last: ')',
+        };
+        assert!(!d.is_total(&universe));
+        assert_eq!(d.complement(None), Err(IllFormed::NeedsAlphabet));
+        let c = d.complement(Some(&universe)).unwrap();
+        assert_eq!(c.check(), Ok(()));
+        // Among inputs that end with an empty stack, exactly one of the two accepts
+        for input in ["", "()", "(())()", ")", ")(", "())", "())(()", "x"] {
+            let mut stack = 0_usize;
+            let balanced = input.chars().all(|token| match token {
+                '(' => {
+                    stack += 1;
+                    true
+                }
+                ')' => stack.checked_sub(1).map(|popped| stack = popped).is_some(),
+                _ => true,
+            }) && stack == 0;
+            let expected = input.chars().all(|token| matches!(token, '(' | ')')) && !balanced;
+            assert_eq!(c.accept(input.chars()), Ok(expected), "{input:?}");
+        }
+        // Acceptance requires an empty stack, so both reject an input that doesn't leave one
+        assert_eq!(d.accept("(".chars()), Ok(false));
+        assert_eq!(c.accept("(".chars()), Ok(false));
+
+        let mut completed = d;
+        assert!(completed.complete(&universe));
+        assert!(completed.is_total(&universe));
+        assert!(!completed.complete(&universe));
+        assert_eq!(completed.complement(None), Ok(c));
+    }
+
+    #[test]
+    fn coverage_records_edges_taken() {
+        let d = parens();
+        let mut covered = BTreeSet::new();
+        assert_eq!("()".chars().run(&d).cover(&mut covered), Ok(true));
+        assert_eq!(covered, d.edges());
+        assert_eq!(covered.len(), 2);
+
+        let mut partial = BTreeSet::new();
+        assert_eq!("(".chars().run(&d).cover(&mut partial), Ok(false));
+        assert_eq!(partial.len(), 1);
+        assert!(matches!(partial.first(), Some(&(0, Edge::Call { .. }))));
+    }
+
+    #[test]
+    fn parse_tree_follows_nesting() {
+        let d = parens();
+        let node = |children| NestedWord::Node {
+            open: '(',
+            children,
+            close: ')',
+        };
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(
+            d.parse_tree(&chars("(())")),
+            Ok(Some(NestedWord::Root(vec![node(vec![node(vec![])])]))),
+        );
+        assert_eq!(
+            d.parse_tree(&chars("()()")),
+            Ok(Some(NestedWord::Root(vec![node(vec![]), node(vec![])]))),
+        );
+        assert_eq!(d.parse_tree(&[]), Ok(Some(NestedWord::Root(vec![]))));
+        assert_eq!(d.parse_tree(&chars("(()")), Ok(None));
+        assert_eq!(d.parse_tree(&chars("())")), Ok(None));
+    }
+
+    #[test]
+    fn toggle_empty_acceptance() {
+        let mut d = parens();
+        d.set_accepts_empty(true);
+        assert_eq!(d, parens());
+        d.set_accepts_empty(false);
+        assert_eq!(d.accept("".chars()), Ok(false));
+        for input in ["()", "(())()"] {
+            assert_eq!(d.accept(input.chars()), Ok(true), "{input:?}");
+        }
+        for input in ["(", ")", "())"] {
+            assert_eq!(d.accept(input.chars()), Ok(false), "{input:?}");
+        }
+        d.set_accepts_empty(true);
+        assert_eq!(d.accept("".chars()), Ok(true));
+        assert_eq!(d.accept("(())".chars()), Ok(true));
+
+        let mut nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        nd.set_accepts_empty(false);
+        assert_eq!(nd.accept("".chars()), Ok(false));
+        assert_eq!(nd.accept("()".chars()), Ok(true));
+    }
+
+    #[test]
+    fn accept_prefix_leaves_the_rest() {
+        let d = parens();
+        let mut stream = "(()())x()".chars().peekable();
+        assert_eq!(d.accept_prefix_in_place(&mut stream), Ok(true));
+        assert_eq!(stream.collect::<String>(), "x()");
+        let mut unbalanced = "(()x".chars().peekable();
+        assert_eq!(d.accept_prefix_in_place(&mut unbalanced), Ok(false));
+        assert_eq!(unbalanced.next(), Some('x'));
+        let mut empty = "".chars().peekable();
+        assert_eq!(d.accept_prefix_in_place(&mut empty), Ok(true));
+    }
+
+    #[test]
+    fn dead_runs_stop_stepping() {
+        use core::cell::Cell;
+
+        struct Counting<'d> {
+            inner: &'d Deterministic<char, char>,
+            steps: Cell<usize>,
+        }
+        impl Execute<char, char> for Counting<'_> {
+            type Ctrl = usize;
+            fn initial(&self) -> usize {
+                self.inner.initial()
+            }
+            fn step(
+                &self,
+                ctrl: usize,
+                stack: &mut Vec<char>,
+                maybe_token: Option<&char>,
+            ) -> Result<Result<usize, bool>, IllFormed<char, char, usize>> {
+                if maybe_token.is_some() {
+                    self.steps.set(self.steps.get() + 1);
+                }
+                self.inner.step(ctrl, stack, maybe_token)
+            }
+            fn would_accept(&self, ctrl: &usize, stack: &[char]) -> bool {
+                self.inner.would_accept(ctrl, stack)
+            }
+            // The automaton itself only gives up with no states left, but a caller can know its trap state
+            fn is_dead(&self, ctrl: &usize) -> bool {
+                *ctrl == 1
+            }
+        }
+        let d = Deterministic::<char, char>::from_text(
+            "initial 0\nstate 0 accepting\non 'a' local -> 0\non 'b' local -> 1\nstate 1\non * local -> 1\n",
+        )
+        .unwrap();
+        assert!(!d.is_dead(&1));
+        let nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        assert!(nd.is_dead(&BTreeSet::new()));
+        assert!(!nd.is_dead(&nd.initial));
+        let counting = Counting {
+            inner: &d,
+            steps: Cell::new(0),
+        };
+        let mut run = "aabaaaa".chars().run(&counting);
+        assert_eq!(run.by_ref().count(), 7);
+        assert_eq!(run.ctrl, Err(false));
+        assert_eq!(counting.steps.get(), 3);
+        counting.steps.set(0);
+        assert_eq!(
+            "abaaa".chars().run(&counting).run_with(|_, _| true),
+            Ok(false)
+        );
+        assert_eq!(counting.steps.get(), 2);
+    }
+
+    #[test]
+    fn complete_against_declared_alphabet() {
+        assert_eq!(
+            <char as Alphabet>::universe(),
+            Range {
+                first: '\0',
+                last: char::MAX,
+            },
+        );
+        let mut d = parens();
+        assert!(d.complete_declared());
+        assert_eq!(d.states.len(), 2);
+        assert_eq!(d.check(), Ok(()));
+        for input in ["", "()", "(())()"] {
+            assert_eq!(d.accept(input.chars()), Ok(true), "{input:?}");
+        }
+        for input in ["x", "(x)", ")"] {
+            assert_eq!(d.accept(input.chars()), Ok(false), "{input:?}");
+        }
+        let c = parens().complement_declared();
+        for input in ["x", "x()", ")", "\u{10ffff}"] {
+            assert_eq!(c.accept(input.chars()), Ok(true), "{input:?}");
+        }
+        assert_eq!(c.accept("(())".chars()), Ok(false));
+    }
+
+    #[test]
+    fn finish_runs_to_completion() {
+        let d = parens();
+        assert_eq!("(())".chars().run(&d).finish(), Ok(true));
+        assert_eq!("(()".chars().run(&d).finish(), Ok(false));
+        assert_eq!("())(".chars().run(&d).finish(), Ok(false));
+        let mut run = "()()".chars().run(&d);
+        assert_eq!(run.next(), Some(Ok('(')));
+        assert_eq!(run.finish(), Ok(true));
+    }
+
+    #[test]
+    fn feed_chunks_across_boundaries() {
+        let d = parens();
+        let mut state = d.start();
+        for chunk in ["((", ")()", ")"] {
+            let tokens: Vec<char> = chunk.chars().collect();
+            assert_eq!(d.feed_chunk(&mut state, &tokens), Ok(()));
+        }
+        assert_eq!(state.stack, vec![]);
+        assert!(d.finish(&state));
+        let mut early = d.start();
+        for chunk in ["(", "))", "("] {
+            let tokens: Vec<char> = chunk.chars().collect();
+            assert_eq!(d.feed_chunk(&mut early, &tokens), Ok(()));
+        }
+        assert_eq!(early.ctrl, Err(false));
+        assert!(!d.finish(&early));
+    }
+
+    #[test]
+    fn record_and_replay_events() {
+        let d = parens();
+        let (result, events) = "()".chars().run(&d).record();
+        assert_eq!(result, Ok(true));
+        assert_eq!(
+            events,
+            vec![
+                RunEvent::Consumed('('),
+                RunEvent::Pushed(()),
+                RunEvent::MovedTo(0),
+                RunEvent::Consumed(')'),
+                RunEvent::Popped(()),
+                RunEvent::MovedTo(0),
+                RunEvent::Accepted,
+            ],
+        );
+        assert_eq!(replay(&d, &events), Ok(None));
+
+        let (rejected, partial) = ")(".chars().run(&d).record();
+        assert_eq!(rejected, Ok(false));
+        assert_eq!(partial, vec![RunEvent::Consumed(')'), RunEvent::Rejected]);
+
+        let mut tampered = events;
+        *get_mut!(tampered, 6) = RunEvent::Rejected;
+        assert_eq!(replay(&d, &tampered), Ok(Some(6)));
+    }
+
+    #[test]
+    fn enumerate_balanced_parens_in_length_order() {
+        let d = Nondeterministic::bounded_depth(3, (), &Range::unit('('), &Range::unit(')'))
+            .determinize()
+            .unwrap();
+        let words: Vec<String> = d.enumerate(6).map(|w| w.into_iter().collect()).collect();
+        assert_eq!(
+            words,
+            ["", "()", "(())", "()()", "((()))", "(()())", "(())()", "()(())", "()()()",],
+        );
+        for word in &words {
+            assert_eq!(d.accept(word.chars()), Ok(true), "{word:?}");
+        }
+        assert_eq!(d.enumerate(0).collect::<Vec<_>>(), vec![vec![]]);
+    }
+
+    #[test]
+    fn min_cost_accept_picks_the_cheaper_run() {
+        let local = |token, dst: &[usize]| {
+            Some(Wildcard::Specific(vec![(
+                Range::unit(token),
+                Return(Edge::Local {
+                    dst: dst.iter().copied().collect(),
+                    call: call!(|x| x),
+                }),
+            )]))
+        };
+        let state = |wildcard, accepting| State {
+            transitions: CurryOpt {
+                wildcard,
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting,
+        };
+        // Two ways to read "ab": through state 1 or through state 2
+        let nd: Nondeterministic<char, ()> = Automaton {
+            states: vec![
+                state(local('a', &[1, 2]), false),
+                state(local('b', &[3]), false),
+                state(local('b', &[3]), false),
+                state(None, true),
+            ],
+            initial: once(0).collect(),
+        };
+        let weight = |src, _: &char, dst| match (src, dst) {
+            (0, 1) => 5_u32,
+            (1, 3) | (0, 2) => 1,
+            _ => 10,
+        };
+        assert_eq!(nd.min_cost_accept(&['a', 'b'], weight), Ok(Some(6)));
+        assert_eq!(nd.min_cost_accept(&['a'], weight), Ok(None));
+        assert_eq!(nd.min_cost_accept(&['b'], weight), Ok(None));
+        let input: Vec<char> = "(())()".chars().collect();
+        assert_eq!(
+            parens().min_cost_accept(&input, |_, _, _| 1_usize),
+            Ok(Some(input.len())),
+        );
+    }
+
+    #[test]
+    fn match_pairs_reads_out_push_pop_correspondence() {
+        let d = parens();
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(d.match_pairs(&chars("(())")), Ok(vec![(1, 2), (0, 3)]));
+        assert_eq!(d.match_pairs(&chars("()()")), Ok(vec![(0, 1), (2, 3)]));
+        assert_eq!(d.match_pairs(&chars("(()")), Ok(vec![(1, 2)]));
+        assert_eq!(d.match_pairs(&chars("())(")), Ok(vec![(0, 1)]));
+    }
+
+    #[test]
+    fn find_first_balanced_span() {
+        let d = parens();
+        let noisy: Vec<char> = "))(x(())()y".chars().collect();
+        assert_eq!(d.find(&noisy), Ok(Some((4, 10))));
+        assert_eq!(d.find(&[')', '(']), Ok(None));
+        assert_eq!(d.find(&[]), Ok(None));
+    }
+
+    #[test]
+    fn custom_acceptance_on_residual_stack() {
+        let d = Deterministic::<char, char>::from_text(
+            "initial 0
+            state 0 accepting
+            on '(' push P -> 0
+            on '[' push B -> 0
+            on ')' when top P pop -> 0
+            on ']' when top B pop -> 0",
+        )
+        .unwrap();
+        let ends_in_bracket = |_: &usize, stack: &[char]| stack.last() == Some(&'B');
+        assert_eq!("([".chars().run(&d).run_with(ends_in_bracket), Ok(true));
+        assert_eq!("[(".chars().run(&d).run_with(ends_in_bracket), Ok(false));
+        assert_eq!("[()".chars().run(&d).run_with(ends_in_bracket), Ok(true));
+        assert_eq!("[]".chars().run(&d).run_with(ends_in_bracket), Ok(false));
+        assert_eq!("[)".chars().run(&d).run_with(ends_in_bracket), Ok(false));
+    }
+
+    #[cfg(feature = "persistent")]
+    #[test]
+    fn persistent_stack_matches_vec() {
+        let d = parens();
+        for input in ["", "()", "(()())", "(()", "())", ")(", "((()))()"] {
+            let mut run = input.chars().run_on_stack(&d, PersistentStack::default());
+            for r in &mut run {
+                let _: char = r.unwrap();
+            }
+            assert_eq!(
+                Ok(run.ctrl.unwrap_err()),
+                d.accept(input.chars()),
+                "{input:?}"
+            );
+            let persistent = input
+                .chars()
+                .run_on_stack(&d, PersistentStack::default())
+                .trace()
+                .unwrap();
+            let vec = input.chars().run(&d).trace().unwrap();
+            assert_eq!(format!("{persistent:?}"), format!("{vec:?}"), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn execute_is_object_safe() {
+        let d = parens();
+        let e: &dyn Execute<char, (), Ctrl = usize> = &d;
+        let mut stack = vec![];
+        let ctrl = e
+            .step(e.initial(), &mut stack, Some(&'('))
+            .unwrap()
+            .unwrap();
+        assert_eq!(stack, [()]);
+        assert!(!e.would_accept(&ctrl, &stack));
+    }
+
+    #[cfg(feature = "persistent")]
+    #[test]
+    fn dropping_a_deep_persistent_stack_does_not_overflow() {
+        let d = parens();
+        let deep = "(".repeat(1_000_000);
+        let mut run = deep.chars().run_on_stack(&d, PersistentStack::default());
+        for r in &mut run {
+            let _: char = r.unwrap();
+        }
+        assert_eq!(run.stack.len(), 1_000_000);
+        let snapshot = run.stack.clone();
+        drop(run);
+        assert_eq!(snapshot.len(), 1_000_000);
+        drop(snapshot);
+    }
+
+    #[test]
+    fn stack_depth_cap_stops_deep_nesting() {
+        let d = parens();
+        let deep = "(".repeat(1_000_000);
+        let mut run = deep.chars().run(&d).with_max_stack_depth(4);
+        assert_eq!(run.max_stack_depth(), Some(4));
+        let overflow = run.find_map(Result::err);
+        assert_eq!(overflow, Some(IllFormed::StackOverflow(4)));
+        assert_eq!(run.stack.len(), 5);
+        assert_eq!(run.ctrl, Err(false));
+        assert_eq!("((((".chars().run(&d).with_max_stack_depth(4).count(), 4,);
+        assert_eq!(
+            deep.chars()
+                .run(&d)
+                .with_max_stack_depth(4)
+                .run_with(|_, _| true),
+            Err(IllFormed::StackOverflow(4)),
+        );
+    }
+
+    #[test]
+    fn errors_report_their_position() {
+        let d: Deterministic<char, ()> = Automaton {
+            states: vec![State {
+                transitions: CurryOpt {
+                    wildcard: Some(Wildcard::Specific(vec![
+                        (
+                            Range::unit('a'),
+                            Return(Edge::Local {
+                                dst: 0,
+                                call: call!(|x| x),
+                            }),
+                        ),
+                        (Range::unit('b'), Return(Edge::Epsilon { dst: 0 })),
+                    ])),
+                    none: None,
+                    some: BTreeMap::new(),
+                },
+                accepting: true,
+            }],
+            initial: 0,
+        };
+        let mut run = "abaa".chars().run(&d);
+        assert_eq!(run.find_map(Result::err), Some(IllFormed::Epsilon));
+        assert_eq!(run.read().checked_sub(1), Some(1));
+        let mut fine = "aaa".chars().run(&d);
+        assert_eq!(fine.by_ref().count(), 3);
+        assert_eq!(fine.read(), 3);
+    }
+}
+
+mod lookup {
+    use super::parens;
+    use crate::*;
+
+    #[test]
+    fn redirect_one_edge_in_place() {
+        let mut d = parens();
+        d.states.push(State {
+            transitions: CurryOpt::default(),
+            accepting: false,
+        });
+        *get_mut!(d.states, 0)
+            .transitions
+            .get_mut((None, (&'(', ())))
+            .unwrap()
+            .dst_mut() = 1;
+        assert_eq!(d.accept("()".chars()), Ok(false));
+        assert_eq!(d.accept("".chars()), Ok(true));
+        assert_eq!(
+            get_mut!(d.states, 0)
+                .transitions
+                .get_mut((Some(&()), (&')', ())))
+                .map(|edge| *edge.dst()),
+            Some(0),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "overlapping ranges")]
+    fn get_mut_rejects_overlaps_like_get() {
+        let local = |dst| {
+            Return(Edge::<char, (), usize>::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let mut wild = Wildcard::Specific(vec![
+            (
+                Range {
+                    first: 'a',
+                    last: 'm',
+                },
+                local(0),
+            ),
+            (
+                Range {
+                    first: 'h',
+                    last: 'z',
+                },
+                local(1),
+            ),
+        ]);
+        drop(wild.get_mut((&'h', ())));
+    }
+}
+
+mod call {
+    use crate::*;
+    use core::{
+        cmp::Ordering,
+        hash::{Hash, Hasher as _},
+        iter::once,
+    };
+    use std::collections::{hash_map::DefaultHasher, BTreeMap};
+
+    #[test]
+    fn identity_is_source_only() {
+        fn hash<T: Hash>(t: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            t.hash(&mut hasher);
+            hasher.finish()
+        }
+        let lhs: Call<u8, u8> = Call::new(|x| x, "identity".to_owned());
+        let rhs: Call<u8, u8> = Call::new(|x| x.wrapping_add(0), "identity".to_owned());
+        assert_eq!(lhs, rhs);
+        assert_eq!(lhs.cmp(&rhs), Ordering::Equal);
+        assert_eq!(hash(&lhs), hash(&rhs));
+    }
+
+    #[test]
+    fn registry_round_trip() {
+        let original: Call<u8, u8> = call!(|x: u8| x.wrapping_add(1));
+        let mut registry: BTreeMap<String, fn(u8) -> u8> = BTreeMap::new();
+        let _ = registry.insert(original.src.clone(), original.ptr);
+        let reloaded = Call::from_registry(&original.src, &registry).unwrap();
+        assert_eq!(reloaded, original);
+        assert_eq!((reloaded.ptr)(41), 42);
+        assert_eq!(Call::from_registry("|x| x", &registry), None);
+    }
+
+    #[test]
+    fn from_tuple() {
+        fn double(x: u8) -> u8 {
+            x.wrapping_mul(2)
+        }
+        let ptr: fn(u8) -> u8 = double;
+        let call: Call<u8, u8> = (ptr, "double".to_owned()).into();
+        assert_eq!(call.src, "double");
+        assert_eq!(format!("{call:?}"), "call!(double)");
+        assert_eq!((call.ptr)(21), 42);
+    }
+
+    #[test]
+    fn calls_build_ast_nodes() {
+        #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+        enum Node {
+            Open,
+            Close,
+            Leaf(char),
+        }
+        let state = State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Specific(vec![
+                    (
+                        Range::unit('('),
+                        Return(Edge::Call {
+                            dst: once(0).collect(),
+                            call: call!(|_| Node::Open),
+                            push: (),
+                        }),
+                    ),
+                    (
+                        Range {
+                            first: 'a',
+                            last: 'z',
+                        },
+                        Return(Edge::Local {
+                            dst: once(0).collect(),
+                            call: call!(Node::Leaf),
+                        }),
+                    ),
+                ])),
+                none: None,
+                some: once((
+                    (),
+                    Wildcard::Specific(vec![(
+                        Range::unit(')'),
+                        Return(Edge::Return {
+                            dst: once(0).collect(),
+                            call: call!(|_| Node::Close),
+                        }),
+                    )]),
+                ))
+                .collect(),
+            },
+            accepting: true,
+        };
+        let letters = State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Specific(vec![(
+                    Range {
+                        first: 'a',
+                        last: 'z',
+                    },
+                    Return(Edge::Local {
+                        dst: once(0).collect(),
+                        call: call!(Node::Leaf),
+                    }),
+                )])),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: false,
+        };
+        let nd: Nondeterministic<char, (), char, Node> = Automaton {
+            states: vec![state, letters],
+            initial: [0, 1].into_iter().collect(),
+        };
+        assert_eq!(nd.check(), Ok(()));
+        let d = nd.determinize().unwrap();
+        assert_eq!(d.check(), Ok(()));
+        let nodes: Vec<_> = "(x)"
+            .chars()
+            .scan(vec![], |stack: &mut Vec<()>, token| {
+                let edge = get!(d.states, d.initial)
+                    .transitions
+                    .get((stack.last(), (&token, ())))?;
+                let node = (edge.call()?.ptr)(token);
+                let _: usize = edge.clone().invoke(stack).ok()?;
+                Some(node)
+            })
+            .collect();
+        assert_eq!(nodes, [Node::Open, Node::Leaf('x'), Node::Close]);
+        assert_eq!(
+            d.transduce("(x)".chars(), |call| call.src.clone()),
+            Ok(Some(vec![
+                "|_| Node::Open".to_owned(),
+                "Node::Leaf".to_owned(),
+                "|_| Node::Close".to_owned(),
+            ])),
+        );
+    }
+}
+
+mod subc {
+    use crate::*;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn generalized_parens_is_deterministic() {
+        let nd = super::parens().generalize();
+        assert!(nd.is_deterministic());
+        assert_eq!(nd.into_deterministic_unchecked(), Some(super::parens()));
+    }
+
+    #[test]
+    fn non_discrete_tokens_determinize() {
+        let state = |dst| State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Specific(vec![(
+                    Range::unit("word".to_owned()),
+                    Return(Edge::Local {
+                        dst: BTreeSet::from([dst]),
+                        call: call!(|x| x),
+                    }),
+                )])),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: dst == 1,
+        };
+        let nd: Nondeterministic<String, ()> = Automaton {
+            states: vec![state(0), state(1)],
+            initial: BTreeSet::from([0, 1]),
+        };
+        let d = nd.compile().unwrap().automaton;
+        assert_eq!(d.accept(["word".to_owned()]), Ok(true));
+        assert_eq!(d.accept(["other".to_owned()]), Ok(false));
+    }
+
+    #[test]
+    fn wildcard_overlapping_empty_stack_reports_the_runtime_conflict() {
+        let push = |call| {
+            Return(Edge::Call {
+                dst: BTreeSet::from([0]),
+                call,
+                push: (),
+            })
+        };
+        let nd: Nondeterministic<char, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range::unit('a'),
+                            push(call!(|x| x)),
+                        )])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                State {
+                    transitions: CurryOpt {
+                        wildcard: None,
+                        none: Some(Wildcard::Specific(vec![(
+                            Range {
+                                first: 'a',
+                                last: 'z',
+                            },
+                            push(call!(|y| y)),
+                        )])),
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+            ],
+            initial: BTreeSet::from([0, 1]),
+        };
+        let conflict = IllFormed::CallMergeConflict("|x| x".to_owned(), "|y| y".to_owned());
+        assert_eq!(
+            nd.accept("a".chars()),
+            Err(IllFormed::AmbiguousInput('a', Box::new(conflict.clone())))
+        );
+        assert_eq!(nd.determinize(), Err(conflict));
+    }
+
+    #[test]
+    fn no_initial_state_is_not_deterministic() {
+        let mut nd = super::parens().generalize();
+        nd.initial.clear();
+        assert!(!nd.is_deterministic());
+        assert_eq!(nd.into_deterministic_unchecked(), None);
+    }
+
+    #[test]
+    fn branching_is_not_deterministic() {
+        let nd: Nondeterministic<char, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Any(Return(Edge::Local {
+                            dst: [0, 1].into_iter().collect(),
+                            call: call!(|x| x),
+                        }))),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                State {
+                    transitions: CurryOpt::default(),
+                    accepting: true,
+                },
+            ],
+            initial: BTreeSet::from([0]),
+        };
+        assert!(!nd.is_deterministic());
+    }
+
+    #[test]
+    fn owned_determinization_matches_borrowed() {
+        let nd = Nondeterministic::bounded_depth(64, (), &Range::unit('('), &Range::unit(')'));
+        let borrowed = nd.determinize().unwrap();
+        assert_eq!(borrowed.states.len(), 65);
+        assert_eq!(nd.clone().into_deterministic(), Ok(borrowed));
+
+        // Subsets of more than one state, plus an unreachable state to prune
+        let mut union =
+            nd | Nondeterministic::bounded_depth(3, (), &Range::unit('('), &Range::unit(')'));
+        union.states.push(State::default());
+        let union_borrowed = union.determinize().unwrap();
+        assert_eq!(union.into_deterministic(), Ok(union_borrowed));
+    }
+
+    #[test]
+    fn determinize_and_accept_in_one_call() {
+        let nd = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        let (d, accepted) = nd.determinize_and_accept("(())".chars()).unwrap();
+        assert!(accepted);
+        assert_eq!(d.accept("(())".chars()), Ok(true));
+        for input in ["", "()", "((()))", "(()", ")(", "()(())"] {
+            assert_eq!(
+                d.accept(input.chars()).ok(),
+                nd.accept(input.chars()).ok(),
+                "{input:?}"
+            );
+        }
+        assert_eq!(
+            nd.determinize_and_accept("((()))".chars()).map(|(_, b)| b),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn lazy_determinization_agrees_with_eager() {
+        let nd = Nondeterministic::bounded_depth(3, (), &Range::unit('('), &Range::unit(')'));
+        let d = nd.determinize().unwrap();
+        let mut lazy = LazyDeterministic::new(nd.clone());
+        assert_eq!(lazy.graph(), &nd);
+        assert_eq!(lazy.explored(), 1);
+        for input in [
+            "", "()", "(())", "((()))", "(((())))", "(()", "())", ")(", "()()()", "(()())", "x",
+        ] {
+            for _ in 0_u8..2 {
+                assert_eq!(
+                    lazy.accept(input.chars()).ok(),
+                    d.accept(input.chars()).ok(),
+                    "{input:?}"
+                );
+            }
+        }
+        assert!(lazy.explored() <= d.states.len());
+        assert_eq!(lazy.into_inner(), nd);
+    }
+
+    #[test]
+    fn lenient_determinization_prefers_pushes() {
+        let state = |edge| State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Specific(vec![(Range::unit('a'), Return(edge))])),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: true,
+        };
+        let nd: Nondeterministic<char, ()> = Automaton {
+            states: vec![
+                state(Edge::Call {
+                    dst: BTreeSet::from([0]),
+                    call: call!(|x| x),
+                    push: (),
+                }),
+                state(Edge::Local {
+                    dst: BTreeSet::from([1]),
+                    call: call!(|x| x),
+                }),
+            ],
+            initial: BTreeSet::from([0, 1]),
+        };
+        assert!(matches!(
+            nd.determinize(),
+            Err(IllFormed::RangeMergeConflict(..))
+        ));
+        let (d, diagnostics) = nd.determinize_lenient().unwrap();
+        assert!(matches!(
+            diagnostics.as_slice(),
+            [IllFormed::RangeMergeConflict(..)]
+        ));
+        assert_eq!(d.states.len(), 2);
+        assert_eq!(d.run_to_end("aa".chars()), Ok((false, vec![(), ()])));
+    }
+
+    #[test]
+    fn lenient_determinization_drops_only_conflicting_locals() {
+        let push = Return(Edge::Call {
+            dst: BTreeSet::from([0]),
+            call: call!(|x| x),
+            push: (),
+        });
+        let local = Return(Edge::Local {
+            dst: BTreeSet::from([1]),
+            call: call!(|x| x),
+        });
+        let pushing = State {
+            transitions: CurryOpt {
+                wildcard: None,
+                none: Some(Wildcard::Specific(vec![(Range::unit('a'), push)])),
+                some: BTreeMap::new(),
+            },
+            accepting: false,
+        };
+        let nd: Nondeterministic<char, ()> = Automaton {
+            states: vec![
+                pushing.clone(),
+                State {
+                    transitions: CurryOpt {
+                        wildcard: None,
+                        none: Some(Wildcard::Specific(vec![(
+                            Range {
+                                first: 'a',
+                                last: 'c',
+                            },
+                            local.clone(),
+                        )])),
+                        some: BTreeMap::from([((), Wildcard::Any(local.clone()))]),
+                    },
+                    accepting: true,
+                },
+            ],
+            initial: BTreeSet::from([0, 1]),
+        };
+        let (d, diagnostics) = nd.determinize_lenient().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        // Only the empty-stack local edge on 'a' met the push: 'b' and 'c' stay, and so does anything on a nonempty stack
+        assert_eq!(d.accept("b".chars()), Ok(true));
+        assert_eq!(d.accept("c".chars()), Ok(true));
+        let start = &get!(d.states, d.initial).transitions;
+        assert!(matches!(
+            start.none,
+            Some(Wildcard::Specific(ref v)) if v.len() == 2,
+        ));
+        assert!(matches!(
+            start.some.get(&()),
+            Some(&Wildcard::Any(Return(Edge::Local { .. }))),
+        ));
+
+        // A local edge on any stack and any token can't give up just 'a', so that still fails
+        let uncarvable: Nondeterministic<char, ()> = Automaton {
+            states: vec![
+                pushing,
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Any(local)),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: true,
+                },
+            ],
+            initial: BTreeSet::from([0, 1]),
+        };
+        assert!(matches!(
+            uncarvable.determinize_lenient(),
+            Err(IllFormed::EdgeMergeConflict(..)),
+        ));
+    }
+
+    #[test]
+    fn unreachable_states_are_ignored() {
+        let clean = Nondeterministic::bounded_depth(2, (), &Range::unit('('), &Range::unit(')'));
+        let mut nd = clean.clone();
+        // Nothing leads here, and it points nowhere
+        nd.states.push(State {
+            transitions: CurryOpt {
+                wildcard: Some(Wildcard::Any(Return(Edge::Local {
+                    dst: BTreeSet::from([99]),
+                    call: call!(|x| x),
+                }))),
+                none: None,
+                some: BTreeMap::new(),
+            },
+            accepting: true,
+        });
+        assert!(nd.check().is_err());
+        assert_eq!(nd.determinize(), clean.determinize());
+        assert!(nd.remove_unreachable());
+        assert!(!nd.remove_unreachable());
+        assert_eq!(nd, clean);
+        assert_eq!(nd.check(), Ok(()));
+    }
+}
+
+mod compile {
+    use super::parens;
+
+    #[test]
+    fn compile_once_validate_many() {
+        let nd = parens().generalize();
+        let compiled = nd.compile().unwrap();
+        let inputs: Vec<Vec<char>> = (0_u32..1000)
+            .map(|i| {
+                (0..(32 - i.leading_zeros()))
+                    .map(|bit| if (i >> bit) & 1 == 0 { '(' } else { ')' })
+                    .collect()
+            })
+            .collect();
+        let verdicts: Result<Vec<_>, _> = compiled.accept_all(inputs.iter().cloned()).collect();
+        let expected: Vec<_> = inputs
+            .iter()
+            .map(|input| nd.accept(input.iter().copied()).unwrap())
+            .collect();
+        assert_eq!(verdicts.unwrap(), expected);
+        assert_eq!(compiled.accept("(()())".chars()), Ok(true));
+    }
+}
+
+mod text {
+    use super::parens;
+    use crate::*;
+
+    const PARENS: &str =
+        "initial 0\nstate 0 accepting\non '(' push P -> 0\non ')' when top P pop -> 0\n";
+
+    #[test]
+    fn parens_round_trip() {
+        let d = Deterministic::<char, char>::from_text(PARENS).unwrap();
+        assert_eq!(d.to_text(), PARENS);
+        assert_eq!(Deterministic::from_text(&d.to_text()), Ok(d.clone()));
+        for input in ["", "()", "(())()", "(", ")", "())", ")("] {
+            assert_eq!(
+                d.accept(input.chars()).ok(),
+                parens().accept(input.chars()).ok()
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_ranges_and_wildcards() {
+        let text = "initial 0\nstate 0\non ' '..'\'' local -> 1\nstate 1 accepting\non * when top X pop -> 1\n";
+        let d = Deterministic::<char, char>::from_text(text).unwrap();
+        assert_eq!(d.to_text(), text);
+        assert_eq!(d.accept(" ".chars()), Ok(true));
+        assert_eq!(d.accept("'".chars()), Ok(true));
+        assert_eq!(d.accept("a".chars()), Ok(false));
+    }
+
+    #[test]
+    fn malformed_lines_report_line_numbers() {
+        assert_eq!(
+            Deterministic::<char, char>::from_text("initial 0\nstate 1\n").map_err(|e| e.line),
+            Err(2),
+        );
+        assert_eq!(
+            Deterministic::<char, char>::from_text("state 0\n").map_err(|e| e.line),
+            Err(2),
+        );
+        assert_eq!(
+            Deterministic::<char, char>::from_text(
+                "initial 0\nstate 0\non * local -> 0\non 'a' local -> 0\n"
+            )
+            .map_err(|e| e.line),
+            Err(4),
+        );
+    }
+
+    #[test]
+    fn ill_formed_automata_are_rejected() {
+        assert_eq!(
+            Deterministic::<char, char>::from_text("initial 0\nstate 0\non '(' push P -> 5\n")
+                .map_err(|e| e.line),
+            Err(4),
+        );
+        assert_eq!(
+            Deterministic::<char, char>::from_text("initial 3\nstate 0\n").map_err(|e| e.line),
+            Err(3),
+        );
+    }
+}
+
+mod json {
+    use super::parens;
+    use crate::*;
+
+    #[test]
+    fn parens_round_trip() {
+        let d = Deterministic::<char, char>::from_text(
+            "initial 0\nstate 0 accepting\non '(' push P -> 0\non ')' when top P pop -> 0\n",
+        )
+        .unwrap();
+        let json = d.to_json();
+        assert_eq!(
+            json,
+            r#"{"initial":0,"states":[{"accepting":true,"transitions":[{"range":["(","("],"action":"push","push":"P","dst":0,"call":"|x| x"},{"top":"P","range":[")",")"],"action":"pop","dst":0,"call":"|x| x"}]}]}"#,
+        );
+        let back = Deterministic::<char, char>::from_json(&json).unwrap();
+        assert_eq!(back, d);
+        for input in ["", "()", "(())()", "(", ")", "())", ")("] {
+            assert_eq!(
+                back.accept(input.chars()).ok(),
+                parens().accept(input.chars()).ok()
+            );
+        }
+    }
+
+    #[test]
+    fn escapes_and_whitespace() {
+        let json = "{ \"initial\" : 0,\n \"states\": [ { \"accepting\": false, \"transitions\": [\n  { \"top\": null, \"range\": [\"\\\"\", \"\\\\\"], \"action\": \"local\", \"dst\": 0, \"call\": \"|x| x\" },\n  { \"top\": \"\\u0041\", \"range\": null, \"action\": \"epsilon\", \"dst\": 0 } ] } ] }";
+        let d = Deterministic::<char, char>::from_json(json).unwrap();
+        let Some(Wildcard::Specific(ref v)) = get!(d.states, 0).transitions.none else {
+            panic!("expected specific tokens on an empty stack");
+        };
+        assert_eq!(
+            v.iter().map(|&(ref range, _)| range).collect::<Vec<_>>(),
+            vec![&Range {
+                first: '"',
+                last: '\\',
+            }],
+        );
+        assert!(get!(d.states, 0).transitions.some.contains_key(&'A'));
+        assert_eq!(Deterministic::from_json(&d.to_json()), Ok(d));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let message = |json| Deterministic::<char, char>::from_json(json).map_err(|e| e.message);
+        assert_eq!(
+            message(""),
+            Err("expected a value but the input ended".to_owned()),
+        );
+        assert_eq!(
+            message(r#"{"states":[]}"#),
+            Err("missing `initial`".to_owned()),
+        );
+        assert_eq!(
+            message(r#"{"initial":0,"states":[]} x"#),
+            Err("trailing characters at byte 26".to_owned()),
+        );
+        assert_eq!(
+            Deterministic::<char, char>::from_json(
+                r#"{"initial":0,"states":[{"accepting":true,"transitions":[{"range":["b","a"],"action":"local","dst":0,"call":""}]}]}"#,
+            )
+            .map_err(|e| e.message),
+            Err("range ends before it starts".to_owned()),
+        );
+        assert!(message(r#"{"initial":1,"states":[]}"#)
+            .unwrap_err()
+            .starts_with("ill-formed automaton"));
+        assert!(message(r#"{"initial":0,"states":[{"accepting":true,"transitions":[{"range":null,"action":"local","dst":7,"call":""}]}]}"#)
+            .unwrap_err()
+            .starts_with("ill-formed automaton"));
+        assert_eq!(
+            message(&"[".repeat(1_000_000)),
+            Err("nested too deeply at byte 128".to_owned()),
+        );
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        let json = r#"{"initial":0,"states":[{"accepting":true,"transitions":[{"range":["\ud83d\ude00","\uD83D\uDE00"],"action":"local","dst":0,"call":""}]}]}"#;
+        let d = Deterministic::<char, char>::from_json(json).unwrap();
+        assert_eq!(d.accept("\u{1f600}".chars()), Ok(true));
+        assert_eq!(Deterministic::from_json(&d.to_json()), Ok(d));
+        let message = |text| Deterministic::<char, char>::from_json(text).map_err(|e| e.message);
+        assert_eq!(
+            message(
+                r#"{"initial":0,"states":[{"accepting":true,"transitions":[{"range":["\ud83d","\ud83d"],"action":"local","dst":0,"call":""}]}]}"#
+            ),
+            Err("bad `\\u` escape at byte 67".to_owned()),
+        );
+    }
+}
+
+mod lexer {
+    use crate::*;
+
+    #[test]
+    fn longest_match_with_priority() {
+        let rule = |text: &str| Deterministic::<char, char>::from_text(text).unwrap();
+        let lexer = Lexer {
+            rules: vec![
+                (
+                    rule("initial 0\nstate 0\non 'i' local -> 1\nstate 1\non 'f' local -> 2\nstate 2 accepting\n"),
+                    "keyword",
+                ),
+                (
+                    rule("initial 0\nstate 0\non 'a'..'z' local -> 1\nstate 1 accepting\non 'a'..'z' local -> 1\n"),
+                    "identifier",
+                ),
+                (
+                    rule("initial 0\nstate 0\non ' ' local -> 1\nstate 1 accepting\non ' ' local -> 1\n"),
+                    "space",
+                ),
+            ],
+        };
+        let input: Vec<char> = "if iffy  i".chars().collect();
+        let mut rest = input.as_slice();
+        let mut tokens = vec![];
+        while let Some((&kind, len)) = lexer.next_token(rest).unwrap() {
+            tokens.push((kind, len));
+            rest = get!(rest, len..);
+        }
+        assert!(rest.is_empty());
+        assert_eq!(
+            tokens,
+            vec![
+                ("keyword", 2),
+                ("space", 1),
+                ("identifier", 4),
+                ("space", 2),
+                ("identifier", 1),
+            ],
+        );
+        assert_eq!(lexer.next_token(&['!']), Ok(None));
+    }
+}
+
+mod reverse {
+    use super::parens;
+    use crate::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn reverse_parens_accepts_mirrored_parens() {
+        let nd = parens().generalize();
+        let rev = nd.clone().reverse().unwrap();
+        for input in ["", "()", "(()())", "((()))", "(", ")(", "())", "(()"] {
+            assert_eq!(
+                rev.accept(input.chars().rev()),
+                nd.accept(input.chars()),
+                "{input:?}",
+            );
+        }
+        assert_eq!(rev.accept(")(".chars()), Ok(true));
+        assert_eq!(rev.accept("()".chars()), Ok(false));
+    }
+
+    #[test]
+    fn bidirectional_parens() {
+        let d = parens();
+        for input in ["", "()", "(()())", "((()))", "(", ")(", "())", "(()"] {
+            let tokens: Vec<char> = input.chars().collect();
+            assert_eq!(
+                d.accept_bidirectional(&tokens),
+                d.accept(input.chars()),
+                "{input:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn bidirectional_needs_one_accepting_state() {
+        let d: Deterministic<char, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(
+                            Range::unit('a'),
+                            Return(Edge::Local {
+                                dst: 1,
+                                call: call!(|x| x),
+                            }),
+                        )])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: true,
+                },
+                State {
+                    transitions: CurryOpt::default(),
+                    accepting: true,
+                },
+            ],
+            initial: 0,
+        };
+        assert_eq!(d.accept_bidirectional(&['a']), Err(IllFormed::Irreversible));
+    }
+}
+
+mod regex {
+    use crate::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn local_only_a_star_b() {
+        let local = |dst| {
+            Return(Edge::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let d: Deterministic<char, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![
+                            (Range::unit('a'), local(0)),
+                            (Range::unit('b'), local(1)),
+                        ])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                State {
+                    transitions: CurryOpt::default(),
+                    accepting: true,
+                },
+            ],
+            initial: 0,
+        };
+        assert_eq!(d.to_regex().as_deref(), Some("a*b"));
+    }
+
+    #[test]
+    fn stack_edges_have_no_regex() {
+        assert_eq!(super::parens().to_regex(), None);
+    }
+}
+
+mod witness {
+    use super::parens;
+    use crate::*;
+
+    #[test]
+    fn lone_close_paren_distinguishes() {
+        let mut lenient = parens();
+        get_mut!(lenient.states, 0).transitions.none = Some(Wildcard::Specific(vec![(
+            Range::unit(')'),
+            Return(Edge::Local {
+                dst: 0,
+                call: call!(|x| x),
+            }),
+        )]));
+        assert_eq!(parens().distinguishing_input(&parens()), None);
+        assert_eq!(parens().distinguishing_input(&lenient), Some(vec![')']));
+        assert_eq!(lenient.distinguishing_input(&parens()), Some(vec![')']));
+    }
+}
+
+mod product {
+    use super::parens;
+    use crate::*;
+
+    #[test]
+    fn intersection_agrees_with_both() {
+        let shallow = Nondeterministic::bounded_depth(2, 'x', &Range::unit('('), &Range::unit(')'))
+            .determinize()
+            .unwrap();
+        let both = parens() & shallow.clone();
+        for input in [
+            "", "()", "(())", "()(())", "((()))", "(", ")(", "())", "(()",
+        ] {
+            assert_eq!(
+                both.accept(input.chars()),
+                Ok(parens().accept(input.chars()).unwrap()
+                    && shallow.accept(input.chars()).unwrap()),
+                "{input:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn union_keeps_both_initial_states() {
+        let small = Nondeterministic::bounded_depth(1, (), &Range::unit('('), &Range::unit(')'));
+        let never = Nondeterministic::default();
+        let either = never | small;
+        assert_eq!(either.states.len(), 3);
+        assert_eq!(either.initial.len(), 2);
+        assert_eq!(either.accept("()".chars()), Ok(true));
+        assert_eq!(either.accept("(())".chars()), Ok(false));
+    }
+}
+
+#[cfg(feature = "graphemes")]
+mod grapheme {
+    use crate::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn emoji_graphemes_are_single_tokens() {
+        let local = |dst| {
+            Return(Edge::Local {
+                dst,
+                call: call!(|x| x),
+            })
+        };
+        let thumbs = Range {
+            first: "\u{1f44d}".to_owned(),
+            last: "\u{1f44d}\u{1f3ff}".to_owned(),
+        };
+        let d: Deterministic<String, ()> = Automaton {
+            states: vec![
+                State {
+                    transitions: CurryOpt {
+                        wildcard: Some(Wildcard::Specific(vec![(thumbs, local(1))])),
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: false,
+                },
+                State {
+                    transitions: CurryOpt {
+                        wildcard: None,
+                        none: None,
+                        some: BTreeMap::new(),
+                    },
+                    accepting: true,
+                },
+            ],
+            initial: 0,
+        };
+        for text in ["\u{1f44d}", "\u{1f44d}\u{1f3fd}", "\u{1f44d}\u{1f3ff}"] {
+            assert_eq!(grapheme_run(text, &d).finish(), Ok(true), "{text:?}");
+        }
+        assert_eq!(grapheme_run("\u{1f44d}\u{1f44d}", &d).finish(), Ok(false));
+        assert_eq!(grapheme_run("\u{1f44e}", &d).finish(), Ok(false));
+        assert_eq!(
+            d.accept("\u{1f44d}\u{1f3fd}".chars().map(String::from)),
+            Ok(false)
+        );
+    }
+}
+
+#[cfg(feature = "checked")]
+mod checked {
+    use crate::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    #[should_panic(expected = "as both a wildcard and a non-wildcard")]
+    fn wildcard_and_specific_stack_symbol_collide() {
+        let edge = Wildcard::Any(Return(Edge::Local {
+            dst: 0,
+            call: call!(|x| x),
+        }));
+        let d: Deterministic<char, ()> = Automaton {
+            states: vec![State {
+                transitions: CurryOpt {
+                    wildcard: Some(edge.clone()),
+                    none: Some(edge),
+                    some: BTreeMap::new(),
+                },
+                accepting: true,
+            }],
+            initial: 0,
+        };
+        drop(d.accept("a".chars()));
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod trace {
+    use super::parens;
+    use core::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::{
+        field::{Field, Visit},
+        subscriber::with_default,
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, Layer, SubscriberExt as _},
+        registry,
+    };
+
+    /// Layer recording the message of every event it sees.
+    struct Messages(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Messages {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                self.0.lock().unwrap().push(format!("{value:?}"));
+            }
+        }
+    }
+
+    impl<Sub: Subscriber> Layer<Sub> for Messages {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, Sub>) {
+            event.record(&mut Self(Arc::clone(&self.0)));
+        }
+    }
+
+    #[test]
+    fn determinization_emits_events() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let subscriber = registry().with(Messages(Arc::clone(&messages)));
+        let nd = parens().generalize();
+        let d = with_default(subscriber, || nd.determinize().unwrap());
+        assert_eq!(d.states.len(), 1);
+        let recorded = messages.lock().unwrap().clone();
+        assert!(recorded.iter().any(|m| m == "subset discovered"));
+        assert!(recorded.iter().any(|m| m == "states merged"));
+    }
 }
@@ -0,0 +1,288 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Compact, human-editable text format for deterministic automata.
+//!
+//! ```text
+//! initial 0
+//! state 0 accepting
+//! on '(' push P -> 0
+//! on ')' when top P pop -> 0
+//! ```
+//!
+//! Each `on` line adds a transition to the most recent `state`:
+//! - tokens are `*` (anything), `'a'` (one token), or `'a'..'z'` (an inclusive range);
+//! - an optional `when empty` or `when top S` restricts it to an empty stack or to `S` on top;
+//! - the action is `push S`, `pop`, `local`, or `epsilon`; and
+//! - `-> n` names the destination state.
+//!
+//! Blank lines and lines starting with `#` are ignored. Calls aren't represented, so every call parses as `call!(|x| x)`.
+
+use crate::{CurryOpt, Deterministic, Edge, Range, Return, State, Wildcard};
+use core::{fmt, str::FromStr};
+
+/// Failure to parse the text format, with the (one-indexed) line where it happened.
+#[allow(clippy::exhaustive_structs)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ParseError {
+    /// One-indexed line number (or one past the last line if something was missing at the end).
+    pub line: usize,
+    /// What went wrong.
+    pub message: String,
+}
+
+/// Which stack condition a transition is keyed on.
+enum Key<S> {
+    /// Any stack.
+    Any,
+    /// Empty stack.
+    Empty,
+    /// This symbol on top.
+    Top(S),
+}
+
+impl<
+        A: 'static + fmt::Debug + fmt::Display + Clone + FromStr + Ord,
+        S: 'static + fmt::Debug + fmt::Display + Copy + FromStr + Ord,
+    > Deterministic<A, S>
+{
+    /// Parse the text format described in this module's documentation.
+    /// # Errors
+    /// If any line is malformed, states are out of order, `initial` is missing,
+    /// or the automaton as a whole is ill-formed (e.g. a transition to a state that doesn't exist).
+    #[inline]
+    pub fn from_text(text: &str) -> Result<Self, ParseError> {
+        let mut states: Vec<State<A, S, usize>> = vec![];
+        let mut initial = None;
+        for (i, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            let fail = |message: &str| ParseError {
+                line: i.saturating_add(1),
+                message: message.to_owned(),
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("initial ") {
+                initial = Some(
+                    rest.trim()
+                        .parse()
+                        .ok()
+                        .ok_or_else(|| fail("bad initial state"))?,
+                );
+            } else if let Some(rest) = line.strip_prefix("state ") {
+                let mut words = rest.split_whitespace();
+                if words.next().and_then(|word| word.parse().ok()) != Some(states.len()) {
+                    return Err(fail("states must be numbered in order from 0"));
+                }
+                let accepting = match (words.next(), words.next()) {
+                    (None, _) => false,
+                    (Some("accepting"), None) => true,
+                    _ => {
+                        return Err(fail(
+                            "expected `accepting` or nothing after the state index",
+                        ))
+                    }
+                };
+                states.push(State {
+                    transitions: CurryOpt::default(),
+                    accepting,
+                });
+            } else if let Some(rest) = line.strip_prefix("on ") {
+                let state = states
+                    .last_mut()
+                    .ok_or_else(|| fail("transition before any state"))?;
+                parse_transition(state, rest).map_err(fail)?;
+            } else {
+                return Err(fail("expected `initial`, `state`, or `on`"));
+            }
+        }
+        let end = text.lines().count().saturating_add(1);
+        let automaton = Self {
+            states,
+            initial: initial.ok_or_else(|| ParseError {
+                line: end,
+                message: "missing `initial`".to_owned(),
+            })?,
+        };
+        automaton.check().map_err(|e| ParseError {
+            line: end,
+            message: format!("ill-formed automaton: {e:?}"),
+        })?;
+        Ok(automaton)
+    }
+
+    /// Write this automaton in the text format described in this module's documentation.
+    /// Calls are dropped.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("initial {}", self.initial)];
+        for (i, state) in self.states.iter().enumerate() {
+            lines.push(format!(
+                "state {i}{}",
+                if state.accepting { " accepting" } else { "" },
+            ));
+            let keyed = state
+                .transitions
+                .wildcard
+                .iter()
+                .map(|wild| (String::new(), wild))
+                .chain(
+                    state
+                        .transitions
+                        .none
+                        .iter()
+                        .map(|wild| (" when empty".to_owned(), wild)),
+                )
+                .chain(
+                    state
+                        .transitions
+                        .some
+                        .iter()
+                        .map(|(s, wild)| (format!(" when top {s}"), wild)),
+                );
+            for (condition, wild) in keyed {
+                let edges = match *wild {
+                    Wildcard::Any(Return(ref edge)) => vec![("*".to_owned(), edge)],
+                    Wildcard::Specific(ref v) => v
+                        .iter()
+                        .map(|&(ref range, Return(ref edge))| {
+                            let tokens = if range.first == range.last {
+                                format!("'{}'", range.first)
+                            } else {
+                                format!("'{}'..'{}'", range.first, range.last)
+                            };
+                            (tokens, edge)
+                        })
+                        .collect(),
+                };
+                for (tokens, edge) in edges {
+                    let action = match *edge {
+                        Edge::Call { dst, push, .. } => format!("push {push} -> {dst}"),
+                        Edge::Return { dst, .. } => format!("pop -> {dst}"),
+                        Edge::Local { dst, .. } => format!("local -> {dst}"),
+                        Edge::Epsilon { dst } => format!("epsilon -> {dst}"),
+                        Edge::Phantom(..) => never!(),
+                    };
+                    lines.push(format!("on {tokens}{condition} {action}"));
+                }
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// Parse everything after `on ` and add the transition it describes to this state.
+/// # Errors
+/// A description of what went wrong.
+#[inline]
+fn parse_transition<
+    A: 'static + fmt::Debug + Clone + FromStr + Ord,
+    S: 'static + fmt::Debug + Copy + FromStr + Ord,
+>(
+    state: &mut State<A, S, usize>,
+    rest: &str,
+) -> Result<(), &'static str> {
+    let (range, after_tokens) = parse_tokens(rest).ok_or("bad tokens")?;
+    let words: Vec<&str> = after_tokens.split_whitespace().collect();
+    let (key, action) = match *words.as_slice() {
+        ["when", "empty", ref action @ ..] => (Key::Empty, action),
+        ["when", "top", s, ref action @ ..] => {
+            (Key::Top(s.parse().ok().ok_or("bad stack symbol")?), action)
+        }
+        ref action => (Key::Any, action),
+    };
+    let parse_dst = |to: &str| to.parse().ok().ok_or("bad destination");
+    let edge = match *action {
+        ["push", symbol, "->", to] => Edge::Call {
+            dst: parse_dst(to)?,
+            call: call!(|x| x),
+            push: symbol.parse().ok().ok_or("bad stack symbol")?,
+        },
+        ["pop", "->", to] => Edge::Return {
+            dst: parse_dst(to)?,
+            call: call!(|x| x),
+        },
+        ["local", "->", to] => Edge::Local {
+            dst: parse_dst(to)?,
+            call: call!(|x| x),
+        },
+        ["epsilon", "->", to] => Edge::Epsilon {
+            dst: parse_dst(to)?,
+        },
+        _ => return Err("expected `push S`, `pop`, `local`, or `epsilon`, then `-> n`"),
+    };
+    let added = match key {
+        Key::Any => add(&mut state.transitions.wildcard, range, edge),
+        Key::Empty => add(&mut state.transitions.none, range, edge),
+        Key::Top(s) => {
+            let mut slot = state.transitions.some.remove(&s);
+            let added = add(&mut slot, range, edge);
+            if let Some(wild) = slot {
+                drop(state.transitions.some.insert(s, wild));
+            }
+            added
+        }
+    };
+    if added {
+        Ok(())
+    } else {
+        Err("`*` can't share a stack condition with any other tokens")
+    }
+}
+
+/// Add a transition on these tokens (or on any token, if `None`), returning `false` if it would overlap a wildcard.
+#[inline]
+#[allow(clippy::type_complexity)]
+pub(crate) fn add<A: 'static + fmt::Debug + Ord, S: 'static + fmt::Debug + Copy + Ord>(
+    slot: &mut Option<Wildcard<A, Return<Edge<A, S, usize>>>>,
+    range: Option<Range<A>>,
+    edge: Edge<A, S, usize>,
+) -> bool {
+    match (slot.as_mut(), range) {
+        (None, None) => *slot = Some(Wildcard::Any(Return(edge))),
+        (None, Some(r)) => *slot = Some(Wildcard::Specific(vec![(r, Return(edge))])),
+        (Some(&mut Wildcard::Specific(ref mut v)), Some(r)) => v.push((r, Return(edge))),
+        _ => return false,
+    }
+    true
+}
+
+/// Parse `*`, `'a'`, or `'a'..'z'` off the front of a string, returning `None` for `*`, and whatever's left.
+#[inline]
+fn parse_tokens<A: Clone + FromStr + Ord>(s: &str) -> Option<(Option<Range<A>>, &str)> {
+    if let Some(rest) = s.strip_prefix('*') {
+        return Some((None, rest));
+    }
+    let (first, rest) = parse_quoted(s)?;
+    let Some(after_dots) = rest.strip_prefix("..") else {
+        return Some((Some(Range::unit(first)), rest));
+    };
+    let (last, remaining) = parse_quoted(after_dots)?;
+    (first <= last).then_some((Some(Range { first, last }), remaining))
+}
+
+/// Parse a single token in single quotes off the front of a string.
+/// The closing quote is the first one followed by whitespace, `..`, or the end of the string.
+#[inline]
+fn parse_quoted<A: FromStr>(s: &str) -> Option<(A, &str)> {
+    let inner = s.strip_prefix('\'')?;
+    let end = inner.char_indices().skip(1).find_map(|(i, c)| {
+        let after = inner.get(i.checked_add(1)?..)?;
+        (c == '\''
+            && (after.is_empty()
+                || after.starts_with(char::is_whitespace)
+                || after.starts_with("..")))
+        .then_some(i)
+    })?;
+    Some((
+        inner.get(..end)?.parse().ok()?,
+        inner.get(end.checked_add(1)?..)?,
+    ))
+}
@@ -0,0 +1,62 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Floating-point numbers with a total order, so they can be used as tokens.
+
+use core::{cmp::Ordering, hash};
+
+/// Implement a total order for a floating-point wrapper via IEEE 754 `totalOrder`.
+macro_rules! total {
+    ($name:ident, $float:ty) => {
+        #[doc = concat!("`", stringify!($float), "` with a total order (IEEE 754 `totalOrder`), so it can be used as a token.")]
+        #[doc = ""]
+        #[doc = "Note that `-0.0 < 0.0` and that `NaN`s sort outside every finite (and infinite) value,"]
+        #[doc = "so a `Range` with non-`NaN` endpoints never contains a `NaN`."]
+        #[allow(clippy::exhaustive_structs)]
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $name(pub $float);
+
+        impl PartialEq for $name {
+            #[inline(always)]
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other).is_eq()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl Ord for $name {
+            #[inline(always)]
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl PartialOrd for $name {
+            #[inline(always)]
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl hash::Hash for $name {
+            #[inline(always)]
+            fn hash<H: hash::Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state);
+            }
+        }
+
+        impl From<$float> for $name {
+            #[inline(always)]
+            fn from(value: $float) -> Self {
+                Self(value)
+            }
+        }
+    };
+}
+
+total!(TotalF32, f32);
+total!(TotalF64, f64);
@@ -0,0 +1,73 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Cheapest accepting runs, with a cost on every transition.
+
+use crate::{Automaton, Execute as _, IllFormed, Indices, Lookup as _};
+use core::{fmt, ops::Add};
+use std::collections::BTreeMap;
+
+impl<
+        A: 'static + fmt::Debug + Clone + Ord,
+        S: 'static + fmt::Debug + Copy + Ord,
+        Ctrl: fmt::Debug + Indices<A, S>,
+    > Automaton<A, S, Ctrl>
+{
+    /// Minimum total cost of any accepting run on this input, or `None` if it's rejected,
+    /// where taking a transition from state `src` on `token` to state `dst` costs `weight(src, token, dst)`
+    /// and an empty run costs `W::default()`.
+    ///
+    /// Keeps only the cheapest cost of reaching each live state after each token (as in the Viterbi algorithm),
+    /// so this takes time linear in the input no matter how many runs there are.
+    /// For a deterministic automaton, this is just the cost of its one run.
+    /// # Errors
+    /// If the parser itself is ill-formed and tries to take a nonsensical action.
+    #[inline]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn min_cost_accept<
+        W: Clone + Default + Ord + Add<Output = W>,
+        F: Fn(usize, &A, usize) -> W,
+    >(
+        &self,
+        input: &[A],
+        weight: F,
+    ) -> Result<Option<W>, IllFormed<A, S, Ctrl>> {
+        let mut ctrl = self.initial.clone();
+        let mut stack = vec![];
+        let mut costs: BTreeMap<usize, W> = ctrl.iter().map(|i| (i, W::default())).collect();
+        for token in input {
+            let top = stack.last().copied();
+            let mut next_costs = BTreeMap::new();
+            for (&src, cost) in &costs {
+                let Some(edge) = get!(self.states, src)
+                    .transitions
+                    .get((top.as_ref(), (token, ())))
+                else {
+                    continue;
+                };
+                for dst in edge.dst().iter() {
+                    let total = cost.clone() + weight(src, token, dst);
+                    if next_costs.get(&dst).is_none_or(|best| total < *best) {
+                        drop(next_costs.insert(dst, total));
+                    }
+                }
+            }
+            ctrl = match self.step(ctrl, &mut stack, Some(token))? {
+                Ok(ok) => ok,
+                Err(_) => return Ok(None),
+            };
+            costs = next_costs;
+        }
+        if !self.would_accept(&ctrl, &stack) {
+            return Ok(None);
+        }
+        Ok(costs
+            .into_iter()
+            .filter(|&(i, _)| get!(self.states, i).accepting)
+            .map(|(_, cost)| cost)
+            .min())
+    }
+}
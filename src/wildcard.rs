@@ -6,8 +6,8 @@
 
 //! Match either (a) literally anything or (b) certain ranges of values.
 
-use crate::{Edge, IllFormed, Indices, Lookup, Merge, Range, Return};
-use core::{fmt, num::NonZeroUsize};
+use crate::{complement_ranges, Discrete, Edge, IllFormed, Indices, Lookup, Merge, Range, Return};
+use core::{fmt, mem::take, num::NonZeroUsize};
 
 /// Match either (a) literally anything or (b) certain ranges of values.
 #[allow(clippy::exhaustive_enums)]
@@ -19,6 +19,31 @@ pub enum Wildcard<Arg: fmt::Debug + Ord, Etc: Lookup> {
     Specific(Vec<(Range<Arg>, Etc)>),
 }
 
+/// What to do when more than one range in a `Wildcard::Specific` matches the same token.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LookupPolicy {
+    /// Overlaps are bugs: lookup panics on any token more than one range matches, in every build.
+    #[default]
+    Exclusive,
+    /// The first (leftmost) matching range wins, so earlier ranges take priority, as in lexer rules.
+    FirstMatch,
+}
+
+impl<Arg: fmt::Debug + Ord, Etc: Lookup> Wildcard<Arg, Etc> {
+    /// Whatever the first range containing this argument maps to, ignoring any later ranges that overlap it.
+    #[inline]
+    pub fn first_match(&self, arg: &Arg) -> Option<&Etc> {
+        match *self {
+            Self::Any(ref etc) => Some(etc),
+            Self::Specific(ref map) => map
+                .iter()
+                .find(|&&(ref k, _)| k.has(arg))
+                .map(|&(_, ref etc)| etc),
+        }
+    }
+}
+
 impl<Arg: fmt::Debug + Ord, Etc: Lookup> fmt::Debug for Wildcard<Arg, Etc> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -42,7 +67,7 @@ impl<Arg: 'static + fmt::Debug + Ord, Etc: Lookup> Lookup for Wildcard<Arg, Etc>
                 .iter()
                 .fold(None, |acc, &(ref k, ref v)| {
                     // TODO: binary search?
-                    if k.contains(arg).is_eq() {
+                    if k.has(arg) {
                         assert!(
                             acc.is_none(),
                             "`Wildcard` with overlapping ranges: e.g. on argument `{arg:?}`",
@@ -56,6 +81,27 @@ impl<Arg: 'static + fmt::Debug + Ord, Etc: Lookup> Lookup for Wildcard<Arg, Etc>
         }
     }
     #[inline]
+    fn get_mut(&mut self, (arg, args): Self::Key<'_>) -> Option<&mut Self::Value> {
+        match *self {
+            Self::Any(ref mut etc) => etc.get_mut(args),
+            Self::Specific(ref mut map) => map
+                .iter_mut()
+                .fold(None, |acc, &mut (ref k, ref mut v)| {
+                    // Same check as `get`, so both always resolve a key to the same edge
+                    if k.has(arg) {
+                        assert!(
+                            acc.is_none(),
+                            "`Wildcard` with overlapping ranges: e.g. on argument `{arg:?}`",
+                        );
+                        Some(v)
+                    } else {
+                        acc
+                    }
+                })?
+                .get_mut(args),
+        }
+    }
+    #[inline]
     fn map_values<F: FnMut(&mut Self::Value)>(&mut self, mut f: F) {
         match *self {
             Self::Any(ref mut etc) => etc.map_values(f),
@@ -71,11 +117,13 @@ impl<Arg: 'static + fmt::Debug + Ord, Etc: Lookup> Lookup for Wildcard<Arg, Etc>
 impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
-        Ctrl: Indices<A, S>,
-    > Merge<A, S, Ctrl> for Wildcard<A, Return<Edge<A, S, Ctrl>>>
+        Ctrl: Indices<A, S> + Merge<A, S, Ctrl, In, Out>,
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Merge<A, S, Ctrl, In, Out> for Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>
 {
     #[inline]
-    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl>> {
+    fn merge(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>> {
         match (self, other) {
             (Self::Any(Return(lhs)), &Self::Any(Return(ref rhs))) => {
                 Ok(Self::Any(Return(lhs.merge(rhs)?)))
@@ -107,13 +155,16 @@ impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
         Ctrl: Indices<A, S>,
-    > Wildcard<A, Return<Edge<A, S, Ctrl>>>
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>
 {
     /// Check for structural errors.
+    /// Specific ranges must be disjoint, but they needn't be sorted, since lookup scans all of them.
     /// # Errors
     /// If this automaton is not well-formed.
     #[inline]
-    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl>> {
+    pub fn check(&self, size: NonZeroUsize) -> Result<(), IllFormed<A, S, Ctrl, In, Out>> {
         match *self {
             Self::Any(Return(ref edge)) => edge.check(size),
             Self::Specific(ref map) => {
@@ -122,17 +173,71 @@ impl<
                     .try_fold((), |(), (i, &(ref k, Return(ref edge)))| {
                         get!(map, ..i)
                             .iter()
-                            .fold(None, |acc, &(ref range, _)| acc.or_else(|| range.union(k)))
-                            .map_or(Ok(()), |union| Err(IllFormed::VecMergeConflict(union)))
+                            .find_map(|&(ref range, _)| range.clamp_to(k))
+                            .map_or(Ok(()), |shared| Err(IllFormed::VecMergeConflict(shared)))
                             .and_then(|()| edge.check(size))
                     })
             }
         }
     }
 
+    /// Same as `merge`, except that overlapping ranges needn't be identical:
+    /// they're split into disjoint pieces, and the piece they share takes both edges merged.
+    /// # Errors
+    /// If overlapping ranges lead to edges that can't merge (`IllFormed::RangeMergeConflict`),
+    /// or anything else `merge` would reject.
+    #[inline]
+    pub fn merge_splitting(self, other: &Self) -> Result<Self, IllFormed<A, S, Ctrl, In, Out>>
+    where
+        A: Discrete,
+        Ctrl: Merge<A, S, Ctrl, In, Out>,
+    {
+        match (self, other) {
+            (Self::Specific(mut lhs), &Self::Specific(ref rhs)) => {
+                for &(ref rk, Return(ref rv)) in rhs {
+                    // Parts of the incoming range that nothing on the left covers
+                    let mut uncovered = vec![rk.clone()];
+                    let mut merged = Vec::with_capacity(lhs.len().saturating_add(2));
+                    for (lk, Return(lv)) in take(&mut lhs) {
+                        let Some(shared) = lk.clamp_to(rk) else {
+                            merged.push((lk, Return(lv)));
+                            continue;
+                        };
+                        uncovered = uncovered
+                            .into_iter()
+                            .flat_map(|piece| {
+                                let (before, _, after) = piece.split_on(&lk);
+                                before.into_iter().chain(after)
+                            })
+                            .collect();
+                        let both = lv.clone().merge(rv).ok().ok_or_else(|| {
+                            IllFormed::RangeMergeConflict(shared.clone(), lv.clone(), rv.clone())
+                        })?;
+                        if both == lv {
+                            merged.push((lk, Return(lv)));
+                            continue;
+                        }
+                        let (before, _, after) = lk.split_on(rk);
+                        merged.extend(before.map(|k| (k, Return(lv.clone()))));
+                        merged.push((shared, Return(both)));
+                        merged.extend(after.map(|k| (k, Return(lv))));
+                    }
+                    merged.extend(uncovered.into_iter().map(|k| (k, Return(rv.clone()))));
+                    lhs = merged;
+                }
+                Ok(Self::Specific(lhs))
+            }
+            (lhs, _) => lhs.merge(other),
+        }
+    }
+
     /// Eliminate absurd relations like transitions to non-existing states.
     #[inline]
-    pub fn deabsurdify(&mut self, size: Option<NonZeroUsize>) {
+    pub fn deabsurdify(&mut self, size: Option<NonZeroUsize>)
+    where
+        A: Discrete,
+        Ctrl: Merge<A, S, Ctrl, In, Out>,
+    {
         match *self {
             Self::Any(Return(ref mut etc)) => {
                 if let Some(s) = size {
@@ -140,17 +245,50 @@ impl<
                 }
             }
             Self::Specific(ref mut v) => {
-                {
-                    let mut rm = vec![];
-                    for (i, &(ref k, _)) in v.iter().enumerate() {
-                        if get!(v, ..i).iter().any(|&(ref range, _)| range.overlap(k)) {
-                            rm.push(i);
+                // Split overlapping ranges into disjoint pieces: where two edges overlap, the piece they share
+                // takes both edges merged, or just the earlier edge if they genuinely conflict
+                #[allow(clippy::type_complexity)]
+                let mut disjoint: Vec<(
+                    Range<A>,
+                    Return<Edge<A, S, Ctrl, In, Out>>,
+                )> = vec![];
+                for (key, Return(edge)) in take(v) {
+                    let mut pieces = vec![key];
+                    // Leftovers of earlier ranges carved around a merged piece, still with their own edge
+                    let mut carved = vec![];
+                    for &mut (ref mut existing, Return(ref mut existing_edge)) in &mut disjoint {
+                        let mut uncovered = vec![];
+                        for piece in pieces {
+                            let (before, shared, after) = piece.split_on(existing);
+                            uncovered.extend(before);
+                            uncovered.extend(after);
+                            let Some(overlap) = shared else {
+                                continue;
+                            };
+                            let Ok(both) = existing_edge.clone().merge(&edge) else {
+                                continue;
+                            };
+                            if both != *existing_edge {
+                                let (left, _, right) = existing.split_on(&overlap);
+                                carved.extend(
+                                    left.into_iter()
+                                        .chain(right)
+                                        .map(|k| (k, Return(existing_edge.clone()))),
+                                );
+                                *existing = overlap;
+                                *existing_edge = both;
+                            }
                         }
+                        pieces = uncovered;
                     }
-                    for i in rm.into_iter().rev() {
-                        drop(v.swap_remove(i));
-                    }
+                    disjoint.extend(carved);
+                    disjoint.extend(
+                        pieces
+                            .into_iter()
+                            .map(|piece| (piece, Return(edge.clone()))),
+                    );
                 }
+                *v = disjoint;
                 if let Some(s) = size {
                     for &mut (_, Return(ref mut edge)) in v {
                         edge.deabsurdify(s);
@@ -160,36 +298,117 @@ impl<
         }
     }
 
-    /// Remove a value by its key.
-    /// # Panics
-    /// If this is a wildcard.
+    /// Remove every token that `shadow` also matches, splitting ranges that it only partly covers.
+    /// Return whether that worked: `false` (leaving this as it was) if this is `Any` but `shadow` isn't,
+    /// since what's left would be the complement of `shadow`'s ranges, with no universe to take it in.
     #[inline]
-    #[allow(clippy::panic)]
-    pub fn remove(&mut self, key: &Range<A>) {
+    #[must_use]
+    pub fn subtract(&mut self, shadow: &Self) -> bool
+    where
+        A: Discrete,
+    {
+        match (&mut *self, shadow) {
+            (_, &Self::Any(..)) => {
+                *self = Self::Specific(vec![]);
+                true
+            }
+            (&mut Self::Any(..), &Self::Specific(ref shadows)) => shadows.is_empty(),
+            (&mut Self::Specific(ref mut v), &Self::Specific(ref shadows)) => {
+                *v = take(v)
+                    .into_iter()
+                    .flat_map(|(key, edge)| {
+                        let mut pieces = vec![key];
+                        for &(ref shadowed, _) in shadows {
+                            pieces = pieces
+                                .into_iter()
+                                .flat_map(|piece| {
+                                    let (before, _, after) = piece.split_on(shadowed);
+                                    before.into_iter().chain(after)
+                                })
+                                .collect();
+                        }
+                        pieces.into_iter().map(move |piece| (piece, edge.clone()))
+                    })
+                    .collect();
+                true
+            }
+        }
+    }
+
+    /// Remove a value by its key. Return whether anything was removed:
+    /// `false` if this is `Any` (which has no keys to remove) or if the key isn't present.
+    #[inline]
+    #[must_use]
+    pub fn remove(&mut self, key: &Range<A>) -> bool {
         match *self {
-            Self::Any(..) => panic!("Trying to remove a key from a wildcard"),
+            Self::Any(..) => false,
             Self::Specific(ref mut v) => {
-                drop(v.swap_remove(unwrap!(v.iter().position(|&(ref k, _)| k == key))));
+                v.iter().position(|&(ref k, _)| k == key).is_some_and(|i| {
+                    drop(v.swap_remove(i));
+                    true
+                })
+            }
+        }
+    }
+
+    /// Keep only edges for which `f` returns `true`. Return whether any edges remain.
+    #[inline]
+    pub fn retain_edges<F: FnMut(&mut Edge<A, S, Ctrl, In, Out>) -> bool>(
+        &mut self,
+        mut f: F,
+    ) -> bool {
+        match *self {
+            Self::Any(Return(ref mut edge)) => f(edge),
+            Self::Specific(ref mut v) => {
+                v.retain_mut(|&mut (_, Return(ref mut edge))| f(edge));
+                !v.is_empty()
             }
         }
     }
 
     /// Iterate over values only, ignoring keys.
     #[inline]
-    pub fn values(&self) -> impl Iterator<Item = &Edge<A, S, Ctrl>> {
+    pub fn values(&self) -> impl Iterator<Item = &Edge<A, S, Ctrl, In, Out>> {
         match *self {
             Self::Any(Return(ref etc)) => vec![etc],
             Self::Specific(ref v) => v.iter().map(|&(_, Return(ref edge))| edge).collect(),
         }
         .into_iter()
     }
+
+    /// If this is `Specific` but its ranges cover all of `universe` and all lead to the same edge,
+    /// rewrite it as `Any`, which skips searching through ranges altogether.
+    #[inline]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn simplify(&mut self, universe: &Range<A>)
+    where
+        A: Discrete,
+    {
+        let Self::Specific(ref mut v) = *self else {
+            return;
+        };
+        let Some(&(_, ref first)) = v.first() else {
+            return;
+        };
+        if v.iter().any(|&(_, ref other)| other != first) {
+            return;
+        }
+        let mut sorted: Vec<_> = v.iter().map(|&(ref range, _)| range.clone()).collect();
+        sorted.sort_unstable();
+        if complement_ranges(&sorted, universe).is_empty() {
+            let (_, edge) = unwrap!(v.pop());
+            *self = Self::Any(edge);
+        }
+    }
 }
 
 impl<
         A: 'static + fmt::Debug + Clone + Ord,
         S: 'static + fmt::Debug + Copy + Ord,
         Ctrl: Indices<A, S>,
-    > Wildcard<A, Return<Edge<A, S, Ctrl>>>
+        In: 'static + fmt::Debug + Clone + Ord,
+        Out: 'static + fmt::Debug + Clone + Ord,
+    > Wildcard<A, Return<Edge<A, S, Ctrl, In, Out>>>
 {
     /// Find any key in common if any exist.
     #[inline]
@@ -212,4 +431,48 @@ impl<
             }
         }
     }
+
+    /// First tokens both would match (`None` if both match anything at all), with the edge each takes on them.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn overlapping_edges<'a>(
+        &'a self,
+        other: &'a Self,
+    ) -> Option<(
+        Option<Range<A>>,
+        &'a Edge<A, S, Ctrl, In, Out>,
+        &'a Edge<A, S, Ctrl, In, Out>,
+    )> {
+        match (self, other) {
+            (&Self::Any(Return(ref lhs)), &Self::Any(Return(ref rhs))) => Some((None, lhs, rhs)),
+            (&Self::Any(Return(ref lhs)), &Self::Specific(ref v)) => v
+                .first()
+                .map(|&(ref k, Return(ref rhs))| (Some(k.clone()), lhs, rhs)),
+            (&Self::Specific(ref v), &Self::Any(Return(ref rhs))) => v
+                .first()
+                .map(|&(ref k, Return(ref lhs))| (Some(k.clone()), lhs, rhs)),
+            (&Self::Specific(ref lv), &Self::Specific(ref rv)) => {
+                lv.iter().find_map(|&(ref lk, Return(ref lhs))| {
+                    rv.iter().find_map(|&(ref rk, Return(ref rhs))| {
+                        lk.clamp_to(rk).map(|shared| (Some(shared), lhs, rhs))
+                    })
+                })
+            }
+        }
+    }
+
+    /// Find any token both would match, not just identical keys, if any exist.
+    /// Unlike `disjoint`, the range returned need not be a key in either.
+    #[inline]
+    pub fn overlap(&self, other: &Self) -> Option<Option<Range<A>>> {
+        if let (&Self::Specific(ref lhs), &Self::Specific(ref rhs)) = (self, other) {
+            lhs.iter().find_map(|&(ref lk, _)| {
+                rhs.iter()
+                    .find_map(|&(ref rk, _)| lk.clamp_to(rk))
+                    .map(Some)
+            })
+        } else {
+            self.disjoint(other)
+        }
+    }
 }
@@ -0,0 +1,103 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Concrete inputs on which two automata disagree.
+
+use crate::{Deterministic, Discrete, Execute as _, Wildcard};
+use core::fmt;
+use std::collections::{BTreeSet, VecDeque};
+
+/// Configuration of one automaton mid-run: its state and stack, or `None` once it has rejected.
+type Config<S> = Option<(usize, Vec<S>)>;
+
+impl<A: fmt::Debug + Clone + Default + Discrete + Ord, S: fmt::Debug + Copy + Ord>
+    Deterministic<A, S>
+{
+    /// Shortest input accepted by exactly one of these two automata, if the bounded search below finds one.
+    /// `None` means only that no difference turned up within that bound, not that the two are equivalent.
+    ///
+    /// Runs a breadth-first search over pairs of configurations (state and stack for each automaton),
+    /// trying one representative token from each region of the alphabet that either automaton distinguishes.
+    /// Stacks are explored up to depth `(self.states.len() + 1) * (other.states.len() + 1)`,
+    /// so `None` means that no witness exists with nesting at most that deep (though one might nest deeper).
+    /// Ill-formed transitions count as rejection.
+    #[inline]
+    #[must_use]
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn distinguishing_input(&self, other: &Self) -> Option<Vec<A>> {
+        let tokens = self
+            .representatives()
+            .union(&other.representatives())
+            .cloned()
+            .collect::<Vec<_>>();
+        let depth = (self.states.len() + 1) * (other.states.len() + 1);
+        let start: (Config<S>, Config<S>) =
+            (Some((self.initial, vec![])), Some((other.initial, vec![])));
+        let mut seen = BTreeSet::new();
+        let _ = seen.insert(start.clone());
+        let mut queue = VecDeque::from([(start, vec![])]);
+        while let Some(((lhs, rhs), input)) = queue.pop_front() {
+            if self.accepts_config(lhs.as_ref()) != other.accepts_config(rhs.as_ref()) {
+                return Some(input);
+            }
+            for token in &tokens {
+                let next = (
+                    self.step_config(lhs.clone(), token),
+                    other.step_config(rhs.clone(), token),
+                );
+                let too_deep = |config: &Config<S>| {
+                    config
+                        .as_ref()
+                        .is_some_and(|&(_, ref stack)| stack.len() > depth)
+                };
+                if (next.0.is_none() && next.1.is_none()) || too_deep(&next.0) || too_deep(&next.1)
+                {
+                    continue;
+                }
+                if seen.insert(next.clone()) {
+                    let mut extended = input.clone();
+                    extended.push(token.clone());
+                    queue.push_back((next, extended));
+                }
+            }
+        }
+        None
+    }
+
+    /// One token from each region of the alphabet this automaton might treat differently.
+    #[inline]
+    fn representatives(&self) -> BTreeSet<A> {
+        let mut tokens = BTreeSet::new();
+        let _ = tokens.insert(A::default());
+        for state in &self.states {
+            for wild in state.transitions.values() {
+                if let Wildcard::Specific(ref v) = *wild {
+                    for &(ref range, _) in v {
+                        tokens.extend(range.first.pred());
+                        tokens.extend(range.last.succ());
+                        let _ = tokens.insert(range.first.clone());
+                        let _ = tokens.insert(range.last.clone());
+                    }
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Whether this configuration accepts if the input ends here.
+    #[inline]
+    fn accepts_config(&self, config: Option<&(usize, Vec<S>)>) -> bool {
+        config.is_some_and(|&(ctrl, ref stack)| self.would_accept(&ctrl, stack))
+    }
+
+    /// Take one step from this configuration.
+    #[inline]
+    fn step_config(&self, config: Config<S>, token: &A) -> Config<S> {
+        let (ctrl, mut stack) = config?;
+        let next = self.step_det(ctrl, &mut stack, Some(token)).ok()?.ok()?;
+        Some((next, stack))
+    }
+}